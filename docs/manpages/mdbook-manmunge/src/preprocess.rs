@@ -179,10 +179,10 @@ fn rewrite_chapter(chapter: &mut Chapter) -> Result<String, Error> {
             }
 
             _ if verbatim.is_some() => {
-                if let Some(verbatim_events) = verbatim.as_mut() {
-                    if let Err(error) = verbatim_events.consume(event) {
-                        log::error!("{}: Could not consume Markdown; {error}", chapter.name);
-                    }
+                if let Some(verbatim_events) = verbatim.as_mut()
+                    && let Err(error) = verbatim_events.consume(event)
+                {
+                    log::error!("{}: Could not consume Markdown; {error}", chapter.name);
                 }
                 vec![None]
             }
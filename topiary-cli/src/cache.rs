@@ -0,0 +1,196 @@
+//! A persistent cache of inputs that are known to be formatted, so they can be skipped on
+//! subsequent runs.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use sha2::{Digest, Sha256};
+use topiary_config::language::GrammarSource;
+use topiary_core::Language;
+
+use crate::io::{InputFile, InputSource};
+
+/// Entries are removed once they have not been used for this long, so the cache doesn't grow
+/// without bound. At worst, this costs formatting an input again.
+const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// How often the cache is pruned of old entries, which is recorded by the modification time of a
+/// file of this name in the cache
+const PRUNE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+const PRUNED: &str = "pruned";
+
+/// The cache is a directory of empty marker files, each named after the hash of some formatted
+/// content, along with everything else that determines how it is formatted
+#[derive(Clone, Debug)]
+pub struct FormatCache {
+    dir: PathBuf,
+    tolerate_parsing_errors: bool,
+}
+
+impl FormatCache {
+    /// Open the cache in Topiary's cache directory, or the `TOPIARY_FORMAT_CACHE_DIR` directory,
+    /// or `None` if that cannot be created
+    pub fn new(tolerate_parsing_errors: bool) -> Option<Self> {
+        let dir = env::var_os("TOPIARY_FORMAT_CACHE_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| topiary_config::project_dirs().cache_dir().join("formatted"));
+
+        if let Err(e) = fs::create_dir_all(&dir) {
+            log::warn!(
+                "Could not create formatting cache at {}, so it will not be used: {e}",
+                dir.display()
+            );
+
+            return None;
+        }
+
+        prune(&dir);
+
+        Some(Self {
+            dir,
+            tolerate_parsing_errors,
+        })
+    }
+
+    /// The cache entry for the given input's content, which is keyed by the hash of that content,
+    /// the query, the grammar's revision and Topiary's version. Only inputs from disk or the Git
+    /// index are cached; standard input is not.
+    pub fn entry(&self, input: &InputFile, language: &Language, content: &[u8]) -> Option<Entry> {
+        if matches!(input.source(), InputSource::Stdin(_)) {
+            return None;
+        }
+
+        let mut hasher = Sha256::new();
+
+        // Each field is length-prefixed, so their boundaries are unambiguous
+        let mut field = |bytes: &[u8]| {
+            hasher.update((bytes.len() as u64).to_le_bytes());
+            hasher.update(bytes);
+        };
+
+        field(env!("CARGO_PKG_VERSION").as_bytes());
+        field(language.name.as_bytes());
+        field(grammar_revision(input.language()).as_bytes());
+        field(language.query.query_content.as_bytes());
+        field(language.indent.as_deref().unwrap_or_default().as_bytes());
+        field(&[self.tolerate_parsing_errors.into()]);
+        field(content);
+
+        let hash = format!("{:x}", hasher.finalize());
+        let (prefix, rest) = hash.split_at(2);
+
+        Some(Entry(self.dir.join(prefix).join(rest)))
+    }
+}
+
+/// Remove the entries that are older than `MAX_AGE`, unless the cache has been pruned within the
+/// last `PRUNE_INTERVAL`. Failures are not fatal, as they only mean the cache is larger.
+fn prune(dir: &Path) {
+    let now = SystemTime::now();
+    let age = |path: &Path| {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+    };
+
+    let stamp = dir.join(PRUNED);
+    if age(&stamp).is_some_and(|age| age < PRUNE_INTERVAL) {
+        return;
+    }
+
+    if let Err(e) = fs::write(&stamp, []) {
+        log::debug!("Could not prune formatting cache {}: {e}", dir.display());
+        return;
+    }
+
+    // Entries are grouped into directories by the first two characters of their hash
+    let entries = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|prefix| prefix.file_type().is_ok_and(|filetype| filetype.is_dir()))
+        .flat_map(|prefix| fs::read_dir(prefix.path()).into_iter().flatten().flatten());
+
+    let mut pruned = 0;
+    for entry in entries {
+        let path = entry.path();
+        if age(&path).is_some_and(|age| age > MAX_AGE) && fs::remove_file(&path).is_ok() {
+            pruned += 1;
+        }
+    }
+
+    log::debug!(
+        "Pruned {pruned} old entries from formatting cache {}",
+        dir.display()
+    );
+}
+
+/// The revision of a language's grammar. Grammars from Git are identified by their revision,
+/// while local grammars are identified by their path and modification time.
+fn grammar_revision(language: &topiary_config::language::Language) -> String {
+    match &language.config.grammar.source {
+        GrammarSource::Git(git) => format!(
+            "{}#{}:{}",
+            git.git,
+            git.rev,
+            git.subdir.as_deref().unwrap_or_default()
+        ),
+
+        GrammarSource::Path(path) => {
+            let modified = fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            format!("{}@{modified:?}", path.display())
+        }
+    }
+}
+
+/// An entry in the formatting cache
+#[derive(Debug)]
+pub struct Entry(PathBuf);
+
+impl Entry {
+    /// Whether the content of this entry is known to be formatted. A hit refreshes the entry's
+    /// modification time, so entries that are still used are not pruned.
+    pub fn is_formatted(&self) -> bool {
+        let touched = fs::File::options()
+            .write(true)
+            .open(&self.0)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+
+        match touched {
+            Ok(()) => true,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => false,
+
+            // The entry exists but cannot be refreshed, which at worst means it is pruned early
+            Err(e) => {
+                log::debug!(
+                    "Could not refresh formatting cache entry {}: {e}",
+                    self.0.display()
+                );
+                self.0.exists()
+            }
+        }
+    }
+
+    /// Record that the content of this entry is formatted. Failures are not fatal, as they only
+    /// mean the content will be formatted again next time.
+    pub fn mark_formatted(&self) {
+        let marked = self
+            .0
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&self.0, []));
+
+        if let Err(e) = marked {
+            log::debug!(
+                "Could not write formatting cache entry {}: {e}",
+                self.0.display()
+            );
+        }
+    }
+}
@@ -2,13 +2,19 @@
 
 use clap::{ArgAction, ArgGroup, Args, CommandFactory, Parser, Subcommand};
 use clap_complete::{generate, shells::Shell};
-use std::{io::stdout, path::PathBuf};
+use std::{
+    io::{self, Read, stdout},
+    path::PathBuf,
+    sync::Arc,
+};
 
 use log::LevelFilter;
+use topiary_config::Configuration;
+use topiary_core::{InputRange, Position};
 
 use crate::{
     error::{CLIResult, TopiaryError},
-    fs, visualisation,
+    explain, fs, git, query, report, visualisation,
 };
 
 #[derive(Debug, Parser)]
@@ -56,64 +62,98 @@ pub struct GlobalArgs {
     pub verbose: u8,
 }
 
-// NOTE This abstraction is largely to workaround clap-rs/clap#4707
-#[derive(Args, Debug)]
-pub struct FromStdin {
-    /// Topiary language identifier (when formatting stdin)
+// Language and query selection, which overrides detection for input files and is needed to read
+// standard input, unless --stdin-filepath is given to detect it from instead
+#[derive(Args, Clone, Debug)]
+pub struct LanguageOverride {
+    /// Topiary language identifier (when formatting stdin, or to override detection)
     #[arg(short, long)]
-    pub language: String,
+    pub language: Option<String>,
 
-    /// Topiary query file override (when formatting stdin)
+    /// Topiary query file override (when overriding the language)
     #[arg(short, long, requires = "language")]
     pub query: Option<PathBuf>,
+
+    /// Detect the language of stdin as if it were read from this path
+    #[arg(long, value_name = "PATH")]
+    pub stdin_filepath: Option<PathBuf>,
+
+    // The contents of standard input, when its language is to be detected from --stdin-filepath,
+    // which can fall back to its first line; set by `buffer_stdin`
+    #[arg(skip)]
+    pub stdin_contents: Option<Arc<[u8]>>,
 }
 
 // Subtype for exactly one input:
-// * FILE       => Read input from disk, visualisation output to stdout
-// * --language => Read input from stdin, visualisation output to stdout
+// * FILE                          => Read input from disk, visualisation output to stdout
+// * --language, --stdin-filepath  => Read input from stdin, visualisation output to stdout
 #[derive(Args, Debug)]
 #[command(
-    // Require exactly one of --language, or FILES...
+    // Require FILE, or either of --language and --stdin-filepath to read from stdin; --language
+    // can also accompany FILE, to override its detection
     group = ArgGroup::new("source")
-        .multiple(false)
+        .multiple(true)
         .required(true)
-        .args(&["language", "file"])
+        .args(&["language", "stdin_filepath", "file"])
 )]
 pub struct ExactlyOneInput {
     #[command(flatten)]
-    pub stdin: Option<FromStdin>,
+    pub overrides: LanguageOverride,
 
     /// Input file (omit to read from stdin)
     ///
-    /// Language detection and query selection is automatic, mapped from file extensions defined in
-    /// the Topiary configuration.
+    /// Language detection and query selection is automatic, mapped from file names, extensions and
+    /// shebangs defined in the Topiary configuration.
+    #[arg(conflicts_with = "stdin_filepath")]
     pub file: Option<PathBuf>,
 }
 
 // Subtype for at least one input
-// * FILES...   => Read input(s) from disk, format in place
-// * --language => Read input from stdin, output to stdout
-#[derive(Args, Debug)]
+// * FILES...                      => Read input(s) from disk, format in place
+// * --changed-since, --staged     => Select input(s) from Git, optionally restricted by FILES...
+// * --language, --stdin-filepath  => Read input from stdin, output to stdout
+#[derive(Args, Clone, Debug)]
 #[command(
-    // Require exactly one of --language, --query, or FILES...
+    // Require any of FILES... and the Git selections, or either of --language and
+    // --stdin-filepath to read from stdin; --language can also accompany FILES..., to override
+    // their detection
     group = ArgGroup::new("source")
-        .multiple(false)
+        .multiple(true)
         .required(true)
-        .args(&["language", "files"])
+        .args(&["language", "stdin_filepath", "files", "changed_since", "staged"])
 )]
 pub struct AtLeastOneInput {
     #[command(flatten)]
-    pub stdin: Option<FromStdin>,
+    pub overrides: LanguageOverride,
 
     /// Input files and directories (omit to read from stdin)
     ///
-    /// Language detection and query selection is automatic, mapped from file extensions defined in
-    /// the Topiary configuration.
+    /// Language detection and query selection is automatic, mapped from file names, extensions and
+    /// shebangs defined in the Topiary configuration. When selecting inputs from Git, these
+    /// restrict the selection.
+    #[arg(conflicts_with = "stdin_filepath")]
     pub files: Vec<PathBuf>,
 
     /// Follow symlinks (when formatting files)
     #[arg(short = 'L', long)]
     pub follow_symlinks: bool,
+
+    /// Only select files that have changed since the given Git revision
+    #[arg(
+        long,
+        value_name = "REV",
+        conflicts_with_all = &["language", "query", "stdin_filepath", "staged"]
+    )]
+    pub changed_since: Option<String>,
+
+    /// Only select files with staged changes, using their contents from the Git index
+    #[arg(long, conflicts_with_all = &["language", "query", "stdin_filepath"])]
+    pub staged: bool,
+
+    // Whether to read standard input, as no other inputs were given; set by `expand_inputs`, as
+    // expanding FILES... can leave none behind
+    #[arg(skip)]
+    pub stdin: bool,
 }
 
 // NOTE When changing the subcommands, please update verify-documented-usage.sh respectively.
@@ -130,6 +170,38 @@ pub enum Commands {
         #[arg(short, long)]
         skip_idempotence: bool,
 
+        /// List inputs that would be changed by formatting, without writing them
+        #[arg(long)]
+        check: bool,
+
+        /// Print a unified diff of the changes formatting would make, without writing them
+        #[arg(long)]
+        diff: bool,
+
+        /// Lines of context to show around each change (with `--diff`)
+        #[arg(long, value_name = "LINES", default_value_t = 3, requires = "diff")]
+        diff_context: usize,
+
+        /// Only format the syntax node enclosing this 0-based, end-exclusive byte range
+        #[arg(long, value_name = "START:END", value_parser = parse_byte_range, conflicts_with = "lines")]
+        range: Option<InputRange>,
+
+        /// Only format the syntax node enclosing this 1-based, inclusive line range
+        #[arg(long, value_name = "FIRST-LAST", value_parser = parse_line_range)]
+        lines: Option<InputRange>,
+
+        /// Do not skip inputs that previous runs found to be formatted
+        #[arg(long)]
+        no_cache: bool,
+
+        /// Keep running, formatting inputs again whenever they or their query files change
+        #[arg(long, conflicts_with_all = ["check", "diff", "report", "staged"])]
+        watch: bool,
+
+        /// Print a machine-readable report of each input's result to standard output
+        #[arg(long, value_name = "FORMAT", conflicts_with = "diff")]
+        report: Option<report::Format>,
+
         #[command(flatten)]
         inputs: AtLeastOneInput,
     },
@@ -138,13 +210,38 @@ pub enum Commands {
     ///
     /// Visualise generates a graph representation of the parse tree that can be rendered by
     /// external visualisation tools, such as Graphviz. By default, the output is in the DOT
-    /// format.
+    /// format. The atoms formats instead list the atoms that the query produces from the parse
+    /// tree, before and after post-processing.
     #[command(aliases = &["vis", "visualize", "view"], display_order = 2)]
     Visualise {
         /// Visualisation format
         #[arg(short, long, default_value = "dot")]
         format: visualisation::Format,
 
+        /// Include the source text of leaf nodes (dot and json only)
+        #[arg(long)]
+        leaf_text: bool,
+
+        /// Include the byte range of each node (dot and json only)
+        #[arg(long)]
+        byte_ranges: bool,
+
+        /// Only visualise the smallest node at this 1-based location
+        #[arg(long, value_name = "LINE:COL", value_parser = parse_position, conflicts_with_all = ["range", "kind"])]
+        at: Option<Position>,
+
+        /// Only visualise the smallest node enclosing this 0-based, end-exclusive byte range
+        #[arg(long, value_name = "START:END", value_parser = parse_byte_range, conflicts_with = "kind")]
+        range: Option<InputRange>,
+
+        /// Only visualise the nodes of this kind (other than those within another)
+        #[arg(long)]
+        kind: Option<String>,
+
+        /// Only visualise this many levels below the visualised node(s), eliding the rest
+        #[arg(long, value_name = "N")]
+        depth: Option<usize>,
+
         #[command(flatten)]
         input: ExactlyOneInput,
     },
@@ -168,10 +265,37 @@ pub enum Commands {
     },
 
     /// Checks how much of the tree-sitter query is used
+    ///
+    /// With several inputs, each language's query is checked across all of the inputs of that
+    /// language: a pattern is covered if any of them matches it.
     #[command(display_order = 5)]
     Coverage {
+        /// Print a machine-readable report of the coverage to standard output
+        #[arg(long, value_name = "FORMAT")]
+        report: Option<report::Format>,
+
+        /// Also list the patterns that are covered, with the inputs that match each of them
+        #[arg(long)]
+        covered_by: bool,
+
+        /// Instead of pattern coverage, list the named node kinds in the inputs that the query
+        /// does not handle
+        ///
+        /// A node is handled if it is captured, contains a captured node, or is within a leaf. The
+        /// leaves of unhandled nodes are output as they are, without any whitespace between them.
+        #[arg(long, conflicts_with_all = ["covered_by", "report"])]
+        nodes: bool,
+
+        /// Instead of checking inputs, list the named node kinds and field names of each input's
+        /// grammar that the query never refers to
+        ///
+        /// Inputs are only used to detect their language, so aren't read; with --language, no
+        /// input is needed.
+        #[arg(long, conflicts_with_all = ["covered_by", "report", "nodes"])]
+        grammar: bool,
+
         #[command(flatten)]
-        input: ExactlyOneInput,
+        inputs: AtLeastOneInput,
     },
 
     /// Generate shell completion script
@@ -184,9 +308,200 @@ pub enum Commands {
     /// Check if an input parses to the respective Tree-sitter grammar
     #[command(alias = "check", display_order = 6)]
     CheckGrammar {
+        /// Print a machine-readable report of each input's result to standard output
+        #[arg(long, value_name = "FORMAT")]
+        report: Option<report::Format>,
+
         #[command(flatten)]
         inputs: AtLeastOneInput,
     },
+
+    /// Run a language server, over standard input and output
+    ///
+    /// The server supports whole document and range formatting, and reports parsing errors as
+    /// diagnostics. Languages are detected from document paths, falling back to the language
+    /// identifier given by the editor.
+    #[command(display_order = 7)]
+    Lsp,
+
+    /// Format an input to standard output, again whenever it or its query file changes
+    ///
+    /// The playground gives quick feedback while developing queries: it shows the formatted input,
+    /// and whether formatting is idempotent, updating these as the input and query are edited.
+    #[command(display_order = 8)]
+    Playground {
+        /// Consume as much as possible in the presence of parsing errors
+        #[arg(short, long)]
+        tolerate_parsing_errors: bool,
+
+        /// Topiary language identifier (to override detection)
+        #[arg(short, long)]
+        language: Option<String>,
+
+        /// Topiary query file override (when overriding the language)
+        #[arg(short, long, requires = "language")]
+        query: Option<PathBuf>,
+
+        /// Input file
+        ///
+        /// Language detection and query selection is automatic, mapped from file names, extensions and
+        /// shebangs defined in the Topiary configuration.
+        file: PathBuf,
+    },
+
+    /// Run a Tree-sitter query against an input, and print its matches
+    ///
+    /// Each match is printed with the index of its pattern and its captures, giving the name, node
+    /// kind, 1-based position range and text of each captured node. Captures are not interpreted
+    /// as formatting directives.
+    #[command(
+        display_order = 10,
+        group = ArgGroup::new("source")
+            .multiple(true)
+            .required(true)
+            .args(&["language", "stdin_filepath", "file"])
+    )]
+    Query {
+        /// Query to run: either a file, or the patterns themselves
+        #[arg(short, long, value_name = "PATTERN_OR_FILE")]
+        query: String,
+
+        /// Output format
+        #[arg(short, long, default_value = "text")]
+        format: query::Format,
+
+        /// Consume as much as possible in the presence of parsing errors
+        #[arg(short, long)]
+        tolerate_parsing_errors: bool,
+
+        /// Topiary language identifier (when querying stdin, or to override detection)
+        #[arg(short, long)]
+        language: Option<String>,
+
+        /// Detect the language of stdin as if it were read from this path
+        #[arg(long, value_name = "PATH")]
+        stdin_filepath: Option<PathBuf>,
+
+        /// Input file (omit to read from stdin)
+        ///
+        /// Language detection is automatic, mapped from file names, extensions and shebangs defined
+        /// in the Topiary configuration.
+        #[arg(conflicts_with = "stdin_filepath")]
+        file: Option<PathBuf>,
+
+        // As with `LanguageOverride::stdin_contents`
+        #[arg(skip)]
+        stdin_contents: Option<Arc<[u8]>>,
+    },
+
+    /// Run a daemon that formats standard input for other invocations, over a Unix domain socket
+    ///
+    /// The daemon keeps the configuration, grammars and queries loaded between requests, and
+    /// reloads the configuration when its files change. While it is running, formatting standard
+    /// input is forwarded to it, when the configuration would otherwise be the same.
+    #[cfg(unix)]
+    #[command(display_order = 9)]
+    Daemon {
+        /// Socket to listen on
+        #[arg(
+            long,
+            value_name = "PATH",
+            env = "TOPIARY_DAEMON_SOCKET",
+            hide_env_values = true
+        )]
+        socket: Option<PathBuf>,
+    },
+
+    /// Explain the formatting of the whitespace around a location in the input
+    ///
+    /// Every atom before and after the leaf at the location is traced back to the query pattern
+    /// and capture that created it. Atoms removed while post-processing, for example by a
+    /// dominating line break or an antispace, are given with the reason.
+    #[command(display_order = 11)]
+    Explain {
+        /// Location to explain, as a 1-based line and column (in bytes)
+        #[arg(long, value_name = "LINE:COL", value_parser = parse_position)]
+        at: Position,
+
+        /// Output format
+        #[arg(short, long, default_value = "text")]
+        format: explain::Format,
+
+        /// Consume as much as possible in the presence of parsing errors
+        #[arg(short, long)]
+        tolerate_parsing_errors: bool,
+
+        #[command(flatten)]
+        input: ExactlyOneInput,
+    },
+
+    /// Test queries against golden files: inputs and their expected formatted output
+    ///
+    /// Every file in DIR/input is formatted, as detected from its path, and compared with the file
+    /// at the same relative path in DIR/expected. Formatting must also be idempotent.
+    #[command(display_order = 12)]
+    Test {
+        /// Directory with `input` and `expected` subdirectories
+        dir: PathBuf,
+
+        /// Write the formatted output of failing inputs as their expected output
+        #[arg(long)]
+        bless: bool,
+    },
+
+    /// Scaffold a new language, from its Tree-sitter grammar
+    ///
+    /// The grammar is fetched and compiled, then a starter query file, generated from the
+    /// grammar's node types, is written to the queries of the configuration directory. The
+    /// language is added to the configuration there, if it has none yet; otherwise, its entry is
+    /// printed, to add by hand.
+    #[command(display_order = 13)]
+    InitLanguage {
+        /// Name of the language, as used in the configuration
+        name: String,
+
+        /// URL of the grammar's Git repository
+        #[arg(long, value_name = "URL")]
+        git: String,
+
+        /// Git revision of the grammar
+        #[arg(long)]
+        rev: String,
+
+        /// Subdirectory of the repository that contains the grammar
+        #[arg(long, value_name = "DIR")]
+        subdir: Option<String>,
+
+        /// File extension of the language (defaults to the file types of the grammar)
+        #[arg(short, long = "extension", value_name = "EXT")]
+        extensions: Vec<String>,
+
+        /// Configuration directory to write to
+        #[arg(short, long, value_name = "DIR", default_value = ".topiary")]
+        output: PathBuf,
+    },
+
+    /// Check the configured languages end to end
+    ///
+    /// Each language's grammar is fetched and compiled, if necessary, and loaded, and its ABI
+    /// version checked. Its query file is found and compiled against the grammar, and its capture
+    /// names and predicates checked. The query file and the configuration source that define the
+    /// language are given too.
+    #[command(display_order = 14)]
+    Doctor {
+        /// Only check this language
+        language: Option<String>,
+    },
+
+    /// Manage the cache of compiled grammars
+    ///
+    /// Each revision of a grammar is compiled to its own library in the cache, so libraries of
+    /// revisions that the configuration no longer uses are left behind.
+    #[command(display_order = 15)]
+    Grammars {
+        #[command(subcommand)]
+        command: GrammarsCommand,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -195,6 +510,25 @@ pub enum ConfigCommand {
     ShowSources,
 }
 
+#[derive(Subcommand, Debug)]
+pub enum GrammarsCommand {
+    /// List the cached grammars, with whether the configuration uses each of them
+    List,
+
+    /// Remove the cached grammars that the configuration does not use
+    ///
+    /// Only the configuration in effect is taken into account, so grammars that only another
+    /// project's configuration uses will be removed; use --dry-run to review them first.
+    Clean {
+        /// List the grammars that would be removed, without removing them
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+
+    /// Check that each cached grammar loads, through its expected symbol
+    Verify,
+}
+
 /// Parse CLI arguments and normalise them for the caller
 #[allow(clippy::result_large_err)]
 pub fn get_args() -> CLIResult<Cli> {
@@ -217,57 +551,21 @@ pub fn get_args() -> CLIResult<Cli> {
         })
         .init();
 
-    // NOTE We do not check that input files are actual files (with Path::is_file), because that
-    // would break in the case of, for example, named pipes; thus also adding a platform dimension
-    // to the check, which is simply not worth the complexity. We _could_ check by opening each
-    // file, but that's going to be done sooner-or-later by Topiary, so there's no need.
-
     match &mut args.command {
-        Commands::Format {
-            inputs:
-                AtLeastOneInput {
-                    files,
-                    follow_symlinks,
-                    ..
-                },
-            ..
-        }
-        | Commands::CheckGrammar {
-            inputs:
-                AtLeastOneInput {
-                    files,
-                    follow_symlinks,
-                    ..
-                },
-            ..
-        } => {
-            // If we're given a list of FILES... then we assume them to all be on disk, even if "-"
-            // is passed as an argument (i.e., interpret this as a valid filename, rather than as
-            // stdin). We recursively expand directories until we're left with a list of
-            // (potential) files, as input sources. This is finally deduplicated to avoid
-            // formatting the same file multiple times (e.g., in the case that a symlink points to
-            // a file within the set, or if the same file is specified twice at the command line).
-            fs::traverse(files, *follow_symlinks)?;
-            files.sort_unstable();
-            files.dedup();
-        }
-
+        // Make sure our FILE is not a directory
         Commands::Visualise {
             input: ExactlyOneInput {
                 file: Some(file), ..
             },
             ..
-        } => {
-            // Make sure our FILE is not a directory
-            if file.is_dir() {
-                return Err(TopiaryError::Bin(
-                    format!(
-                        "Cannot visualise directory \"{}\"; please provide a single file from disk or stdin.",
-                        file.display()
-                    ),
-                    None,
-                ));
-            }
+        } if file.is_dir() => {
+            return Err(TopiaryError::Bin(
+                format!(
+                    "Cannot visualise directory \"{}\"; please provide a single file from disk or stdin.",
+                    file.display()
+                ),
+                None,
+            ));
         }
 
         // Attempt to detect shell from environment, when omitted
@@ -288,6 +586,234 @@ pub fn get_args() -> CLIResult<Cli> {
     Ok(args)
 }
 
+/// Normalise the input files of the subcommands that take them, selecting them from Git and
+/// expanding directories. This depends on the configuration, so must happen after its loading.
+#[allow(clippy::result_large_err)]
+pub fn expand_inputs(command: &mut Commands, config: &Configuration) -> CLIResult<()> {
+    // NOTE We do not check that input files are actual files (with Path::is_file), because that
+    // would break in the case of, for example, named pipes; thus also adding a platform dimension
+    // to the check, which is simply not worth the complexity. We _could_ check by opening each
+    // file, but that's going to be done sooner-or-later by Topiary, so there's no need.
+
+    if let Commands::Format {
+        inputs:
+            AtLeastOneInput {
+                overrides,
+                files,
+                follow_symlinks,
+                changed_since,
+                staged,
+                stdin,
+            },
+        ..
+    }
+    | Commands::CheckGrammar {
+        inputs:
+            AtLeastOneInput {
+                overrides,
+                files,
+                follow_symlinks,
+                changed_since,
+                staged,
+                stdin,
+            },
+        ..
+    }
+    | Commands::Coverage {
+        inputs:
+            AtLeastOneInput {
+                overrides,
+                files,
+                follow_symlinks,
+                changed_since,
+                staged,
+                stdin,
+            },
+        ..
+    } = command
+    {
+        *stdin = files.is_empty() && changed_since.is_none() && !*staged;
+
+        // When selecting from Git, any FILES... only restrict the selection, which is subject to
+        // the same ignore files and globs as a walk of the working tree. Staged files are read
+        // from the index, so they don't need to be traversed on disk; changed files do.
+        if *staged {
+            *files = git::staged(files)?;
+            fs::retain_unignored(files, &git::root()?, config)?;
+        } else {
+            if let Some(rev) = changed_since {
+                *files = git::changed_since(rev, files)?;
+                fs::retain_unignored(files, &git::root()?, config)?;
+            }
+
+            // If we're given a list of FILES... then we assume them to all be on disk, even if
+            // "-" is passed as an argument (i.e., interpret this as a valid filename, rather than
+            // as stdin). We recursively expand directories until we're left with a list of
+            // (potential) files, as input sources. This is finally deduplicated to avoid
+            // formatting the same file multiple times (e.g., in the case that a symlink points to
+            // a file within the set, or if the same file is specified twice at the command line).
+            // When the language is overridden, every file found in a directory is taken to be in
+            // that language; otherwise, those whose language cannot be detected are skipped.
+            fs::traverse(
+                files,
+                *follow_symlinks,
+                config,
+                overrides.language.is_none(),
+            )?;
+            files.sort_unstable();
+            files.dedup();
+        }
+    }
+
+    // Ranges only make sense within a single input
+    if let Commands::Format {
+        range,
+        lines,
+        inputs,
+        ..
+    } = command
+        && (range.is_some() || lines.is_some())
+        && inputs.files.len() > 1
+    {
+        return Err(TopiaryError::Bin(
+            "A range can only be formatted within a single input".into(),
+            None,
+        ));
+    }
+
+    // Standard input cannot be watched for changes
+    if let Commands::Format {
+        watch: true,
+        inputs: AtLeastOneInput { stdin: true, .. },
+        ..
+    } = command
+    {
+        return Err(TopiaryError::Bin(
+            "Cannot watch standard input for changes; please provide input files.".into(),
+            None,
+        ));
+    }
+
+    // Formatting standard input writes to standard output, where it would be mixed with a report
+    if let Commands::Format {
+        check: false,
+        report: Some(_),
+        inputs: AtLeastOneInput { stdin: true, .. },
+        ..
+    } = command
+    {
+        return Err(TopiaryError::Bin(
+            "Cannot report on formatting standard input, unless checking it with --check".into(),
+            None,
+        ));
+    }
+
+    Ok(())
+}
+
+/// Read standard input up front, for the subcommands that detect its language from
+/// --stdin-filepath, as detection can fall back to its first line. Otherwise, it's left to be
+/// read as it's processed. This must happen after forwarding to a daemon, which reads it itself.
+#[allow(clippy::result_large_err)]
+pub fn buffer_stdin(command: &mut Commands) -> CLIResult<()> {
+    let (language, stdin_filepath, stdin_contents) = match command {
+        Commands::Format {
+            inputs: AtLeastOneInput { overrides, .. },
+            ..
+        }
+        | Commands::CheckGrammar {
+            inputs: AtLeastOneInput { overrides, .. },
+            ..
+        }
+        | Commands::Coverage {
+            inputs: AtLeastOneInput { overrides, .. },
+            ..
+        }
+        | Commands::Visualise {
+            input: ExactlyOneInput { overrides, .. },
+            ..
+        }
+        | Commands::Explain {
+            input: ExactlyOneInput { overrides, .. },
+            ..
+        } => (
+            &overrides.language,
+            &overrides.stdin_filepath,
+            &mut overrides.stdin_contents,
+        ),
+
+        Commands::Query {
+            language,
+            stdin_filepath,
+            stdin_contents,
+            ..
+        } => (&*language, &*stdin_filepath, stdin_contents),
+
+        _ => return Ok(()),
+    };
+
+    // --stdin-filepath conflicts with input files, so it always means standard input is read
+    if language.is_none() && stdin_filepath.is_some() {
+        let mut contents = Vec::new();
+        io::stdin().lock().read_to_end(&mut contents)?;
+        *stdin_contents = Some(contents.into());
+    }
+
+    Ok(())
+}
+
+/// Parse a byte range, given as START:END
+fn parse_byte_range(arg: &str) -> Result<InputRange, String> {
+    let (start, end) = arg
+        .split_once(':')
+        .ok_or("Byte range must be given as START:END")?;
+
+    let start: usize = start.parse().map_err(|e| format!("Invalid start: {e}"))?;
+    let end: usize = end.parse().map_err(|e| format!("Invalid end: {e}"))?;
+
+    if start > end {
+        return Err("Start of byte range must not be after its end".into());
+    }
+
+    Ok(InputRange::Bytes(start..end))
+}
+
+/// Parse a line range, given as FIRST-LAST
+fn parse_line_range(arg: &str) -> Result<InputRange, String> {
+    let (first, last) = arg
+        .split_once('-')
+        .ok_or("Line range must be given as FIRST-LAST")?;
+
+    let first: usize = first
+        .parse()
+        .map_err(|e| format!("Invalid first line: {e}"))?;
+    let last: usize = last
+        .parse()
+        .map_err(|e| format!("Invalid last line: {e}"))?;
+
+    if first == 0 || first > last {
+        return Err("Line range must start from 1 and not end before it starts".into());
+    }
+
+    Ok(InputRange::Lines(first..=last))
+}
+
+/// Parse a location, given as LINE:COL, into a 1-based position
+fn parse_position(arg: &str) -> Result<Position, String> {
+    let (row, column) = arg
+        .split_once(':')
+        .ok_or("Location must be given as LINE:COL")?;
+
+    let row: u32 = row.parse().map_err(|e| format!("Invalid line: {e}"))?;
+    let column: u32 = column.parse().map_err(|e| format!("Invalid column: {e}"))?;
+
+    if row == 0 || column == 0 {
+        return Err("Lines and columns start from 1".into());
+    }
+
+    Ok(Position { row, column })
+}
+
 /// Generate shell completion script, for the given shell, and output to stdout
 pub fn completion(shell: Shell) {
     generate(shell, &mut Cli::command(), "topiary", &mut stdout());
@@ -0,0 +1,187 @@
+//! Query coverage across many inputs, as checked by `topiary coverage`. Inputs are grouped by their
+//! language definition, so a pattern counts as covered if any input of its language matches it,
+//! and a node kind counts as handled if the query handles it in any input.
+//!
+//! Alternatively, a query's coverage of its grammar is checked without any inputs.
+
+use std::{
+    io::{self, Write},
+    sync::Arc,
+};
+
+use topiary_core::{
+    CoverageData, FormatterResult, GrammarCoverage, Language, UnhandledKind, check_node_coverage,
+    check_query_coverage_inputs,
+};
+use topiary_tree_sitter_facade::Tree;
+
+use crate::io::{InputLocation, QuerySource};
+
+/// The inputs that share a language definition, with their parse trees
+pub struct Group {
+    pub language: Arc<Language>,
+    pub query: QuerySource,
+    pub inputs: Vec<(InputLocation, Tree, String)>,
+}
+
+impl Group {
+    /// Add a parsed input to the group of its language definition, starting one if necessary
+    pub fn add(
+        groups: &mut Vec<Self>,
+        language: Arc<Language>,
+        query: &QuerySource,
+        input: (InputLocation, Tree, String),
+    ) {
+        match groups
+            .iter_mut()
+            .find(|group| Arc::ptr_eq(&group.language, &language))
+        {
+            // Inputs are resolved in no particular order, so are kept sorted by path
+            Some(group) => {
+                let idx = group
+                    .inputs
+                    .partition_point(|(location, _, _)| location.path() < input.0.path());
+                group.inputs.insert(idx, input);
+            }
+            None => groups.push(Self {
+                language,
+                query: query.clone(),
+                inputs: vec![input],
+            }),
+        }
+    }
+
+    /// The locations of the group's inputs
+    pub fn locations(&self) -> impl Iterator<Item = &InputLocation> {
+        self.inputs.iter().map(|(location, _, _)| location)
+    }
+
+    fn parsed(&self) -> Vec<(&Tree, &str)> {
+        self.inputs
+            .iter()
+            .map(|(_, tree, content)| (tree, content.as_str()))
+            .collect()
+    }
+
+    /// Check the query's coverage across all of the group's inputs
+    pub fn check(&self) -> FormatterResult<CoverageData> {
+        check_query_coverage_inputs(&self.parsed(), &self.language.query, &self.language.grammar)
+    }
+
+    /// Find the node kinds in the group's inputs that the query does not handle
+    pub fn check_nodes(&self) -> Vec<UnhandledKind> {
+        check_node_coverage(&self.parsed(), &self.language.query)
+    }
+}
+
+/// The 1-based line and column of the given byte offset into some content
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let prefix = content.get(..offset).unwrap_or(content);
+    let line_start = prefix.rfind('\n').map_or(0, |idx| idx + 1);
+
+    (
+        prefix.matches('\n').count() + 1,
+        prefix[line_start..].chars().count() + 1,
+    )
+}
+
+/// Write the location of each pattern that the group's inputs match, with the inputs that match it
+pub fn write_covered(
+    output: &mut impl Write,
+    group: &Group,
+    coverage: &CoverageData,
+) -> io::Result<()> {
+    let locations: Vec<&InputLocation> = group.locations().collect();
+
+    writeln!(output, "Covered patterns in {}:", group.query)?;
+    for pattern in &coverage.covered_patterns {
+        let (line, column) =
+            line_column(&group.language.query.query_content, pattern.span.offset());
+        let inputs: Vec<String> = pattern
+            .inputs
+            .iter()
+            .map(|&idx| locations[idx].to_string())
+            .collect();
+
+        writeln!(output, "  {line}:{column} {}", inputs.join(", "))?;
+    }
+
+    output.flush()
+}
+
+/// Write the node kinds that the query does not handle, with examples from the group's inputs
+pub fn write_unhandled(
+    output: &mut impl Write,
+    group: &Group,
+    unhandled: &[UnhandledKind],
+) -> io::Result<()> {
+    if unhandled.is_empty() {
+        writeln!(output, "All node kinds are handled by {}", group.query)?;
+        return output.flush();
+    }
+
+    let locations: Vec<&InputLocation> = group.locations().collect();
+    let width = unhandled
+        .iter()
+        .map(|kind| kind.kind.len())
+        .max()
+        .unwrap_or(0);
+
+    writeln!(output, "Node kinds not handled by {}:", group.query)?;
+    for kind in unhandled {
+        let examples: Vec<String> = kind
+            .examples
+            .iter()
+            .map(|(idx, position)| {
+                format!("{}:{}:{}", locations[*idx], position.row, position.column)
+            })
+            .collect();
+
+        writeln!(
+            output,
+            "  {:width$}  {:>5}  {}",
+            kind.kind,
+            kind.count,
+            examples.join(", ")
+        )?;
+    }
+
+    output.flush()
+}
+
+/// Write the named node kinds and field names of a language's grammar that its query never refers to
+pub fn write_unreferenced(
+    output: &mut impl Write,
+    language: &Language,
+    query: &QuerySource,
+    coverage: &GrammarCoverage,
+) -> io::Result<()> {
+    if coverage.is_empty() {
+        writeln!(
+            output,
+            "All node kinds and fields of the {} grammar are referred to by {query}",
+            language.name
+        )?;
+        return output.flush();
+    }
+
+    for (what, names) in [
+        ("Node kinds", &coverage.kinds),
+        ("Fields", &coverage.fields),
+    ] {
+        if names.is_empty() {
+            continue;
+        }
+
+        writeln!(
+            output,
+            "{what} of the {} grammar not referred to by {query}:",
+            language.name
+        )?;
+        for name in names {
+            writeln!(output, "  {name}")?;
+        }
+    }
+
+    output.flush()
+}
@@ -0,0 +1,564 @@
+//! A daemon that keeps the configuration, grammars and queries loaded, serving formatting requests
+//! over a Unix domain socket, along with the client that forwards to it.
+//!
+//! The protocol is newline-delimited JSON: each line is a request, answered by one line of
+//! response. A client first says hello with the identity of its configuration; the daemon only
+//! formats for clients whose configuration matches its own, so that a daemon started in one
+//! project is not used by another. Other clients are expected to format locally instead.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream, unix::OwnedWriteHalf},
+    sync::mpsc,
+};
+use topiary_config::{Configuration, source::Source};
+use topiary_core::{Operation, formatter_str};
+
+use crate::{
+    cli::{Cli, Commands},
+    error::{CLIError, CLIResult, TopiaryError},
+    io::{InputFile, Overrides, QuerySource},
+    language::LanguageDefinitionCache,
+};
+
+// Configuration files are often saved in several steps, so we wait for this long after a change
+// for any others, before reloading
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// The socket that the daemon listens on, unless overridden
+fn default_socket() -> PathBuf {
+    let dirs = topiary_config::project_dirs();
+
+    dirs.runtime_dir()
+        .unwrap_or_else(|| dirs.cache_dir())
+        .join("daemon.sock")
+}
+
+fn socket_from_env() -> PathBuf {
+    std::env::var_os("TOPIARY_DAEMON_SOCKET")
+        .map(PathBuf::from)
+        .unwrap_or_else(default_socket)
+}
+
+/// Everything, other than the configuration files' contents, that determines which configuration
+/// and queries are used. The daemon and its clients must agree on this.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Identity {
+    version: String,
+    languages_files: Vec<PathBuf>,
+    merge: bool,
+    language_dir: Option<PathBuf>,
+}
+
+impl Identity {
+    fn new(merge: bool, file: &Option<PathBuf>) -> Self {
+        let file = file
+            .as_ref()
+            .map(|file| std::path::absolute(file).unwrap_or_else(|_| file.clone()));
+
+        Self {
+            version: env!("CARGO_PKG_VERSION").into(),
+            languages_files: Source::config_sources(&file)
+                .filter_map(|(_, source)| source.languages_file())
+                .collect(),
+            merge,
+            language_dir: std::env::var_os("TOPIARY_LANGUAGE_DIR").map(PathBuf::from),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request {
+    Hello {
+        identity: Identity,
+    },
+    Format {
+        language: Option<String>,
+        query: Option<PathBuf>,
+        path: Option<PathBuf>,
+        skip_idempotence: bool,
+        tolerate_parsing_errors: bool,
+        input: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Response {
+    Ready,
+    Mismatch,
+    Formatted {
+        output: String,
+    },
+    Failed {
+        kind: String,
+        message: String,
+        cause: Option<String>,
+        exit_code: u8,
+    },
+}
+
+impl From<TopiaryError> for Response {
+    fn from(e: TopiaryError) -> Self {
+        Response::Failed {
+            kind: e.kind().into(),
+            message: e.to_string(),
+            cause: std::error::Error::source(&e).map(ToString::to_string),
+            exit_code: e.exit_code(),
+        }
+    }
+}
+
+fn protocol_error(message: impl Into<String>) -> Response {
+    Response::Failed {
+        kind: "Protocol".into(),
+        message: message.into(),
+        cause: None,
+        exit_code: 10,
+    }
+}
+
+fn daemon_error<E>(message: impl Into<String>) -> impl FnOnce(E) -> TopiaryError
+where
+    E: std::error::Error + 'static,
+{
+    move |e| TopiaryError::Bin(message.into(), Some(CLIError::Generic(Box::new(e))))
+}
+
+/// The loaded configuration, and the language definitions built from it
+struct State {
+    identity: Identity,
+    config: Configuration,
+    languages: LanguageDefinitionCache,
+
+    // The modification time of each query file, when its language definition was last fetched,
+    // so that definitions are rebuilt when their query changes
+    queries: Mutex<HashMap<PathBuf, Option<SystemTime>>>,
+}
+
+impl State {
+    fn new(config: Configuration, merge: bool, file: &Option<PathBuf>) -> Self {
+        Self {
+            identity: Identity::new(merge, file),
+            config,
+            languages: LanguageDefinitionCache::new(),
+            queries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Invalidate the language definitions that use the given query file, if it has changed since
+    /// they were fetched
+    async fn refresh(&self, query: &Path) {
+        let modified = query.metadata().and_then(|m| m.modified()).ok();
+        let stale = {
+            let mut queries = self.queries.lock().unwrap();
+            queries
+                .insert(query.to_path_buf(), modified)
+                .is_some_and(|previous| previous != modified)
+        };
+
+        if stale {
+            log::info!("Query {} changed", query.display());
+            self.languages.invalidate(query).await;
+        }
+    }
+
+    async fn format(
+        &self,
+        language: Option<String>,
+        query: Option<PathBuf>,
+        path: Option<PathBuf>,
+        skip_idempotence: bool,
+        tolerate_parsing_errors: bool,
+        input: &str,
+    ) -> CLIResult<String> {
+        if language.is_none() && path.is_none() {
+            return Err(TopiaryError::Bin(
+                "A language or path is needed to format standard input".into(),
+                None,
+            ));
+        }
+
+        // The input is resolved from the request alone; the daemon's own standard input is never
+        // read, and any detection from the input's first line is from that of the request
+        let source = InputFile::stdin(
+            &self.config,
+            path.as_ref(),
+            &Overrides::new(language, query),
+            Some(input.as_bytes().into()),
+        )?;
+        if let QuerySource::Path(query) = source.query() {
+            self.refresh(query).await;
+        }
+
+        let language = self.languages.fetch(&source).await?;
+
+        log::info!(
+            "Formatting a request, as {} using {}",
+            source.language().name,
+            source.query()
+        );
+
+        let mut output = Vec::new();
+        formatter_str(
+            input,
+            &mut output,
+            &language,
+            Operation::Format {
+                skip_idempotence,
+                tolerate_parsing_errors,
+            },
+        )?;
+
+        // The formatter only ever emits valid UTF-8, given valid UTF-8 input
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+}
+
+struct Daemon {
+    merge: bool,
+    file: Option<PathBuf>,
+
+    // NOTE This lock is never held over an await point; connections take their own reference to
+    // the state, so that a reload does not affect requests in flight
+    state: RwLock<Arc<State>>,
+}
+
+impl Daemon {
+    fn state(&self) -> Arc<State> {
+        self.state.read().unwrap().clone()
+    }
+
+    /// Reload the configuration, discarding all language definitions. If the new configuration
+    /// is invalid, we carry on with the old one.
+    fn reload(&self) {
+        match Configuration::fetch(self.merge, &self.file) {
+            Ok((config, _)) => {
+                *self.state.write().unwrap() = Arc::new(State::new(config, self.merge, &self.file));
+                log::info!("Reloaded configuration");
+            }
+
+            Err(e) => {
+                log::error!("Could not reload configuration, so keeping the previous one: {e}")
+            }
+        }
+    }
+
+    async fn handle(&self, request: Request, state: &mut Option<Arc<State>>) -> Response {
+        match (request, state.as_ref()) {
+            (Request::Hello { identity }, _) => {
+                let current = self.state();
+                if current.identity == identity {
+                    *state = Some(current);
+                    Response::Ready
+                } else {
+                    log::debug!("Client identity {identity:?} does not match ours");
+                    Response::Mismatch
+                }
+            }
+
+            (Request::Format { .. }, None) => protocol_error("Say hello before formatting"),
+
+            (
+                Request::Format {
+                    language,
+                    query,
+                    path,
+                    skip_idempotence,
+                    tolerate_parsing_errors,
+                    input,
+                },
+                Some(state),
+            ) => state
+                .format(
+                    language,
+                    query,
+                    path,
+                    skip_idempotence,
+                    tolerate_parsing_errors,
+                    &input,
+                )
+                .await
+                .map_or_else(Response::from, |output| Response::Formatted { output }),
+        }
+    }
+
+    async fn serve_connection(&self, stream: UnixStream) -> std::io::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        // The configuration is fixed for the connection, once the client has said hello
+        let mut state = None;
+
+        while let Some(line) = lines.next_line().await? {
+            let response = match serde_json::from_str(&line) {
+                Ok(request) => self.handle(request, &mut state).await,
+                Err(e) => protocol_error(format!("Invalid request: {e}")),
+            };
+
+            send(&mut writer, &response).await?;
+        }
+
+        Ok(())
+    }
+}
+
+async fn send<T: Serialize>(writer: &mut OwnedWriteHalf, message: &T) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer.write_all(&line).await
+}
+
+/// Watch the configuration files, reloading the daemon's configuration whenever they change
+#[allow(clippy::result_large_err)]
+fn watch_config(daemon: Arc<Daemon>) -> CLIResult<RecommendedWatcher> {
+    let files: BTreeSet<PathBuf> = daemon
+        .state()
+        .identity
+        .languages_files
+        .iter()
+        .cloned()
+        .collect();
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut watcher = RecommendedWatcher::new(
+        move |event: notify::Result<notify::Event>| {
+            // The receiver only goes away when the daemon stops, so there's nothing to do if this
+            // fails
+            let _ = tx.send(event);
+        },
+        notify::Config::default(),
+    )
+    .map_err(daemon_error("Could not watch configuration for changes"))?;
+
+    // We watch the directories containing the files, rather than the files themselves, so that
+    // configuration files that do not exist yet are picked up when they are created
+    let directories: BTreeSet<&Path> = files.iter().filter_map(|file| file.parent()).collect();
+    for directory in directories
+        .into_iter()
+        .filter(|directory| directory.is_dir())
+    {
+        watcher
+            .watch(directory, RecursiveMode::NonRecursive)
+            .map_err(daemon_error("Could not watch configuration for changes"))?;
+    }
+
+    tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            let mut events = vec![event];
+            while let Ok(Some(event)) = tokio::time::timeout(DEBOUNCE, rx.recv()).await {
+                events.push(event);
+            }
+
+            let changed = events.into_iter().flatten().any(|event| {
+                !matches!(event.kind, EventKind::Access(_))
+                    && event.paths.iter().any(|path| files.contains(path))
+            });
+
+            if changed {
+                daemon.reload();
+            }
+        }
+    });
+
+    Ok(watcher)
+}
+
+/// Run the daemon on the given socket, until interrupted
+#[allow(clippy::result_large_err)]
+pub async fn serve(
+    config: Configuration,
+    merge: bool,
+    file: Option<PathBuf>,
+    socket: Option<PathBuf>,
+) -> CLIResult<()> {
+    let socket = socket.unwrap_or_else(default_socket);
+
+    if let Some(parent) = socket.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // A socket file can be left behind by a daemon that did not shut down cleanly, which we can
+    // replace, but we must not replace one that is still in use
+    if socket.exists() {
+        if UnixStream::connect(&socket).await.is_ok() {
+            return Err(TopiaryError::Bin(
+                format!("A daemon is already listening on {}", socket.display()),
+                None,
+            ));
+        }
+
+        std::fs::remove_file(&socket)?;
+    }
+
+    let listener = UnixListener::bind(&socket)?;
+
+    let state = RwLock::new(Arc::new(State::new(config, merge, &file)));
+    let daemon = Arc::new(Daemon { merge, file, state });
+
+    // The watcher stops when dropped, so we keep it until the daemon stops
+    let _watcher = watch_config(daemon.clone())?;
+
+    log::info!("Listening on {}", socket.display());
+
+    let result = loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let stream = match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(e) => break Err(e.into()),
+                };
+
+                let daemon = daemon.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = daemon.serve_connection(stream).await {
+                        log::warn!("Connection closed: {e}");
+                    }
+                });
+            }
+
+            _ = tokio::signal::ctrl_c() => break Ok(()),
+        }
+    };
+
+    std::fs::remove_file(&socket)?;
+    result
+}
+
+/// A connection to the daemon, which has agreed to format for us
+struct Client {
+    lines: tokio::io::Lines<BufReader<tokio::net::unix::OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+}
+
+impl Client {
+    /// Connect to the daemon and say hello, or `None` if its configuration differs from ours
+    async fn connect(socket: &Path, identity: Identity) -> std::io::Result<Option<Self>> {
+        let (reader, writer) = UnixStream::connect(socket).await?.into_split();
+        let mut client = Self {
+            lines: BufReader::new(reader).lines(),
+            writer,
+        };
+
+        Ok(match client.request(&Request::Hello { identity }).await? {
+            Response::Ready => Some(client),
+            _ => None,
+        })
+    }
+
+    async fn request(&mut self, request: &Request) -> std::io::Result<Response> {
+        send(&mut self.writer, request).await?;
+
+        let line = self.lines.next_line().await?.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "The daemon closed the connection",
+            )
+        })?;
+
+        Ok(serde_json::from_str(&line)?)
+    }
+}
+
+/// Forward formatting standard input to a running daemon, when its configuration matches ours.
+/// This returns `None` when the request should be handled locally instead: because it is not
+/// formatting standard input, or because no suitable daemon is running.
+pub async fn forward(args: &Cli) -> Option<CLIResult<()>> {
+    let Commands::Format {
+        tolerate_parsing_errors,
+        skip_idempotence,
+        check: false,
+        diff: false,
+        range: None,
+        lines: None,
+        watch: false,
+        report: None,
+        inputs,
+        ..
+    } = &args.command
+    else {
+        return None;
+    };
+
+    if !inputs.files.is_empty() || inputs.changed_since.is_some() || inputs.staged {
+        return None;
+    }
+
+    let socket = socket_from_env();
+    let identity = Identity::new(args.global.merge_configuration, &args.global.configuration);
+
+    // Nothing has been read from standard input yet, so any failure so far can fall back
+    let mut client = match Client::connect(&socket, identity).await {
+        Ok(Some(client)) => client,
+
+        Ok(None) => {
+            log::info!("The daemon's configuration differs from ours, so formatting locally");
+            return None;
+        }
+
+        Err(e) => {
+            log::debug!("Not using a daemon at {}: {e}", socket.display());
+            return None;
+        }
+    };
+
+    log::info!("Forwarding to the daemon at {}", socket.display());
+
+    // Paths are resolved from our working directory, rather than the daemon's
+    let absolute = |path: &PathBuf| std::path::absolute(path).unwrap_or_else(|_| path.clone());
+
+    let result = async {
+        let mut input = String::new();
+        tokio::io::stdin().read_to_string(&mut input).await?;
+
+        let request = Request::Format {
+            language: inputs.overrides.language.clone(),
+            query: inputs.overrides.query.as_ref().map(absolute),
+            path: inputs.overrides.stdin_filepath.as_ref().map(absolute),
+            skip_idempotence: *skip_idempotence,
+            tolerate_parsing_errors: *tolerate_parsing_errors,
+            input,
+        };
+
+        match client.request(&request).await? {
+            Response::Formatted { output } => {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(output.as_bytes())?;
+                stdout.flush()?;
+
+                Ok(())
+            }
+
+            Response::Failed {
+                message,
+                cause,
+                exit_code,
+                ..
+            } => Err(TopiaryError::Bin(
+                message,
+                Some(CLIError::Forwarded {
+                    cause: cause.map(Into::into),
+                    exit_code,
+                }),
+            )),
+
+            response => Err(TopiaryError::Bin(
+                format!("Unexpected response from the daemon: {response:?}"),
+                None,
+            )),
+        }
+    };
+
+    Some(result.await)
+}
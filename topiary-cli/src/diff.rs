@@ -0,0 +1,174 @@
+//! Unified diff rendering, for reviewing formatting changes without applying them.
+
+use std::fmt::Write;
+
+use prettydiff::basic::{DiffOp, diff};
+
+/// A single line of the diff, tagged with how it differs between the two inputs
+#[derive(Clone, Copy, PartialEq)]
+enum Tag {
+    Equal,
+    Remove,
+    Insert,
+}
+
+struct Line<'a> {
+    tag: Tag,
+    content: &'a str,
+
+    // 0-based indices of this line in the old and new inputs, respectively; for lines that don't
+    // exist on one side, this is the index of the line that would follow it
+    old_index: usize,
+    new_index: usize,
+}
+
+/// Render a unified diff between `old` and `new`, with `context` lines of unchanged context around
+/// each hunk. If the inputs are identical, an empty string is returned.
+pub fn unified(old: &str, new: &str, old_name: &str, new_name: &str, context: usize) -> String {
+    // We keep the line terminators, so that a change to the final newline is still a change
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+
+    let lines = flatten(&diff(&old_lines, &new_lines));
+    let changes: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, line)| (line.tag != Tag::Equal).then_some(idx))
+        .collect();
+
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    let mut buffer = String::new();
+    writeln!(buffer, "--- {old_name}").unwrap();
+    writeln!(buffer, "+++ {new_name}").unwrap();
+
+    // Group changes into hunks, merging those whose context would overlap or touch
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for idx in changes {
+        match hunks.last_mut() {
+            Some((_, last)) if idx - *last <= 2 * context + 1 => *last = idx,
+            _ => hunks.push((idx, idx)),
+        }
+    }
+
+    for (first, last) in hunks {
+        let start = first.saturating_sub(context);
+        let end = (last + context + 1).min(lines.len());
+        let hunk = &lines[start..end];
+
+        let old_count = hunk.iter().filter(|l| l.tag != Tag::Insert).count();
+        let new_count = hunk.iter().filter(|l| l.tag != Tag::Remove).count();
+
+        // Hunk ranges are 1-based, unless they're empty, in which case they refer to the line
+        // preceding the (empty) range
+        let range_start = |index: usize, count: usize| if count == 0 { index } else { index + 1 };
+
+        writeln!(
+            buffer,
+            "@@ -{},{old_count} +{},{new_count} @@",
+            range_start(hunk[0].old_index, old_count),
+            range_start(hunk[0].new_index, new_count),
+        )
+        .unwrap();
+
+        for line in hunk {
+            let prefix = match line.tag {
+                Tag::Equal => ' ',
+                Tag::Remove => '-',
+                Tag::Insert => '+',
+            };
+
+            match line.content.strip_suffix('\n') {
+                Some(content) => writeln!(buffer, "{prefix}{content}").unwrap(),
+                None => {
+                    writeln!(buffer, "{prefix}{}", line.content).unwrap();
+                    writeln!(buffer, "\\ No newline at end of file").unwrap();
+                }
+            }
+        }
+    }
+
+    buffer
+}
+
+/// Colour the lines of a unified diff with ANSI escape codes, for output to a terminal. Lines that
+/// are not part of a diff are left as they are.
+pub fn colourise(diff: &str) -> String {
+    let mut buffer = String::new();
+
+    for line in diff.split_inclusive('\n') {
+        let colour = if line.starts_with("---") || line.starts_with("+++") {
+            "1"
+        } else if line.starts_with("@@") {
+            "36"
+        } else if line.starts_with('-') {
+            "31"
+        } else if line.starts_with('+') {
+            "32"
+        } else {
+            buffer.push_str(line);
+            continue;
+        };
+
+        let (content, newline) = match line.strip_suffix('\n') {
+            Some(content) => (content, "\n"),
+            None => (line, ""),
+        };
+        write!(buffer, "\x1b[{colour}m{content}\x1b[0m{newline}").unwrap();
+    }
+
+    buffer
+}
+
+/// Flatten the diff operations into individual lines, keeping track of line indices on both sides
+fn flatten<'a>(ops: &[DiffOp<'_, &'a str>]) -> Vec<Line<'a>> {
+    let mut lines = Vec::new();
+    let mut old_index = 0;
+    let mut new_index = 0;
+
+    let mut push = |tag: Tag, content: &'a str, old_index: &mut usize, new_index: &mut usize| {
+        lines.push(Line {
+            tag,
+            content,
+            old_index: *old_index,
+            new_index: *new_index,
+        });
+
+        if tag != Tag::Insert {
+            *old_index += 1;
+        }
+
+        if tag != Tag::Remove {
+            *new_index += 1;
+        }
+    };
+
+    for op in ops {
+        match op {
+            DiffOp::Equal(equal) => equal
+                .iter()
+                .for_each(|l| push(Tag::Equal, l, &mut old_index, &mut new_index)),
+
+            DiffOp::Remove(removed) => removed
+                .iter()
+                .for_each(|l| push(Tag::Remove, l, &mut old_index, &mut new_index)),
+
+            DiffOp::Insert(inserted) => inserted
+                .iter()
+                .for_each(|l| push(Tag::Insert, l, &mut old_index, &mut new_index)),
+
+            DiffOp::Replace(removed, inserted) => {
+                removed
+                    .iter()
+                    .for_each(|l| push(Tag::Remove, l, &mut old_index, &mut new_index));
+                inserted
+                    .iter()
+                    .for_each(|l| push(Tag::Insert, l, &mut old_index, &mut new_index));
+            }
+        }
+    }
+
+    lines
+}
@@ -0,0 +1,236 @@
+//! End-to-end checks of the configured languages, as run by `topiary doctor`. Problems with a
+//! language otherwise only surface once an input in that language is formatted.
+
+use std::{collections::HashSet, error::Error, path::PathBuf};
+
+use tabled::{Table, settings::Style};
+use topiary_config::{
+    Configuration, Source, error::TopiaryConfigFetchingError, language::Language,
+};
+use topiary_core::TopiaryQuery;
+use topiary_tree_sitter_facade::Parser;
+
+use crate::{
+    error::{CLIError, CLIResult, TopiaryError},
+    io::to_query_from_language,
+};
+
+/// The outcome of each check of a language, where `None` means it could not be checked
+struct Diagnosis {
+    grammar: Option<bool>,
+    symbol: Option<bool>,
+    abi: Option<(u32, bool)>,
+    query: Option<bool>,
+    captures: Option<bool>,
+    query_file: String,
+    config_source: String,
+    problems: Vec<String>,
+}
+
+/// A message for the error, with its cause, if any
+fn describe(e: &dyn Error) -> String {
+    match e.source() {
+        Some(source) => format!("{e}: {source}"),
+        None => e.to_string(),
+    }
+}
+
+/// A one-line message for the error; its `Display` can otherwise run to a backtrace
+fn describe_fetching(e: &TopiaryConfigFetchingError) -> String {
+    match e {
+        TopiaryConfigFetchingError::Git(e) => format!("Git error: {e:#}"),
+        TopiaryConfigFetchingError::LibLoading(e) => format!("Libloading error: {e}"),
+        e => e.to_string(),
+    }
+}
+
+/// Check that the language's grammar can be fetched, compiled and loaded, and that its query can
+/// be found and compiles against it
+async fn diagnose(language: &Language, sources: &[(Source, HashSet<String>)]) -> Diagnosis {
+    let mut diagnosis = Diagnosis {
+        grammar: None,
+        symbol: None,
+        abi: None,
+        query: None,
+        captures: None,
+        query_file: "-".into(),
+        config_source: sources
+            .iter()
+            .find(|(_, names)| names.contains(&language.name))
+            .map_or("-".into(), |(source, _)| source.to_string()),
+        problems: Vec::new(),
+    };
+
+    // Loading the grammar fetches and compiles it, if necessary
+    let grammar = match language.grammar() {
+        Ok(grammar) => {
+            diagnosis.grammar = Some(true);
+            diagnosis.symbol = Some(true);
+            Some(grammar)
+        }
+
+        // A library that exists, but can't be loaded, is most likely missing the symbol
+        Err(e @ TopiaryConfigFetchingError::LibLoading(_))
+            if language.library_path().is_ok_and(|path| path.is_file()) =>
+        {
+            diagnosis.grammar = Some(true);
+            diagnosis.symbol = Some(false);
+            diagnosis.problems.push(format!(
+                "Cannot load the grammar (is its symbol right?): {}",
+                describe_fetching(&e)
+            ));
+            None
+        }
+
+        Err(e) => {
+            diagnosis.grammar = Some(false);
+            diagnosis.problems.push(format!(
+                "Cannot fetch or compile the grammar: {}",
+                describe_fetching(&e)
+            ));
+            None
+        }
+    };
+
+    if let Some(grammar) = &grammar {
+        let compatible = Parser::new().is_ok_and(|mut parser| parser.set_language(grammar).is_ok());
+        if !compatible {
+            diagnosis.problems.push(format!(
+                "The grammar's ABI version, {}, is not supported",
+                grammar.version()
+            ));
+        }
+        diagnosis.abi = Some((grammar.version(), compatible));
+    }
+
+    let query = match to_query_from_language(language) {
+        Ok(query) => {
+            diagnosis.query_file = query.to_string();
+            query.get_content().await.map_err(|e| describe(&e))
+        }
+        Err(e) => Err(describe(&e)),
+    };
+
+    match (grammar, query) {
+        (_, Err(e)) => diagnosis
+            .problems
+            .push(format!("Cannot read the query: {e}")),
+
+        (Some(grammar), Ok(content)) => match TopiaryQuery::new(&grammar, &content) {
+            Ok(query) => {
+                let problems = query.check();
+                diagnosis.query = Some(true);
+                diagnosis.captures = Some(problems.is_empty());
+                diagnosis.problems.extend(problems);
+            }
+
+            Err(e) => {
+                diagnosis.query = Some(false);
+                diagnosis.problems.push(describe(&e));
+            }
+        },
+
+        // The query can only be compiled against the grammar
+        (None, Ok(_)) => {}
+    }
+
+    diagnosis
+}
+
+/// Check each of the given languages, or all of them, and print a table of the outcomes followed
+/// by any problems; failing if there are any
+#[allow(clippy::result_large_err)]
+pub async fn run(
+    config: &Configuration,
+    merge: bool,
+    file: &Option<PathBuf>,
+    language: Option<String>,
+) -> CLIResult<()> {
+    let mut languages: Vec<&Language> = match &language {
+        Some(name) => vec![config.get_language(name)?],
+        None => config.languages().iter().collect(),
+    };
+    languages.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // The configuration sources that were read, from highest to lowest priority, each with the
+    // languages it defines
+    let sources = if merge {
+        Source::fetch_all(file)
+    } else {
+        match Source::fetch_one(file) {
+            Source::Builtin => vec![Source::Builtin],
+            source => vec![source, Source::Builtin],
+        }
+    };
+    let sources: Vec<_> = sources
+        .into_iter()
+        .map(|source| {
+            let names = source.language_names();
+            (source, names)
+        })
+        .collect();
+
+    let outcome = |checked: Option<bool>| match checked {
+        Some(true) => "\u{2705}",  // Check Mark
+        Some(false) => "\u{274C}", // Cross Mark
+        None => "-",
+    };
+
+    let mut rows = Vec::new();
+    let mut problems = Vec::new();
+    for language in languages {
+        log::info!("Checking {}", language.name);
+        let diagnosis = diagnose(language, &sources).await;
+
+        rows.push([
+            language.name.clone(),
+            outcome(diagnosis.grammar).into(),
+            outcome(diagnosis.symbol).into(),
+            diagnosis.abi.map_or("-".into(), |(version, ok)| {
+                format!("{} {version}", outcome(Some(ok)))
+            }),
+            outcome(diagnosis.query).into(),
+            outcome(diagnosis.captures).into(),
+            diagnosis.query_file,
+            diagnosis.config_source,
+        ]);
+
+        problems.extend(
+            diagnosis
+                .problems
+                .into_iter()
+                .map(|problem| format!("{}: {problem}", language.name)),
+        );
+    }
+
+    let mut table = Table::builder(rows);
+    table.remove_record(0);
+    table.insert_record(
+        0,
+        [
+            "language",
+            "grammar",
+            "symbol",
+            "ABI",
+            "query",
+            "captures",
+            "query file",
+            "config source",
+        ],
+    );
+    println!("{}", table.build().with(Style::modern_rounded()));
+
+    if problems.is_empty() {
+        return Ok(());
+    }
+
+    println!();
+    for problem in &problems {
+        println!("{problem}");
+    }
+
+    Err(TopiaryError::Bin(
+        "Some languages have problems".into(),
+        Some(CLIError::Failed),
+    ))
+}
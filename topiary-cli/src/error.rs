@@ -24,8 +24,23 @@ pub enum CLIError {
     Multiple,
     UnsupportedLanguage(String),
 
+    /// Some inputs are not formatted (when running with `--check`)
+    Unformatted,
+
+    /// Some node kinds are not handled by the query (when running `coverage --nodes` or `--grammar`)
+    Unhandled,
+
+    /// Some tests or checks failed (when running `test` or `doctor`)
+    Failed,
+
     /// Could not detect the input language from the `(filename, Option<extension>)`
     LanguageDetection(PathBuf, Option<String>),
+
+    /// An error from a request forwarded to the daemon, with the exit code it would have caused
+    Forwarded {
+        cause: Option<Box<dyn error::Error>>,
+        exit_code: u8,
+    },
 }
 
 /// # Safety
@@ -41,36 +56,61 @@ unsafe impl Send for TopiaryError {}
 /// to it. Since our TopiaryError contains no mutable data, TopiaryError is Sync.
 unsafe impl Sync for TopiaryError {}
 
-impl fmt::Display for TopiaryError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl TopiaryError {
+    /// The name of the error's underlying variant, for machine-readable reports
+    pub fn kind(&self) -> &'static str {
         match self {
-            TopiaryError::Lib(error) => write!(f, "{error}"),
-            TopiaryError::Bin(message, _) => write!(f, "{message}"),
-            TopiaryError::Config(e) => write!(f, "{e}"),
+            TopiaryError::Lib(error) => match error {
+                FormatterError::Idempotence => "Idempotence",
+                FormatterError::IdempotenceParsing(_) => "IdempotenceParsing",
+                FormatterError::Internal(_, _) => "Internal",
+                FormatterError::Parsing(_) => "Parsing",
+                FormatterError::PatternDoesNotMatch => "PatternDoesNotMatch",
+                FormatterError::Query(_, _) => "Query",
+                FormatterError::Io(_) => "Io",
+                FormatterError::InvalidSelection(_) => "InvalidSelection",
+            },
+
+            TopiaryError::Bin(_, error) => match error {
+                Some(CLIError::IOError(_)) => "IOError",
+                Some(CLIError::Generic(_)) | None => "Generic",
+                Some(CLIError::Multiple) => "Multiple",
+                Some(CLIError::UnsupportedLanguage(_)) => "UnsupportedLanguage",
+                Some(CLIError::Unformatted) => "Unformatted",
+                Some(CLIError::Unhandled) => "Unhandled",
+                Some(CLIError::Failed) => "Failed",
+                Some(CLIError::LanguageDetection(_, _)) => "LanguageDetection",
+                Some(CLIError::Forwarded { .. }) => "Forwarded",
+            },
+
+            TopiaryError::Config(error) => match error {
+                TopiaryConfigError::FileNotFound(_) => "FileNotFound",
+                TopiaryConfigError::UnknownLanguage(_) => "UnknownLanguage",
+                TopiaryConfigError::UnknownExtension(_) => "UnknownExtension",
+                TopiaryConfigError::NoExtension(_) => "NoExtension",
+                TopiaryConfigError::QueryFileNotFound(_) => "QueryFileNotFound",
+                TopiaryConfigError::Io(_) => "Io",
+                TopiaryConfigError::Missing => "Missing",
+                TopiaryConfigError::TreeSitterFacade(_) => "TreeSitterFacade",
+                TopiaryConfigError::Nickel(_) => "Nickel",
+                TopiaryConfigError::NickelDeserialization(_) => "NickelDeserialization",
+                TopiaryConfigError::Fetching(_) => "Fetching",
+            },
         }
     }
-}
 
-impl error::Error for TopiaryError {
-    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    /// The process exit code for the error
+    pub fn exit_code(&self) -> u8 {
         match self {
-            TopiaryError::Lib(error) => error.source(),
-            TopiaryError::Bin(_, Some(CLIError::IOError(error))) => Some(error),
-            TopiaryError::Bin(_, Some(CLIError::Generic(error))) => error.source(),
-            TopiaryError::Bin(_, Some(CLIError::Multiple)) => None,
-            TopiaryError::Bin(_, Some(CLIError::UnsupportedLanguage(_))) => None,
-            TopiaryError::Bin(_, Some(CLIError::LanguageDetection(_, _))) => None,
-            TopiaryError::Bin(_, None) => None,
-            TopiaryError::Config(error) => error.source(),
-        }
-    }
-}
+            // Inputs that are not formatted, when checking: Exit 11
+            // (Distinct from other negative results, so CI can tell it apart from a failure)
+            TopiaryError::Bin(_, Some(CLIError::Unformatted)) => 11,
 
-impl From<TopiaryError> for ExitCode {
-    fn from(e: TopiaryError) -> Self {
-        let exit_code = match e {
             // Things went well but Topiary needs to answer 'false' in a clean way: Exit 1
-            _ if e.benign() => 1,
+            _ if self.benign() => 1,
+
+            // Errors from the daemon: As they would have exited
+            TopiaryError::Bin(_, Some(CLIError::Forwarded { exit_code, .. })) => *exit_code,
 
             // Multiple errors: Exit 9
             TopiaryError::Bin(_, Some(CLIError::Multiple)) => 9,
@@ -94,13 +134,48 @@ impl From<TopiaryError> for ExitCode {
             | TopiaryError::Bin(_, Some(CLIError::IOError(_))) => 3,
 
             // Bad arguments: Exit 2
-            // (Handled by clap: https://github.com/clap-rs/clap/issues/3426)
+            // (Mostly handled by clap: https://github.com/clap-rs/clap/issues/3426; a range is
+            // only known to be invalid once the input is read)
+            TopiaryError::Lib(FormatterError::InvalidSelection(_)) => 2,
 
             // Anything else: Exit 10
             _ => 10,
-        };
+        }
+    }
+}
 
-        ExitCode::from(exit_code)
+impl fmt::Display for TopiaryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TopiaryError::Lib(error) => write!(f, "{error}"),
+            TopiaryError::Bin(message, _) => write!(f, "{message}"),
+            TopiaryError::Config(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl error::Error for TopiaryError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            TopiaryError::Lib(error) => error.source(),
+            TopiaryError::Bin(_, Some(CLIError::IOError(error))) => Some(error),
+            TopiaryError::Bin(_, Some(CLIError::Generic(error))) => error.source(),
+            TopiaryError::Bin(_, Some(CLIError::Multiple)) => None,
+            TopiaryError::Bin(_, Some(CLIError::UnsupportedLanguage(_))) => None,
+            TopiaryError::Bin(_, Some(CLIError::Unformatted)) => None,
+            TopiaryError::Bin(_, Some(CLIError::Unhandled)) => None,
+            TopiaryError::Bin(_, Some(CLIError::Failed)) => None,
+            TopiaryError::Bin(_, Some(CLIError::LanguageDetection(_, _))) => None,
+            TopiaryError::Bin(_, Some(CLIError::Forwarded { cause, .. })) => cause.as_deref(),
+            TopiaryError::Bin(_, None) => None,
+            TopiaryError::Config(error) => error.source(),
+        }
+    }
+}
+
+impl From<TopiaryError> for ExitCode {
+    fn from(e: TopiaryError) -> Self {
+        ExitCode::from(e.exit_code())
     }
 }
 
@@ -180,6 +255,9 @@ impl Benign for TopiaryError {
     fn benign(&self) -> bool {
         match self {
             TopiaryError::Lib(FormatterError::PatternDoesNotMatch) => true,
+            TopiaryError::Bin(_, Some(CLIError::Unformatted)) => true,
+            TopiaryError::Bin(_, Some(CLIError::Unhandled)) => true,
+            TopiaryError::Bin(_, Some(CLIError::Failed)) => true,
             _ => false,
         }
     }
@@ -0,0 +1,94 @@
+//! Output formats for the provenance of the whitespace around a location, as explained by
+//! `topiary explain`.
+
+use std::io::{self, Write};
+
+use clap::ValueEnum;
+use serde::Serialize;
+use topiary_core::{Fate, Gap, LeafTrace, Provenance};
+
+/// Explanation output formats
+#[derive(Clone, Debug, ValueEnum)]
+pub enum Format {
+    /// The atoms before and after the leaf, each with its origin and what became of it
+    Text,
+
+    /// JSON serialisation
+    Json,
+}
+
+#[derive(Serialize)]
+struct Explanation<'a> {
+    leaf: &'a LeafTrace,
+    before: Gap<'a>,
+    after: Gap<'a>,
+}
+
+/// Write the explanation of the whitespace around the given leaf to the output, in the given
+/// format
+pub fn write(
+    output: &mut impl Write,
+    provenance: &Provenance,
+    leaf: usize,
+    format: Format,
+) -> io::Result<()> {
+    let explanation = Explanation {
+        leaf: &provenance.leaves[leaf],
+        before: provenance.gap_before(leaf),
+        after: provenance.gap_after(leaf),
+    };
+
+    match format {
+        Format::Text => {
+            let leaf = explanation.leaf;
+            writeln!(output, "Leaf {} {:?}", leaf.node, leaf.text)?;
+
+            writeln!(output, "\nBefore:")?;
+            write_gap(output, &explanation.before)?;
+
+            writeln!(output, "\nAfter:")?;
+            write_gap(output, &explanation.after)?;
+        }
+
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *output, &explanation)?;
+            writeln!(output)?;
+        }
+    }
+
+    output.flush()
+}
+
+fn write_gap(output: &mut impl Write, gap: &Gap) -> io::Result<()> {
+    let mut result = Vec::new();
+
+    for atom in &gap.atoms {
+        writeln!(output, "  #{} {}: {}", atom.id, atom.atom, atom.origin)?;
+
+        for decision in &atom.decisions {
+            writeln!(output, "      {decision}")?;
+        }
+
+        match &atom.fate {
+            Fate::Kept { atom } => {
+                writeln!(output, "      kept as {atom}")?;
+                result.push(atom.as_str());
+            }
+            Fate::Dropped { reason, by: None } => writeln!(output, "      dropped: {reason}")?,
+            Fate::Dropped {
+                reason,
+                by: Some(by),
+            } => writeln!(output, "      dropped: {reason} (#{by})")?,
+        }
+    }
+
+    for skipped in &gap.skipped {
+        writeln!(output, "  skipped {}: {}", skipped.origin, skipped.reason)?;
+    }
+
+    if result.is_empty() {
+        writeln!(output, "  = nothing")
+    } else {
+        writeln!(output, "  = {}", result.join(", "))
+    }
+}
@@ -1,8 +1,15 @@
-use crate::error::CLIResult;
+use crate::error::{CLIError, CLIResult, TopiaryError};
+use ignore::{
+    WalkBuilder,
+    gitignore::Gitignore,
+    overrides::{Override, OverrideBuilder},
+};
 use std::{
+    collections::HashMap,
     fs,
     path::{Path, PathBuf},
 };
+use topiary_config::Configuration;
 
 #[cfg(unix)]
 use std::os::unix::fs::MetadataExt;
@@ -102,8 +109,17 @@ impl FileMeta {
 /// Given a vector of paths, recursively expand those that identify as directories, in place.
 /// Follow symlinks, if specified, and skip over files with multiple links. Ultimately, we'll
 /// finish with a vector of canonical paths to real files with a single link.
+///
+/// Directories are walked respecting `.gitignore` and `.topiaryignore` files, as well as the
+/// include and exclude globs from the configuration. Files found this way that are not in a
+/// configured language are skipped, if specified, whereas any files given explicitly are kept.
 #[allow(clippy::result_large_err)]
-pub fn traverse(files: &mut Vec<PathBuf>, follow_symlinks: bool) -> CLIResult<()> {
+pub fn traverse(
+    files: &mut Vec<PathBuf>,
+    follow_symlinks: bool,
+    config: &Configuration,
+    skip_unknown: bool,
+) -> CLIResult<()> {
     let mut expanded = vec![];
 
     for file in &mut *files {
@@ -131,8 +147,8 @@ pub fn traverse(files: &mut Vec<PathBuf>, follow_symlinks: bool) -> CLIResult<()
 
         if is_dir {
             // Descend into directory, symlink-aware as required
-            let mut subfiles = file.read_dir()?.flatten().map(|f| f.path()).collect();
-            traverse(&mut subfiles, follow_symlinks)?;
+            let mut subfiles = walk(file, follow_symlinks, config, skip_unknown)?;
+            traverse(&mut subfiles, follow_symlinks, config, skip_unknown)?;
             expanded.append(&mut subfiles);
         } else if meta.is_file() {
             if meta.is_symlink() && !follow_symlinks {
@@ -167,3 +183,191 @@ pub fn traverse(files: &mut Vec<PathBuf>, follow_symlinks: bool) -> CLIResult<()
     *files = expanded;
     Ok(())
 }
+
+/// The project root, which the include and exclude globs of the configuration are relative to:
+/// the nearest directory, from the current one, with a `.topiary` configuration directory or that
+/// is the root of a Git working tree; otherwise, the current directory itself.
+fn project_root() -> PathBuf {
+    let cwd = std::env::current_dir().unwrap_or_default();
+
+    cwd.ancestors()
+        .find(|dir| dir.join(".topiary").is_dir() || dir.join(".git").exists())
+        .unwrap_or(&cwd)
+        .to_path_buf()
+}
+
+/// The directory that the globs are relative to, for paths within the given directory: the
+/// project root, unless the directory is outside of the project, in which case it's the directory
+/// itself
+fn glob_root(dir: &Path) -> PathBuf {
+    let root = project_root();
+    let dir = std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf());
+
+    if dir.starts_with(&root) { root } else { dir }
+}
+
+/// The include and exclude globs of the configuration, relative to the given directory. We don't
+/// hand these to the walker as overrides, as those would take precedence over ignore files;
+/// inclusions only restrict. Paths must be absolute, to be matched against these.
+#[allow(clippy::result_large_err)]
+fn overrides(dir: &Path, config: &Configuration) -> CLIResult<Override> {
+    let glob_error = |e: ignore::Error| {
+        TopiaryError::Bin(
+            "Invalid include or exclude glob in configuration".into(),
+            Some(CLIError::Generic(Box::new(e))),
+        )
+    };
+
+    let mut overrides = OverrideBuilder::new(dir);
+    for glob in config.include() {
+        overrides.add(glob).map_err(glob_error)?;
+    }
+    for glob in config.exclude() {
+        overrides.add(&format!("!{glob}")).map_err(glob_error)?;
+    }
+
+    overrides.build().map_err(glob_error)
+}
+
+/// Keep only those of the given files, from the Git working tree at `root`, that a walk of `root`
+/// would find: that is, those that are not ignored by any `.topiaryignore`, `.ignore` or
+/// `.gitignore` files, nor excluded by the include and exclude globs from the configuration.
+#[allow(clippy::result_large_err)]
+pub fn retain_unignored(
+    files: &mut Vec<PathBuf>,
+    root: &Path,
+    config: &Configuration,
+) -> CLIResult<()> {
+    // Like a walk, files outside of the project have globs relative to the directory walked; here,
+    // the root of the working tree
+    let project = project_root();
+    let (project_overrides, root_overrides) =
+        (overrides(&project, config)?, overrides(root, config)?);
+
+    // The ignore files of each directory, if it has them, in order of precedence
+    const IGNORE_FILES: [&str; 3] = [".topiaryignore", ".ignore", ".gitignore"];
+    let mut ignore_files: HashMap<PathBuf, Vec<Option<Gitignore>>> = HashMap::new();
+
+    files.retain(|file| {
+        let (base, overrides) = if file.starts_with(&project) {
+            (project.as_path(), &project_overrides)
+        } else {
+            (root, &root_overrides)
+        };
+
+        // Like the walk, which prunes directories, a file is excluded if any of its directories is
+        let excluded = file
+            .ancestors()
+            .take_while(|path| path.starts_with(base) && *path != base)
+            .enumerate()
+            .any(|(depth, path)| overrides.matched(path, depth > 0).is_ignore());
+
+        let dirs: Vec<&Path> = file
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(root))
+            .collect();
+
+        for dir in &dirs {
+            ignore_files.entry(dir.to_path_buf()).or_insert_with(|| {
+                IGNORE_FILES
+                    .iter()
+                    .map(|name| dir.join(name))
+                    .map(|path| path.is_file().then(|| Gitignore::new(path).0))
+                    .collect()
+            });
+        }
+
+        // As when walking, each kind of ignore file takes precedence over the next, and within a
+        // kind, those in deeper directories take precedence
+        let ignored = (0..IGNORE_FILES.len())
+            .flat_map(|kind| dirs.iter().map(move |dir| (kind, dir)))
+            .filter_map(|(kind, dir)| ignore_files[*dir][kind].as_ref())
+            .map(|ignore| ignore.matched_path_or_any_parents(file, false))
+            .find(|matched| !matched.is_none())
+            .is_some_and(|matched| matched.is_ignore());
+
+        if excluded || ignored {
+            log::debug!("Skipping {}: Ignored or excluded", file.display());
+        }
+
+        !excluded && !ignored
+    });
+
+    Ok(())
+}
+
+/// Walk a directory, returning the files within it that Topiary should format. Directories
+/// themselves are not returned, as the walk already descends into them.
+#[allow(clippy::result_large_err)]
+fn walk(
+    dir: &Path,
+    follow_symlinks: bool,
+    config: &Configuration,
+    skip_unknown: bool,
+) -> CLIResult<Vec<PathBuf>> {
+    let overrides = overrides(&glob_root(dir), config)?;
+
+    let walker = WalkBuilder::new(dir)
+        .hidden(false)
+        .require_git(false)
+        .follow_links(follow_symlinks)
+        .add_custom_ignore_filename(".topiaryignore")
+        .filter_entry(move |entry| {
+            let is_dir = entry.file_type().is_some_and(|filetype| filetype.is_dir());
+            let path = std::path::absolute(entry.path());
+            entry.file_name() != ".git"
+                && !path.is_ok_and(|path| overrides.matched(path, is_dir).is_ignore())
+        })
+        .build();
+
+    let mut files = vec![];
+
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                log::error!("Skipping {e}");
+                continue;
+            }
+        };
+
+        // The walk itself descends into (followed) directories, including its root
+        if entry.file_type().is_some_and(|filetype| filetype.is_dir()) {
+            continue;
+        }
+
+        // Files found by walking a directory are only of interest if they're in a language we
+        // know. Only those that could be scripts are read, to detect it from their first line.
+        if skip_unknown
+            && config.detect_by_path(entry.path()).is_none()
+            && !(may_be_script(&entry) && config.detect(entry.path()).is_ok())
+        {
+            log::debug!(
+                "Skipping {}: Language cannot be detected",
+                entry.path().display()
+            );
+            continue;
+        }
+
+        let path = entry.into_path();
+
+        files.push(path);
+    }
+
+    Ok(files)
+}
+
+/// Whether the file could be a script, whose language is given by its first line: one with no
+/// extension, or that is executable
+fn may_be_script(entry: &ignore::DirEntry) -> bool {
+    if entry.path().extension().is_none() {
+        return true;
+    }
+
+    #[cfg(unix)]
+    return entry.metadata().is_ok_and(|meta| meta.mode() & 0o111 != 0);
+
+    #[cfg(not(unix))]
+    false
+}
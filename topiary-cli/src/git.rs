@@ -0,0 +1,289 @@
+//! Input selection from the enclosing Git repository, so that only changed files are formatted.
+
+use std::{
+    convert::Infallible,
+    error, fs,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use gix::{
+    ObjectId, Repository, ThreadSafeRepository,
+    bstr::{BStr, BString, ByteSlice},
+    index::{
+        entry::{Stage, Stat},
+        fs::Metadata,
+    },
+    progress::Discard,
+    status::{UntrackedFiles, tree_index::TrackRenames},
+};
+
+use crate::error::{CLIError, CLIResult, TopiaryError};
+
+/// Attach a message to a Git error
+fn git_error<E: error::Error + 'static>(message: &str) -> impl FnOnce(E) -> TopiaryError + '_ {
+    move |e| TopiaryError::Bin(message.into(), Some(CLIError::Generic(Box::new(e))))
+}
+
+#[allow(clippy::result_large_err)]
+fn discover(path: &Path) -> CLIResult<Repository> {
+    gix::discover(path).map_err(git_error("Could not find a Git repository"))
+}
+
+#[allow(clippy::result_large_err)]
+fn workdir(repo: &Repository) -> CLIResult<PathBuf> {
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| TopiaryError::Bin("Git repository has no working tree".into(), None))?;
+
+    Ok(workdir.canonicalize()?)
+}
+
+/// The working tree of the enclosing repository
+#[allow(clippy::result_large_err)]
+pub fn root() -> CLIResult<PathBuf> {
+    workdir(&discover(Path::new("."))?)
+}
+
+/// Convert repository-relative paths into absolute paths, keeping only those which fall within
+/// any of the given filter paths (or all of them, if there are no filters)
+#[allow(clippy::result_large_err)]
+fn resolve(workdir: &Path, paths: Vec<BString>, filters: &[PathBuf]) -> CLIResult<Vec<PathBuf>> {
+    let filters = filters
+        .iter()
+        .map(|filter| filter.canonicalize())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut resolved: Vec<PathBuf> = paths
+        .iter()
+        .filter_map(|path| gix::path::try_from_bstr(path.as_bstr()).ok())
+        .map(|path| workdir.join(path))
+        .filter(|path| filters.is_empty() || filters.iter().any(|f| path.starts_with(f)))
+        .collect();
+
+    resolved.sort_unstable();
+    resolved.dedup();
+
+    Ok(resolved)
+}
+
+/// The files that have changed since the given revision, in either the index or the working
+/// tree, within the given paths. Deleted and untracked files are not included.
+#[allow(clippy::result_large_err)]
+pub fn changed_since(rev: &str, filters: &[PathBuf]) -> CLIResult<Vec<PathBuf>> {
+    let repo = discover(Path::new("."))?;
+    let workdir = workdir(&repo)?;
+
+    let tree = repo
+        .rev_parse_single(rev)
+        .map_err(git_error("Could not resolve Git revision"))?
+        .object()
+        .map_err(git_error("Could not find Git object"))?
+        .peel_to_tree()
+        .map_err(git_error("Git revision does not refer to a tree"))?
+        .id;
+
+    let status = repo
+        .status(Discard)
+        .map_err(git_error("Could not get Git status"))?
+        .untracked_files(UntrackedFiles::None)
+        .head_tree(tree)
+        .into_iter(None)
+        .map_err(git_error("Could not get Git status"))?;
+
+    let mut changed = Vec::new();
+    for item in status {
+        let item = item.map_err(git_error("Could not get Git status"))?;
+        changed.push(item.location().to_owned());
+    }
+
+    // Changes include deletions, which we have no interest in
+    let mut changed = resolve(&workdir, changed, filters)?;
+    changed.retain(|path| path.is_file());
+
+    Ok(changed)
+}
+
+/// The files with changes staged in the index, relative to `HEAD`, within the given paths.
+/// Deleted files are not included.
+#[allow(clippy::result_large_err)]
+pub fn staged(filters: &[PathBuf]) -> CLIResult<Vec<PathBuf>> {
+    let repo = discover(Path::new("."))?;
+    let workdir = workdir(&repo)?;
+
+    let index = repo
+        .index_or_empty()
+        .map_err(git_error("Could not read Git index"))?;
+    let head = repo
+        .head_tree_id_or_empty()
+        .map_err(git_error("Could not resolve Git HEAD"))?;
+
+    let mut staged = Vec::new();
+    repo.tree_index_status(
+        &head,
+        &index,
+        None,
+        TrackRenames::Disabled,
+        |change, _, _| {
+            // Deletions have no index entry to format
+            if !matches!(change, gix::diff::index::ChangeRef::Deletion { .. }) {
+                staged.push(change.location().to_owned());
+            }
+
+            Ok::<_, Infallible>(gix::diff::index::Action::Continue)
+        },
+    )
+    .map_err(git_error("Could not compare Git index with HEAD"))?;
+
+    resolve(&workdir, staged, filters)
+}
+
+/// The Git index, as read once for all of the staged inputs of a run. Staged inputs are read
+/// from it, and their formatted contents are staged in it, in memory; the index is then written
+/// back once, by [Index::write], when all of them are done.
+#[derive(Debug)]
+pub struct Index {
+    // Inputs are formatted concurrently, so each uses its own thread-local handle on this
+    repo: ThreadSafeRepository,
+    workdir: PathBuf,
+    file: Mutex<gix::index::File>,
+    changed: AtomicBool,
+}
+
+impl Index {
+    /// Open the index of the enclosing repository
+    #[allow(clippy::result_large_err)]
+    pub fn open() -> CLIResult<Self> {
+        let repo = discover(Path::new("."))?;
+        let workdir = workdir(&repo)?;
+        let file = repo
+            .open_index()
+            .map_err(git_error("Could not read Git index"))?;
+
+        Ok(Self {
+            repo: repo.into_sync(),
+            workdir,
+            file: Mutex::new(file),
+            changed: AtomicBool::new(false),
+        })
+    }
+
+    /// The repository-relative path of the given (absolute) path
+    #[allow(clippy::result_large_err)]
+    fn relative(&self, path: &Path) -> CLIResult<BString> {
+        let relative = path.strip_prefix(&self.workdir).map_err(|_| {
+            TopiaryError::Bin(
+                format!("{} is not within the Git working tree", path.display()),
+                None,
+            )
+        })?;
+
+        let relative = gix::path::to_unix_separators_on_windows(gix::path::into_bstr(relative));
+        Ok(relative.into_owned())
+    }
+
+    /// The id of the staged blob of the given file
+    #[allow(clippy::result_large_err)]
+    fn staged_id(&self, path: &Path, relative: &BStr) -> CLIResult<ObjectId> {
+        self.file
+            .lock()
+            .unwrap()
+            .entry_by_path(relative)
+            .map(|entry| entry.id)
+            .ok_or_else(|| {
+                TopiaryError::Bin(
+                    format!("{} is not staged in the Git index", path.display()),
+                    None,
+                )
+            })
+    }
+
+    /// Read the staged contents of the given file
+    #[allow(clippy::result_large_err)]
+    pub fn read(&self, path: &Path) -> CLIResult<Vec<u8>> {
+        let id = self.staged_id(path, self.relative(path)?.as_bstr())?;
+
+        let repo = self.repo.to_thread_local();
+        let blob = repo
+            .find_blob(id)
+            .map_err(git_error("Could not read staged Git blob"))?;
+
+        Ok(blob.data.clone())
+    }
+
+    /// Replace the staged contents of the given file. If the file in the working tree matches
+    /// what was staged (i.e., it has no unstaged changes), then it is updated too.
+    #[allow(clippy::result_large_err)]
+    pub fn stage(&self, path: &Path, contents: &[u8]) -> CLIResult<()> {
+        let repo = self.repo.to_thread_local();
+        let relative = self.relative(path)?;
+        let relative: &BStr = relative.as_bstr();
+        let previous = self.staged_id(path, relative)?;
+
+        let id = repo
+            .write_blob(contents)
+            .map_err(git_error("Could not write Git blob"))?
+            .detach();
+
+        if id == previous {
+            return Ok(());
+        }
+
+        let unstaged_changes = fs::read(path).ok()
+            != Some(
+                repo.find_blob(previous)
+                    .map_err(git_error("Could not read staged Git blob"))?
+                    .data
+                    .clone(),
+            );
+
+        // Only update the working tree when that wouldn't lose any unstaged changes
+        let stat = if unstaged_changes {
+            log::warn!(
+                "Not updating {} in the working tree, as it has unstaged changes",
+                path.display()
+            );
+
+            None
+        } else {
+            fs::write(path, contents)?;
+
+            Metadata::from_path_no_follow(path)
+                .ok()
+                .and_then(|metadata| Stat::from_fs(&metadata).ok())
+        };
+
+        let mut file = self.file.lock().unwrap();
+        if let Some(entry) = file.entry_mut_by_path_and_stage(relative, Stage::Unconflicted) {
+            entry.id = id;
+
+            // Keep the index in sync with the working tree, so the file isn't reported as modified
+            if let Some(stat) = stat {
+                entry.stat = stat;
+            }
+
+            self.changed.store(true, Ordering::Relaxed);
+        }
+
+        Ok(())
+    }
+
+    /// Write the index back, if anything has been staged in it
+    #[allow(clippy::result_large_err)]
+    pub fn write(&self) -> CLIResult<()> {
+        if !self.changed.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        self.file
+            .lock()
+            .unwrap()
+            .write(Default::default())
+            .map_err(git_error("Could not write Git index"))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,203 @@
+//! Golden-file testing of queries, as run by `topiary test`. A test directory holds an `input`
+//! directory of inputs, and an `expected` directory with the formatted output of each input, at
+//! the same relative path.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+};
+
+use topiary_config::Configuration;
+use topiary_core::{Operation, formatter_str};
+
+use crate::{
+    diff,
+    error::{CLIError, CLIResult, TopiaryError},
+    fs::traverse,
+    io::{InputFile, InputFrom, Inputs, Overrides, read_input},
+    language::LanguageDefinitionCache,
+};
+
+/// An input and the path of its expected output
+struct Case {
+    /// The input's path, relative to the `input` directory
+    name: PathBuf,
+    input: PathBuf,
+    expected: PathBuf,
+}
+
+/// The outcome of a test case
+enum Outcome {
+    Passed,
+    Blessed,
+    Failed(String),
+}
+
+/// Find each input in the test directory, with its expected output
+#[allow(clippy::result_large_err)]
+fn discover(dir: &Path, config: &Configuration) -> CLIResult<Vec<Case>> {
+    let input_dir = dir.join("input").canonicalize().map_err(|e| {
+        TopiaryError::Bin(
+            format!("Cannot find the input directory of {}", dir.display()),
+            Some(CLIError::IOError(e)),
+        )
+    })?;
+
+    // Every input is a test case, so none are skipped for their language
+    let mut inputs = vec![input_dir.clone()];
+    traverse(&mut inputs, false, config, false)?;
+    inputs.sort_unstable();
+
+    Ok(inputs
+        .into_iter()
+        .filter_map(|input| {
+            let name = input.strip_prefix(&input_dir).ok()?.to_path_buf();
+            let expected = dir.join("expected").join(&name);
+
+            Some(Case {
+                name,
+                input,
+                expected,
+            })
+        })
+        .collect())
+}
+
+/// Format the input of a test case, checking idempotence, and compare it with the expected output
+#[allow(clippy::result_large_err)]
+async fn check(
+    case: &Case,
+    input: CLIResult<InputFile<'_>>,
+    cache: &LanguageDefinitionCache,
+    bless: bool,
+) -> CLIResult<Outcome> {
+    let mut input = input?;
+    let language = cache.fetch(&input).await?;
+    let input_content = read_input(&mut input)?;
+
+    let mut output = Vec::new();
+    let operation = Operation::Format {
+        skip_idempotence: false,
+        tolerate_parsing_errors: false,
+    };
+    formatter_str(&input_content, &mut output, &language, operation)
+        .map_err(|e| e.with_location(input.source().to_string()))?;
+
+    // The formatter only ever emits valid UTF-8, given valid UTF-8 input
+    let formatted = String::from_utf8_lossy(&output);
+
+    let expected = match fs::read_to_string(&case.expected) {
+        Ok(expected) => Some(expected),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    if expected.as_deref() == Some(&formatted) {
+        return Ok(Outcome::Passed);
+    }
+
+    if bless {
+        if let Some(parent) = case.expected.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&case.expected, formatted.as_bytes())?;
+
+        return Ok(Outcome::Blessed);
+    }
+
+    Ok(Outcome::Failed(match expected {
+        Some(expected) => diff::unified(
+            &expected,
+            &formatted,
+            &case.expected.display().to_string(),
+            "formatted",
+            3,
+        ),
+        None => "No expected output; run with --bless to create it\n".into(),
+    }))
+}
+
+/// Run each test case in the test directory, printing its outcome, and fail if any of them fail.
+/// When blessing, the expected output of those that would fail on their output is rewritten.
+#[allow(clippy::result_large_err)]
+pub async fn run(config: &Configuration, dir: &Path, bless: bool) -> CLIResult<()> {
+    let cases = discover(dir, config)?;
+    let cache = LanguageDefinitionCache::new();
+
+    let mut stdout = io::stdout().lock();
+    let colour = stdout.is_terminal() && std::env::var_os("NO_COLOR").is_none();
+
+    // Inputs are matched with their cases by their location, regardless of the order they're
+    // given out in
+    let files = cases.iter().map(|case| case.input.clone()).collect();
+    let mut inputs = Inputs::new(config, InputFrom::Files(files, Overrides::default()));
+    let mut located = HashMap::new();
+    while let Some((location, input)) = inputs.next_located() {
+        if let Some(path) = location.path() {
+            located.insert(path.to_path_buf(), input);
+        }
+    }
+
+    let (mut passed, mut blessed, mut failed) = (0, 0, 0);
+    for case in &cases {
+        let input = located.remove(&case.input).unwrap_or_else(|| {
+            Err(TopiaryError::Bin(
+                format!("No input was resolved for {}", case.input.display()),
+                Some(CLIError::Failed),
+            ))
+        });
+
+        let outcome = check(case, input, &cache, bless).await.unwrap_or_else(|e| {
+            let mut message = format!("{e}\n");
+            if let Some(source) = std::error::Error::source(&e) {
+                message.push_str(&format!("Cause: {source}\n"));
+            }
+
+            Outcome::Failed(message)
+        });
+
+        let name = case.name.display();
+        match outcome {
+            Outcome::Passed => {
+                passed += 1;
+                writeln!(stdout, "test {name} ... ok")?;
+            }
+
+            Outcome::Blessed => {
+                blessed += 1;
+                writeln!(stdout, "test {name} ... blessed")?;
+            }
+
+            Outcome::Failed(details) => {
+                failed += 1;
+                writeln!(stdout, "test {name} ... FAILED")?;
+                if colour {
+                    write!(stdout, "{}", diff::colourise(&details))?;
+                } else {
+                    write!(stdout, "{details}")?;
+                }
+            }
+        }
+    }
+
+    let result = if failed == 0 { "ok" } else { "FAILED" };
+    write!(
+        stdout,
+        "\ntest result: {result}. {passed} passed; {failed} failed"
+    )?;
+    if bless {
+        write!(stdout, "; {blessed} blessed")?;
+    }
+    writeln!(stdout)?;
+
+    if failed > 0 {
+        return Err(TopiaryError::Bin(
+            "Some tests failed".into(),
+            Some(CLIError::Failed),
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,140 @@
+//! Management of the cache of compiled grammars, as done by `topiary grammars`. Each revision of a
+//! grammar is compiled to its own library, so libraries are left behind when revisions change.
+
+use tabled::{Table, settings::Style};
+use topiary_config::{
+    Configuration,
+    cache::{self, CachedGrammar},
+};
+
+use crate::error::{CLIError, CLIResult, TopiaryError};
+
+/// A size in bytes, in the largest binary unit that keeps it at least one
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+fn bool_emoji(b: bool) -> &'static str {
+    if b {
+        "\u{2705}" // Check Mark
+    } else {
+        "\u{274C}" // Cross Mark
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn cached_grammars() -> CLIResult<Vec<CachedGrammar>> {
+    let grammars = cache::cached_grammars()?;
+    if grammars.is_empty() {
+        println!("No grammars in {}", cache::cache_dir().display());
+    }
+
+    Ok(grammars)
+}
+
+/// Print a table of the cached grammars, with whether the configuration uses each of them
+#[allow(clippy::result_large_err)]
+pub fn list(config: &Configuration) -> CLIResult<()> {
+    let grammars = cached_grammars()?;
+    if grammars.is_empty() {
+        return Ok(());
+    }
+
+    let rows: Vec<_> = grammars
+        .iter()
+        .map(|grammar| {
+            (
+                grammar.language.clone(),
+                grammar.revision.clone(),
+                human_size(grammar.size),
+                bool_emoji(grammar.is_used_by(config)),
+                grammar.path.display().to_string(),
+            )
+        })
+        .collect();
+
+    let mut table = Table::builder(rows);
+    table.remove_record(0);
+    table.insert_record(0, ["language", "revision", "size", "used", "path"]);
+    println!("{}", table.build().with(Style::modern_rounded()));
+
+    Ok(())
+}
+
+/// Remove the cached grammars that the configuration does not use or, on a dry run, only list them
+#[allow(clippy::result_large_err)]
+pub fn clean(config: &Configuration, dry_run: bool) -> CLIResult<()> {
+    let grammars = cached_grammars()?;
+    if grammars.is_empty() {
+        return Ok(());
+    }
+
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+
+    let mut removed = 0;
+    let mut freed = 0;
+    for grammar in grammars
+        .iter()
+        .filter(|grammar| !grammar.is_used_by(config))
+    {
+        if !dry_run {
+            grammar.remove()?;
+        }
+        println!("{verb} {}", grammar.path.display());
+
+        removed += 1;
+        freed += grammar.size;
+    }
+
+    println!(
+        "{verb} {removed} unused grammar{}, freeing {}",
+        if removed == 1 { "" } else { "s" },
+        human_size(freed)
+    );
+
+    Ok(())
+}
+
+/// Load each cached grammar through its expected symbol; failing if any can't be
+#[allow(clippy::result_large_err)]
+pub fn verify(config: &Configuration) -> CLIResult<()> {
+    let grammars = cached_grammars()?;
+
+    let mut failed = 0;
+    for grammar in &grammars {
+        let symbol = grammar.symbol(config);
+
+        match grammar.load(config) {
+            Ok(_) => println!("{} ... ok ({symbol})", grammar.path.display()),
+            Err(e) => {
+                failed += 1;
+                println!("{} ... FAILED ({symbol}): {e}", grammar.path.display());
+            }
+        }
+    }
+
+    if failed == 0 {
+        return Ok(());
+    }
+
+    Err(TopiaryError::Bin(
+        format!(
+            "{failed} of {} grammars could not be loaded",
+            grammars.len()
+        ),
+        Some(CLIError::Failed),
+    ))
+}
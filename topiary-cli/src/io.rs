@@ -3,7 +3,7 @@ use std::{
     fmt::{self, Display},
     fs::File,
     io::{self, BufWriter, Read, Result, Seek, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::Arc,
 };
 
@@ -13,9 +13,11 @@ use topiary_config::Configuration;
 use topiary_core::{Language, Operation, TopiaryQuery, formatter};
 
 use crate::{
-    cli::{AtLeastOneInput, ExactlyOneInput, FromStdin},
+    cli::{AtLeastOneInput, ExactlyOneInput, LanguageOverride},
     error::{CLIError, CLIResult, TopiaryError, print_error},
+    git,
     language::LanguageDefinitionCache,
+    report::{Report, Status},
 };
 
 #[derive(Debug, Clone, Hash)]
@@ -52,7 +54,7 @@ impl Display for QuerySource {
 }
 
 impl QuerySource {
-    async fn get_content(&self) -> CLIResult<String> {
+    pub(crate) async fn get_content(&self) -> CLIResult<String> {
         let contents = match self {
             Self::Path(query) => tokio::fs::read_to_string(query).await?,
             Self::BuiltIn(contents) => contents.to_owned(),
@@ -61,30 +63,93 @@ impl QuerySource {
     }
 }
 
+/// Language and query overrides, which take precedence over detection from an input's path
+#[derive(Default)]
+pub struct Overrides {
+    language: Option<String>,
+    query: Option<QuerySource>,
+}
+
+impl From<&LanguageOverride> for Overrides {
+    fn from(overrides: &LanguageOverride) -> Self {
+        Self {
+            language: overrides.language.to_owned(),
+            query: overrides.query.as_ref().map(|p| p.into()),
+        }
+    }
+}
+
+impl Overrides {
+    /// Overrides of the language and, from a file, its query
+    pub fn new(language: Option<String>, query: Option<PathBuf>) -> Self {
+        Self {
+            language,
+            query: query.map(QuerySource::Path),
+        }
+    }
+
+    /// Resolve the language and query of an input, using the overrides where given and otherwise
+    /// detecting them from its path and, if given, the first line of its contents
+    #[allow(clippy::result_large_err)]
+    fn resolve<'cfg>(
+        &self,
+        config: &'cfg Configuration,
+        path: Option<&PathBuf>,
+        first_line: Option<&str>,
+    ) -> CLIResult<(&'cfg topiary_config::language::Language, QuerySource)> {
+        let language = match (&self.language, path, first_line) {
+            (Some(name), _, _) => config.get_language(name)?,
+            (None, Some(path), Some(line)) => config.detect_with_first_line(path, line)?,
+            (None, Some(path), None) => config.detect(path)?,
+            (None, None, _) => {
+                unreachable!("Clap guarantees a language or path for standard input")
+            }
+        };
+
+        let query = match &self.query {
+            // The user specified a query file
+            Some(query) => query.clone(),
+            // The user did not specify a file, try the default locations
+            None => to_query_from_language(language)?,
+        };
+
+        Ok((language, query))
+    }
+}
+
 /// Unified interface for input sources. We either have input from:
-/// * Standard input, in which case we need to specify the language and, optionally, query override
+/// * Standard input, in which case we need to specify the language or a path to detect it from;
+///   along with its contents, if they have already been read to detect its language from
 /// * A sequence of files
+/// * A sequence of files selected from Git, optionally read from its index
+///
+/// Standard input and files can have their language and query overridden; files selected from Git
+/// cannot, as each is detected from its path (which the CLI parser enforces).
 ///
 /// These are captured by the CLI parser, with `cli::AtLeastOneInput` and `cli::ExactlyOneInput`.
 /// We use this struct to normalise the interface for downstream (using `From` implementations).
 pub enum InputFrom {
-    Stdin(String, Option<QuerySource>),
-    Files(Vec<PathBuf>),
+    Stdin(Option<PathBuf>, Overrides, Option<Arc<[u8]>>),
+    Files(Vec<PathBuf>, Overrides),
+    Git { files: Vec<PathBuf>, staged: bool },
 }
 
 impl From<&ExactlyOneInput> for InputFrom {
     fn from(input: &ExactlyOneInput) -> Self {
         match input {
             ExactlyOneInput {
-                stdin: Some(FromStdin { language, query }),
-                ..
-            } => InputFrom::Stdin(language.to_owned(), query.as_ref().map(|p| p.into())),
+                overrides,
+                file: Some(path),
+            } => InputFrom::Files(vec![path.to_owned()], overrides.into()),
 
             ExactlyOneInput {
-                file: Some(path), ..
-            } => InputFrom::Files(vec![path.to_owned()]),
-
-            _ => unreachable!("Clap guarantees input is always one of the above"),
+                overrides,
+                file: None,
+            } => InputFrom::Stdin(
+                overrides.stdin_filepath.to_owned(),
+                overrides.into(),
+                overrides.stdin_contents.clone(),
+            ),
         }
     }
 }
@@ -93,37 +158,58 @@ impl From<&AtLeastOneInput> for InputFrom {
     fn from(input: &AtLeastOneInput) -> Self {
         match input {
             AtLeastOneInput {
-                stdin: Some(FromStdin { language, query }),
+                files,
+                changed_since,
+                staged,
                 ..
-            } => InputFrom::Stdin(language.to_owned(), query.as_ref().map(|p| p.into())),
+            } if changed_since.is_some() || *staged => InputFrom::Git {
+                files: files.to_owned(),
+                staged: *staged,
+            },
+
+            AtLeastOneInput {
+                overrides,
+                stdin: true,
+                ..
+            } => InputFrom::Stdin(
+                overrides.stdin_filepath.to_owned(),
+                overrides.into(),
+                overrides.stdin_contents.clone(),
+            ),
+
+            AtLeastOneInput {
+                overrides, files, ..
+            } => InputFrom::Files(files.to_owned(), overrides.into()),
+        }
+    }
+}
 
-            AtLeastOneInput { files, .. } => InputFrom::Files(files.to_owned()),
+impl InputFrom {
+    /// Use the given query, rather than the language's, for inputs from standard input or files
+    pub fn with_query(mut self, query: QuerySource) -> Self {
+        if let InputFrom::Stdin(_, overrides, _) | InputFrom::Files(_, overrides) = &mut self {
+            overrides.query = Some(query);
         }
+
+        self
     }
 }
 
-/// Each `InputFile` needs to locate its source (standard input or disk), such that its `io::Read`
-/// implementation can do the right thing.
+/// Each `InputFile` needs to locate its source (standard input, disk or the Git index), such that
+/// its `io::Read` implementation can do the right thing.
 #[derive(Debug)]
 pub enum InputSource {
-    Stdin,
+    /// Standard input, which is already read if its contents were needed to detect its language
+    Stdin(Option<io::Cursor<Arc<[u8]>>>),
     Disk(Arc<PathBuf>, Option<File>),
-}
-
-impl InputSource {
-    pub fn location(&self) -> InputLocation {
-        match self {
-            InputSource::Stdin => InputLocation(None),
-            InputSource::Disk(path, _) => InputLocation(Some(path.clone())),
-        }
-    }
+    Staged(Arc<PathBuf>, io::Cursor<Vec<u8>>, Arc<git::Index>),
 }
 
 impl fmt::Display for InputSource {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Stdin => write!(f, "standard input"),
-            Self::Disk(path, _) => write!(f, "{}", path.display()),
+            Self::Stdin(_) => write!(f, "standard input"),
+            Self::Disk(path, _) | Self::Staged(path, _, _) => write!(f, "{}", path.display()),
         }
     }
 }
@@ -132,6 +218,13 @@ impl fmt::Display for InputSource {
 #[derive(Debug)]
 pub struct InputLocation(Option<Arc<PathBuf>>);
 
+impl InputLocation {
+    /// The path of the input, or `None` for standard input
+    pub fn path(&self) -> Option<&Path> {
+        self.0.as_deref().map(PathBuf::as_path)
+    }
+}
+
 impl fmt::Display for InputLocation {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self.0 {
@@ -150,7 +243,47 @@ pub struct InputFile<'cfg> {
     pub(crate) query: QuerySource,
 }
 
-impl InputFile<'_> {
+impl<'cfg> InputFile<'cfg> {
+    /// Create an `InputFile` of the given language, using its default query
+    #[allow(clippy::result_large_err)]
+    pub fn new(
+        source: InputSource,
+        language: &'cfg topiary_config::language::Language,
+    ) -> CLIResult<Self> {
+        let query = to_query_from_language(language)?;
+
+        Ok(Self {
+            source,
+            language,
+            query,
+        })
+    }
+
+    /// Create an `InputFile` from standard input, with its language and query resolved from the
+    /// overrides, or detected from its path. Detection can fall back to the first line of the
+    /// input, which must be that of its contents, if they have been read, rather than of the file
+    /// at its path.
+    #[allow(clippy::result_large_err)]
+    pub fn stdin(
+        config: &'cfg Configuration,
+        path: Option<&PathBuf>,
+        overrides: &Overrides,
+        contents: Option<Arc<[u8]>>,
+    ) -> CLIResult<Self> {
+        let first_line = contents.as_deref().map(|contents| {
+            let line = contents.split(|&b| b == b'\n').next().unwrap_or_default();
+            String::from_utf8_lossy(line).into_owned()
+        });
+
+        let (language, query) = overrides.resolve(config, path, first_line.as_deref())?;
+
+        Ok(Self {
+            source: InputSource::Stdin(contents.map(io::Cursor::new)),
+            language,
+            query,
+        })
+    }
+
     /// Convert our `InputFile` into language definition values that Topiary can consume
     #[allow(clippy::result_large_err)]
     pub async fn to_language(&self) -> CLIResult<Language> {
@@ -211,7 +344,8 @@ pub(crate) fn read_input(input: &mut dyn io::Read) -> Result<String> {
 impl Read for InputFile<'_> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         match &mut self.source {
-            InputSource::Stdin => io::stdin().lock().read(buf),
+            InputSource::Stdin(Some(buffered)) => buffered.read(buf),
+            InputSource::Stdin(None) => io::stdin().lock().read(buf),
 
             InputSource::Disk(path, fd) => {
                 if fd.is_none() {
@@ -220,60 +354,122 @@ impl Read for InputFile<'_> {
 
                 fd.as_mut().unwrap().read(buf)
             }
+
+            InputSource::Staged(_, contents, _) => contents.read(buf),
         }
     }
 }
 
 /// `Inputs` is an iterator of fully qualified `InputFile`s, each wrapped in `CLIResult`, which is
-/// populated by its constructor from any type that implements `Into<InputFrom>`
+/// populated by its constructor from any type that implements `Into<InputFrom>`. Each is paired
+/// with its location, so that failures to resolve an input can still be attributed to it.
 #[allow(clippy::result_large_err)]
-pub struct Inputs<'cfg>(Vec<CLIResult<InputFile<'cfg>>>);
+pub struct Inputs<'cfg> {
+    inputs: Vec<(InputLocation, CLIResult<InputFile<'cfg>>)>,
+    /// The Git index that staged inputs are read from, to be written back once they're processed
+    index: Option<Arc<git::Index>>,
+}
 
-impl<'cfg, 'i> Inputs<'cfg> {
-    pub fn new<T>(config: &'cfg Configuration, inputs: &'i T) -> Self
+impl<'cfg> Inputs<'cfg> {
+    pub fn new<T>(config: &'cfg Configuration, inputs: T) -> Self
     where
-        &'i T: Into<InputFrom>,
+        T: Into<InputFrom>,
     {
+        let on_disk = |path: &PathBuf| InputLocation(Some(Arc::new(path.clone())));
+        let mut index = None;
+
         let inputs = match inputs.into() {
-            InputFrom::Stdin(language_name, query) => {
-                vec![(|| {
-                    let language = config.get_language(&language_name)?;
-                    let query_source: QuerySource = match query {
-                        // The user specified a query file
-                        Some(p) => p,
-                        // The user did not specify a file, try the default locations
-                        None => to_query_from_language(language)?,
-                    };
-
-                    Ok(InputFile {
-                        source: InputSource::Stdin,
-                        language,
-                        query: query_source,
-                    })
-                })()]
+            InputFrom::Stdin(path, overrides, contents) => {
+                let input = InputFile::stdin(config, path.as_ref(), &overrides, contents);
+                vec![(InputLocation(None), input)]
             }
 
-            InputFrom::Files(files) => files
+            InputFrom::Files(files, overrides) => files
                 .into_iter()
                 .map(|path| {
-                    let language = config.detect(&path)?;
-                    let query: QuerySource = to_query_from_language(language)?;
-
-                    Ok(InputFile {
-                        source: InputSource::Disk(path.into(), None),
-                        language,
-                        query,
-                    })
+                    let location = on_disk(&path);
+                    let input =
+                        overrides
+                            .resolve(config, Some(&path), None)
+                            .map(|(language, query)| InputFile {
+                                source: InputSource::Disk(path.into(), None),
+                                language,
+                                query,
+                            });
+
+                    (location, input)
                 })
                 .collect(),
+
+            // Files selected from Git may well be in languages that Topiary doesn't know about,
+            // which we skip, rather than fail on
+            InputFrom::Git { files, staged } => {
+                let files: Vec<_> = files
+                    .into_iter()
+                    .filter_map(|path| match config.detect(&path) {
+                        Ok(language) => Some((path, language)),
+                        Err(e) => {
+                            log::info!("Skipping {}: {e}", path.display());
+                            None
+                        }
+                    })
+                    .collect();
+
+                // The index is read once for all of the staged inputs
+                let opened = (staged && !files.is_empty()).then(git::Index::open);
+                let opened = match opened.transpose() {
+                    Ok(opened) => opened.map(Arc::new),
+                    Err(e) => {
+                        let message = format!("{e}");
+                        return Self {
+                            inputs: files
+                                .iter()
+                                .map(|(path, _)| {
+                                    (on_disk(path), Err(TopiaryError::Bin(message.clone(), None)))
+                                })
+                                .collect(),
+                            index: None,
+                        };
+                    }
+                };
+                index.clone_from(&opened);
+
+                files
+                    .into_iter()
+                    .map(|(path, language)| {
+                        let location = on_disk(&path);
+                        let input = match &opened {
+                            Some(index) => index.read(&path).and_then(|contents| {
+                                let source = InputSource::Staged(
+                                    path.into(),
+                                    io::Cursor::new(contents),
+                                    index.clone(),
+                                );
+                                InputFile::new(source, language)
+                            }),
+                            None => InputFile::new(InputSource::Disk(path.into(), None), language),
+                        };
+
+                        (location, input)
+                    })
+                    .collect()
+            }
         };
 
-        Self(inputs)
+        Self { inputs, index }
+    }
+
+    /// The next input, paired with its location
+    #[allow(clippy::result_large_err)]
+    pub fn next_located(&mut self) -> Option<(InputLocation, CLIResult<InputFile<'cfg>>)> {
+        self.inputs.pop()
     }
 }
 
 #[allow(clippy::result_large_err)]
-fn to_query_from_language(language: &topiary_config::language::Language) -> CLIResult<QuerySource> {
+pub(crate) fn to_query_from_language(
+    language: &topiary_config::language::Language,
+) -> CLIResult<QuerySource> {
     let query: QuerySource = match language.find_query_file() {
         Ok(p) => p.into(),
         // For some reason, Topiary could not find any
@@ -295,7 +491,7 @@ impl<'cfg> Iterator for Inputs<'cfg> {
     type Item = CLIResult<InputFile<'cfg>>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.0.pop()
+        self.next_located().map(|(_, input)| input)
     }
 }
 
@@ -303,7 +499,8 @@ impl<'cfg> Iterator for Inputs<'cfg> {
 /// disk (which uses temporary files to perform atomic updates in place). It implements
 /// `io::Write`, so it can be passed directly to the Topiary API.
 ///
-/// NOTE When writing to disk, the `persist` function must be called to perform the in place write.
+/// NOTE When writing to disk, or the Git index, the `persist` function must be called to perform
+/// the in place write.
 #[derive(Debug)]
 pub enum OutputFile {
     Stdout,
@@ -313,6 +510,12 @@ pub enum OutputFile {
         staged: File,
         output: OsString,
     },
+    Staged {
+        // NOTE The index is updated all at once, so we can stage to memory
+        staged: Vec<u8>,
+        output: Arc<PathBuf>,
+        index: Arc<git::Index>,
+    },
 }
 
 impl OutputFile {
@@ -330,16 +533,34 @@ impl OutputFile {
     // This function must be called to persist the output to disk
     #[allow(clippy::result_large_err)]
     pub fn persist(self) -> CLIResult<()> {
-        if let Self::Disk { mut staged, output } = self {
-            // Rewind to the beginning of the staged output
-            staged.flush()?;
-            staged.rewind()?;
+        match self {
+            Self::Stdout => {}
+
+            Self::Disk { mut staged, output } => {
+                // Rewind to the beginning of the staged output
+                staged.flush()?;
+                staged.rewind()?;
+
+                // Open the actual output for writing and copy the staged contents
+                let mut writer = File::create(&output)?;
+                let bytes = io::copy(&mut staged, &mut writer)?;
 
-            // Open the actual output for writing and copy the staged contents
-            let mut writer = File::create(&output)?;
-            let bytes = io::copy(&mut staged, &mut writer)?;
+                log::debug!("Wrote {bytes} bytes to {}", &output.display());
+            }
 
-            log::debug!("Wrote {bytes} bytes to {}", &output.display());
+            Self::Staged {
+                staged,
+                output,
+                index,
+            } => {
+                index.stage(&output, &staged)?;
+
+                log::debug!(
+                    "Wrote {} bytes to the Git index for {}",
+                    staged.len(),
+                    output.display()
+                );
+            }
         }
 
         Ok(())
@@ -351,6 +572,7 @@ impl fmt::Display for OutputFile {
         match self {
             Self::Stdout => write!(f, "standard output"),
             Self::Disk { output, .. } => write!(f, "{}", output.display()),
+            Self::Staged { output, .. } => write!(f, "{} (in the Git index)", output.display()),
         }
     }
 }
@@ -360,6 +582,7 @@ impl Write for OutputFile {
         match self {
             Self::Stdout => io::stdout().lock().write(buf),
             Self::Disk { staged, .. } => staged.write(buf),
+            Self::Staged { staged, .. } => staged.write(buf),
         }
     }
 
@@ -367,6 +590,7 @@ impl Write for OutputFile {
         match self {
             Self::Stdout => io::stdout().lock().flush(),
             Self::Disk { staged, .. } => staged.flush(),
+            Self::Staged { .. } => Ok(()),
         }
     }
 }
@@ -374,14 +598,20 @@ impl Write for OutputFile {
 // Convenience conversion:
 // * stdin maps to stdout
 // * Files map to themselves (i.e., for in-place updates)
+// * Staged files map to the Git index (and, when it has no unstaged changes, the working tree)
 impl TryFrom<&InputFile<'_>> for OutputFile {
     type Error = TopiaryError;
 
     #[allow(clippy::result_large_err)]
     fn try_from(input: &InputFile) -> CLIResult<Self> {
         match &input.source {
-            InputSource::Stdin => Ok(Self::Stdout),
+            InputSource::Stdin(_) => Ok(Self::Stdout),
             InputSource::Disk(path, _) => Self::new(path.to_string_lossy().as_ref()),
+            InputSource::Staged(path, _, index) => Ok(Self::Staged {
+                staged: Vec::new(),
+                output: path.clone(),
+                index: index.clone(),
+            }),
         }
     }
 }
@@ -458,39 +688,66 @@ pub(crate) async fn format_config(config: &Configuration, nickel_term: &RichTerm
     Ok(())
 }
 
-// meant to be used in scenarios where multiple inputs are possible
-pub(crate) async fn process_inputs<F>(inputs: Inputs<'_>, process_fn: F) -> CLIResult<()>
+// meant to be used in scenarios where multiple inputs are possible; when given a report, each
+// input's result is recorded in it
+pub(crate) async fn process_inputs<F>(
+    mut inputs: Inputs<'_>,
+    cache: &LanguageDefinitionCache,
+    report: Option<&Report>,
+    process_fn: &F,
+) -> CLIResult<()>
 where
-    F: Fn(InputFile, Arc<Language>) -> CLIResult<()> + Send + Sync + 'static,
+    F: Fn(InputFile, Arc<Language>) -> CLIResult<Status> + Send + Sync,
 {
-    let cache = LanguageDefinitionCache::new();
-    let (_, mut results) = async_scoped::TokioScope::scope_and_block(|scope| {
-        for input in inputs {
-            scope.spawn(async {
-                // This happens when the input resolver cannot establish an input
-                // source, language or query file.
-                let input = input?;
-                let location = input.source().location();
-                let language = cache.fetch(&input).await?;
-                process_fn(input, language).map_err(|e| {
-                    let TopiaryError::Lib(fmt_err) = e else {
-                        return e;
-                    };
-                    fmt_err.with_location(location.to_string()).into()
-                })
+    let index = inputs.index.take();
+    let (_, results) = async_scoped::TokioScope::scope_and_block(|scope| {
+        while let Some((location, input)) = inputs.next_located() {
+            scope.spawn(async move {
+                let result = async {
+                    // This happens when the input resolver cannot establish an input
+                    // source, language or query file.
+                    let input = input?;
+                    let language = cache.fetch(&input).await?;
+                    process_fn(input, language).map_err(|e| {
+                        let TopiaryError::Lib(fmt_err) = e else {
+                            return e;
+                        };
+                        fmt_err.with_location(location.to_string()).into()
+                    })
+                }
+                .await;
+
+                (location, result)
             });
         }
     });
 
+    // Whatever was staged is written back to the Git index at once
+    if let Some(index) = index {
+        index.write()?;
+    }
+
+    let mut results: Vec<CLIResult<()>> = results
+        .into_iter()
+        .map(|joined| {
+            let (location, result) = joined?;
+            if let Some(report) = report {
+                report.add(&location, &result);
+            }
+
+            result.map(|_| ())
+        })
+        .collect();
+
     if results.len() == 1 {
         // If we just had one input, then handle errors as normal
-        return results.swap_remove(0)?;
+        return results.swap_remove(0);
     }
 
     // use `.count()` here to ensure eager evaluation of iterator
     let errs = results
         .into_iter()
-        .filter_map(|r| r.map_err(TopiaryError::from).flatten().err())
+        .filter_map(|r| r.err())
         .inspect(|e| print_error(&e))
         .count();
     if errs > 0 {
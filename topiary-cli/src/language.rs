@@ -4,16 +4,21 @@ use std::{
         hash_map::{DefaultHasher, Entry},
     },
     hash::{Hash, Hasher},
+    path::Path,
     sync::Arc,
 };
 
 use tokio::sync::Mutex;
 use topiary_core::Language;
 
-use crate::{error::CLIResult, io::InputFile};
+use crate::{
+    error::CLIResult,
+    io::{InputFile, QuerySource},
+};
 
-/// Thread-safe language definition cache
-pub struct LanguageDefinitionCache(Mutex<HashMap<u64, Arc<Language>>>);
+/// Thread-safe language definition cache, with each entry's query source kept alongside it, so
+/// that it can be invalidated when its query file changes
+pub struct LanguageDefinitionCache(Mutex<HashMap<u64, (QuerySource, Arc<Language>)>>);
 
 impl LanguageDefinitionCache {
     pub fn new() -> Self {
@@ -47,7 +52,7 @@ impl LanguageDefinitionCache {
                     input.query()
                 );
 
-                lang_def.get().to_owned()
+                lang_def.get().1.to_owned()
             }
 
             // ...otherwise, fetch the language definition, to populate the cache
@@ -61,8 +66,27 @@ impl LanguageDefinitionCache {
                 );
 
                 let lang_def = Arc::new(input.to_language().await?);
-                slot.insert(lang_def).to_owned()
+                slot.insert((input.query().clone(), lang_def)).1.to_owned()
             }
         })
     }
+
+    /// Remove the language definitions that use the given query file, so that they are rebuilt
+    /// from its new contents when next fetched
+    pub async fn invalidate(&self, query: &Path) {
+        let query = query.canonicalize().unwrap_or_else(|_| query.to_path_buf());
+
+        self.0.lock().await.retain(|key, (source, _)| {
+            let QuerySource::Path(path) = source else {
+                return true;
+            };
+
+            let stale = path.canonicalize().is_ok_and(|path| path == query);
+            if stale {
+                log::debug!("Cache {:p}: Invalidate {:#016x} ({})", self, key, source);
+            }
+
+            !stale
+        });
+    }
 }
@@ -0,0 +1,335 @@
+//! A language server, over standard input and output, so editors can format documents without
+//! reloading the configuration and grammars on every request.
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::{Arc, Mutex},
+};
+
+use tower_lsp::{
+    Client, LanguageServer, LspService, Server,
+    jsonrpc::{Error, ErrorCode, Result},
+    lsp_types::{
+        Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+        DidOpenTextDocumentParams, DocumentFormattingParams, DocumentRangeFormattingParams,
+        InitializeParams, InitializeResult, InitializedParams, MessageType, OneOf, Position, Range,
+        ServerCapabilities, ServerInfo, TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit,
+        Url,
+    },
+};
+
+use topiary_config::Configuration;
+use topiary_core::{
+    FormatterError, InputRange, Language, Operation, formatter_range, formatter_str,
+};
+
+use crate::{
+    error::CLIResult,
+    io::{InputFile, InputSource},
+    language::LanguageDefinitionCache,
+};
+
+/// An open document, as last synchronised by the client
+#[derive(Clone)]
+struct Document {
+    text: String,
+    language_id: String,
+}
+
+struct Backend {
+    client: Client,
+    config: Configuration,
+    cache: LanguageDefinitionCache,
+
+    // NOTE This lock is never held over an await point, so that documents are stored as soon as
+    // their notification is handled, before any subsequent request can look them up
+    documents: Mutex<HashMap<Url, Document>>,
+}
+
+impl Backend {
+    /// Resolve the language of a document from its path, falling back to the client's language
+    /// identifier (e.g., for unsaved documents), and fetch its definition from the cache
+    async fn language(&self, uri: &Url, language_id: &str) -> CLIResult<Arc<Language>> {
+        let path = uri.to_file_path().ok();
+
+        let language = match path.as_ref().map(|path| self.config.detect(path)) {
+            Some(Ok(language)) => language,
+            _ => self.config.get_language(language_id)?,
+        };
+
+        let source = match path {
+            Some(path) => InputSource::Disk(path.into(), None),
+            None => InputSource::Stdin(None),
+        };
+
+        self.cache.fetch(&InputFile::new(source, language)?).await
+    }
+
+    fn document(&self, uri: &Url) -> Option<Document> {
+        self.documents.lock().unwrap().get(uri).cloned()
+    }
+
+    /// Publish the document's parsing errors, if any, as diagnostics
+    async fn diagnose(&self, uri: Url, document: Document, version: i32) {
+        let language = match self.language(&uri, &document.language_id).await {
+            Ok(language) => language,
+            Err(error) => {
+                log::info!("Not diagnosing {uri}: {error}");
+                return;
+            }
+        };
+
+        let diagnostics = match topiary_core::parse(&document.text, &language.grammar, false) {
+            Err(FormatterError::Parsing(span)) => vec![Diagnostic {
+                range: Range::new(
+                    to_position(&document.text, span.start_byte() as usize),
+                    to_position(&document.text, span.end_byte() as usize),
+                ),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("topiary".into()),
+                message: format!("Could not parse input as {}", language.name),
+                ..Default::default()
+            }],
+
+            _ => Vec::new(),
+        };
+
+        self.client
+            .publish_diagnostics(uri, diagnostics, Some(version))
+            .await;
+    }
+
+    /// Format the document, or the given range within it, returning the edit to apply
+    async fn format(&self, uri: &Url, range: Option<Range>) -> Result<Option<Vec<TextEdit>>> {
+        let Some(document) = self.document(uri) else {
+            return Err(Error::invalid_params(format!("Unknown document: {uri}")));
+        };
+
+        let range = match range {
+            Some(range) => {
+                let start = to_offset(&document.text, range.start);
+                let end = to_offset(&document.text, range.end);
+                Some(InputRange::Bytes(start..end.max(start)))
+            }
+
+            None => None,
+        };
+
+        let language = self
+            .language(uri, &document.language_id)
+            .await
+            .map_err(internal_error)?;
+
+        log::info!("Formatting {uri}, as {}", language.name);
+
+        let mut output = Vec::new();
+        let operation = Operation::Format {
+            skip_idempotence: false,
+            tolerate_parsing_errors: false,
+        };
+
+        // NOTE FormatterError is not Send, so it mustn't be held over the await below
+        let formatted = match &range {
+            Some(range) => {
+                formatter_range(&document.text, &mut output, &language, operation, range)
+            }
+            None => formatter_str(&document.text, &mut output, &language, operation),
+        }
+        .map_err(|error| match error {
+            // Parsing errors are already reported as diagnostics, so we don't fail the request
+            FormatterError::Parsing(_) => None,
+            error => Some(error.to_string()),
+        });
+
+        let message = match formatted {
+            Ok(()) => {
+                return Ok(Some(minimal_edit(
+                    &document.text,
+                    &String::from_utf8_lossy(&output),
+                )));
+            }
+
+            Err(None) => return Ok(None),
+            Err(Some(message)) => message,
+        };
+
+        self.client.show_message(MessageType::ERROR, &message).await;
+
+        Err(internal_error(message))
+    }
+}
+
+#[tower_lsp::async_trait]
+impl LanguageServer for Backend {
+    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+        Ok(InitializeResult {
+            capabilities: ServerCapabilities {
+                text_document_sync: Some(TextDocumentSyncCapability::Kind(
+                    TextDocumentSyncKind::FULL,
+                )),
+                document_formatting_provider: Some(OneOf::Left(true)),
+                document_range_formatting_provider: Some(OneOf::Left(true)),
+                ..Default::default()
+            },
+            server_info: Some(ServerInfo {
+                name: env!("CARGO_PKG_NAME").into(),
+                version: Some(env!("CARGO_PKG_VERSION").into()),
+            }),
+        })
+    }
+
+    async fn initialized(&self, _: InitializedParams) {
+        log::info!("Language server initialised");
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        let document = Document {
+            text: params.text_document.text,
+            language_id: params.text_document.language_id,
+        };
+
+        let uri = params.text_document.uri;
+        self.documents
+            .lock()
+            .unwrap()
+            .insert(uri.clone(), document.clone());
+
+        self.diagnose(uri, document, params.text_document.version)
+            .await;
+    }
+
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        // We only advertise full synchronisation, so the last change contains the whole document
+        let Some(change) = params.content_changes.into_iter().next_back() else {
+            return;
+        };
+
+        let uri = params.text_document.uri;
+        let document = {
+            let mut documents = self.documents.lock().unwrap();
+            let Some(document) = documents.get_mut(&uri) else {
+                return;
+            };
+
+            document.text = change.text;
+            document.clone()
+        };
+
+        self.diagnose(uri, document, params.text_document.version)
+            .await;
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+        self.documents.lock().unwrap().remove(&uri);
+        self.client.publish_diagnostics(uri, Vec::new(), None).await;
+    }
+
+    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        self.format(&params.text_document.uri, None).await
+    }
+
+    async fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        self.format(&params.text_document.uri, Some(params.range))
+            .await
+    }
+}
+
+/// Serve the language server over standard input and output, until the client exits
+pub async fn serve(config: Configuration) -> CLIResult<()> {
+    let (service, socket) = LspService::new(|client| Backend {
+        client,
+        config,
+        cache: LanguageDefinitionCache::new(),
+        documents: Mutex::new(HashMap::new()),
+    });
+
+    Server::new(tokio::io::stdin(), tokio::io::stdout(), socket)
+        .serve(service)
+        .await;
+
+    Ok(())
+}
+
+fn internal_error(error: impl Display) -> Error {
+    Error {
+        code: ErrorCode::InternalError,
+        message: error.to_string().into(),
+        data: None,
+    }
+}
+
+/// Convert a byte offset into an LSP position, whose character offsets count UTF-16 code units
+fn to_position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+
+    Position::new(
+        before.matches('\n').count() as u32,
+        before[line_start..].encode_utf16().count() as u32,
+    )
+}
+
+/// Convert an LSP position into a byte offset, clamping it to the text
+fn to_offset(text: &str, position: Position) -> usize {
+    let mut offset = 0;
+
+    for (row, line) in text.split_inclusive('\n').enumerate() {
+        if row == position.line as usize {
+            let mut column = 0;
+
+            for (idx, c) in line.char_indices() {
+                if column >= position.character as usize || c == '\n' {
+                    return offset + idx;
+                }
+
+                column += c.len_utf16();
+            }
+
+            return offset + line.len();
+        }
+
+        offset += line.len();
+    }
+
+    text.len()
+}
+
+/// A single edit that turns the original text into the formatted text, spanning only the part
+/// that differs between them
+fn minimal_edit(original: &str, formatted: &str) -> Vec<TextEdit> {
+    let prefix: usize = original
+        .chars()
+        .zip(formatted.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(c, _)| c.len_utf8())
+        .sum();
+
+    if prefix == original.len() && prefix == formatted.len() {
+        return Vec::new();
+    }
+
+    let suffix: usize = original[prefix..]
+        .chars()
+        .rev()
+        .zip(formatted[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(c, _)| c.len_utf8())
+        .sum();
+
+    vec![TextEdit {
+        range: Range::new(
+            to_position(original, prefix),
+            to_position(original, original.len() - suffix),
+        ),
+        new_text: formatted[prefix..formatted.len() - suffix].to_string(),
+    }]
+}
@@ -1,24 +1,50 @@
+mod cache;
 mod cli;
+mod coverage;
+#[cfg(unix)]
+mod daemon;
+mod diff;
+mod doctor;
 mod error;
+mod explain;
 mod fs;
+mod git;
+mod golden;
+mod grammars;
 mod io;
 mod language;
+mod lsp;
+mod query;
+mod report;
+mod scaffold;
 mod visualisation;
+mod watch;
 
 use std::{
-    io::{BufReader, BufWriter, Write},
+    io::{BufReader, BufWriter, IsTerminal, Write},
+    path::PathBuf,
     process::ExitCode,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
 };
 
 use error::Benign;
 use tabled::{Table, settings::Style};
 use topiary_config::source::Source;
-use topiary_core::{Operation, check_query_coverage, formatter};
+use topiary_core::{
+    FormatterError, Language, Operation, Subtree, VisualiseOptions, check_grammar_coverage,
+    explain, formatter, formatter_range, formatter_str, query_matches, visualise_subtree,
+};
 
 use crate::{
-    cli::Commands,
-    error::{CLIResult, print_error},
-    io::{Inputs, OutputFile, process_inputs, read_input},
+    cache::{Entry, FormatCache},
+    cli::{AtLeastOneInput, Commands, ExactlyOneInput, LanguageOverride},
+    error::{CLIError, CLIResult, TopiaryError, print_error},
+    io::{InputFile, InputFrom, Inputs, OutputFile, QuerySource, process_inputs, read_input},
+    language::LanguageDefinitionCache,
+    report::Status,
 };
 
 use miette::{NamedSource, Report};
@@ -36,22 +62,161 @@ async fn main() -> ExitCode {
 }
 
 async fn run() -> CLIResult<()> {
-    let args = cli::get_args()?;
+    let mut args = cli::get_args()?;
+
+    // Formatting standard input is forwarded to a running daemon, if there is one, which saves us
+    // loading the configuration, grammar and query ourselves
+    #[cfg(unix)]
+    if let Some(result) = daemon::forward(&args).await {
+        return result;
+    }
 
     let file_config = &args.global.configuration;
     let (config, nickel_config) =
         topiary_config::Configuration::fetch(args.global.merge_configuration, file_config)?;
 
+    cli::expand_inputs(&mut args.command, &config)?;
+    cli::buffer_stdin(&mut args.command)?;
+
     // Delegate by subcommand
     match args.command {
         Commands::Format {
             tolerate_parsing_errors,
             skip_idempotence,
+            check,
+            diff,
+            diff_context,
+            range,
+            lines,
+            no_cache,
+            report,
             inputs,
-        } => {
+            ..
+        } if check || diff => {
             let inputs = Inputs::new(&config, &inputs);
+            let range = range.or(lines);
+
+            // Ranges are formatted in isolation, so say nothing about the whole input
+            let cache = (!no_cache && range.is_none())
+                .then(|| FormatCache::new(tolerate_parsing_errors))
+                .flatten();
+            let report = report.map(report::Report::new);
+            let list_unformatted = report.is_none();
+
+            // Count the inputs that would change, rather than failing on the first, so that we
+            // can list them all
+            let unformatted = Arc::new(AtomicUsize::new(0));
+            let counter = unformatted.clone();
+
+            let check_input = move |mut input: InputFile, language: Arc<Language>| {
+                let input_content = read_input(&mut input)?;
+
+                let cached = cache
+                    .as_ref()
+                    .and_then(|cache| cache.entry(&input, &language, input_content.as_bytes()));
+                if cached.as_ref().is_some_and(Entry::is_formatted) {
+                    log::info!("Skipping {}, which is already formatted", input.source());
+                    return Ok(Status::Ok);
+                }
+
+                log::info!(
+                    "Checking {}, as {} using {}, is formatted",
+                    input.source(),
+                    input.language().name,
+                    input.query(),
+                );
+
+                let mut output = Vec::new();
+                let operation = Operation::Format {
+                    skip_idempotence,
+                    tolerate_parsing_errors,
+                };
+
+                match &range {
+                    Some(range) => {
+                        formatter_range(&input_content, &mut output, &language, operation, range)?
+                    }
+                    None => formatter_str(&input_content, &mut output, &language, operation)?,
+                }
+
+                if output != input_content.as_bytes() {
+                    if diff {
+                        // The formatter only ever emits valid UTF-8, given valid UTF-8 input
+                        let output = String::from_utf8_lossy(&output);
+                        let name = input.source().to_string();
+
+                        print!(
+                            "{}",
+                            diff::unified(&input_content, &output, &name, &name, diff_context)
+                        );
+                    } else if list_unformatted {
+                        println!("{}", input.source());
+                    }
+
+                    counter.fetch_add(1, Ordering::Relaxed);
+                    return Ok(Status::Unformatted);
+                }
+
+                if let Some(cached) = cached {
+                    cached.mark_formatted();
+                }
+
+                Ok(Status::Ok)
+            };
+
+            let result = process_inputs(
+                inputs,
+                &LanguageDefinitionCache::new(),
+                report.as_ref(),
+                &check_input,
+            )
+            .await;
+
+            if let Some(report) = &report {
+                report.print()?;
+            }
+            result?;
+
+            // A diff alone is informational; it's only a failure when also checking
+            let unformatted = unformatted.load(Ordering::Relaxed);
+            if check && unformatted > 0 {
+                return Err(TopiaryError::Bin(
+                    format!("{unformatted} input(s) would be changed by formatting"),
+                    Some(CLIError::Unformatted),
+                ));
+            }
+        }
+
+        Commands::Format {
+            tolerate_parsing_errors,
+            skip_idempotence,
+            range,
+            lines,
+            no_cache,
+            watch,
+            report,
+            inputs,
+            ..
+        } => {
+            let range = range.or(lines);
+            let report = report.map(report::Report::new);
+
+            // Ranges are formatted in isolation, so say nothing about the whole input
+            let cache = (!no_cache && range.is_none())
+                .then(|| FormatCache::new(tolerate_parsing_errors))
+                .flatten();
+
+            let format_input = move |mut input: InputFile, language: Arc<Language>| {
+                let input_content = read_input(&mut input)?;
+
+                let cached = cache
+                    .as_ref()
+                    .and_then(|cache| cache.entry(&input, &language, input_content.as_bytes()));
+                if cached.as_ref().is_some_and(Entry::is_formatted) {
+                    log::info!("Skipping {}, which is already formatted", input.source());
+                    return Ok(Status::Ok);
+                }
 
-            process_inputs(inputs, move |input, language| {
                 let output = OutputFile::try_from(&input)?;
 
                 log::info!(
@@ -62,38 +227,97 @@ async fn run() -> CLIResult<()> {
                     output
                 );
 
-                let mut buf_output = BufWriter::new(output);
+                let mut formatted = Vec::new();
+                let operation = Operation::Format {
+                    skip_idempotence,
+                    tolerate_parsing_errors,
+                };
 
-                {
-                    // NOTE This newly opened scope is important! `buf_input` takes
-                    // ownership of `input`, which -- upon reading -- contains an
-                    // open file handle. We need to close this file, by dropping
-                    // `buf_input`, before we attempt to persist our output.
-                    // Otherwise, we get an exclusive lock problem on Windows.
-                    let mut buf_input = BufReader::new(input);
-
-                    formatter(
-                        &mut buf_input,
-                        &mut buf_output,
+                match &range {
+                    Some(range) => formatter_range(
+                        &input_content,
+                        &mut formatted,
                         &language,
-                        Operation::Format {
-                            skip_idempotence,
-                            tolerate_parsing_errors,
-                        },
-                    )?;
+                        operation,
+                        range,
+                    )?,
+                    None => formatter_str(&input_content, &mut formatted, &language, operation)?,
                 }
 
-                buf_output.into_inner()?.persist()?;
+                // The formatted output is known to be formatted itself if it passed the
+                // idempotence check, or if formatting didn't change anything
+                let formatted_entry = (!skip_idempotence || formatted == input_content.as_bytes())
+                    .then(|| {
+                        cache
+                            .as_ref()
+                            .and_then(|cache| cache.entry(&input, &language, &formatted))
+                    })
+                    .flatten();
+
+                // NOTE `input` contains an open file handle, once read. We need to close this
+                // file, by dropping `input`, before we attempt to persist our output. Otherwise,
+                // we get an exclusive lock problem on Windows.
+                drop(input);
+
+                // Inputs that are already formatted are left untouched, other than standard input,
+                // which must always be echoed
+                if formatted != input_content.as_bytes() || matches!(output, OutputFile::Stdout) {
+                    let mut buf_output = BufWriter::new(output);
+                    buf_output.write_all(&formatted)?;
+                    buf_output.into_inner()?.persist()?;
+                }
 
-                CLIResult::Ok(())
-            })
-            .await?;
+                if let Some(entry) = formatted_entry {
+                    entry.mark_formatted();
+                }
+
+                Ok(Status::Ok)
+            };
+
+            let language_cache = LanguageDefinitionCache::new();
+            let result = process_inputs(
+                Inputs::new(&config, &inputs),
+                &language_cache,
+                report.as_ref(),
+                &format_input,
+            )
+            .await;
+
+            if let Some(report) = &report {
+                report.print()?;
+            }
+
+            if watch {
+                // Errors are reported, rather than fatal, so we can keep watching
+                if let Err(e) = result {
+                    print_error(&e);
+                }
+
+                let watched = watch::Watched::new(Inputs::new(&config, &inputs));
+                watch::run(watched, &language_cache, |files| {
+                    let changed = AtLeastOneInput {
+                        files,
+                        ..inputs.clone()
+                    };
+
+                    process_inputs(
+                        Inputs::new(&config, &changed),
+                        &language_cache,
+                        None,
+                        &format_input,
+                    )
+                })
+                .await?;
+            } else {
+                result?;
+            }
         }
 
-        Commands::CheckGrammar { inputs } => {
+        Commands::CheckGrammar { report, inputs } => {
             let inputs = Inputs::new(&config, &inputs);
+            let report = report.map(report::Report::new);
 
-            process_inputs(inputs, |mut input, language| {
+            let check_input = |mut input: InputFile, language: Arc<Language>| {
                 let input_content = read_input(&mut input)?;
                 log::debug!(
                     "Checking {}, as {} for grammar correctness",
@@ -103,12 +327,52 @@ async fn run() -> CLIResult<()> {
 
                 topiary_core::parse(&input_content, &language.grammar, false)?;
 
-                Ok(())
-            })
-            .await?;
+                Ok(Status::Ok)
+            };
+
+            let result = process_inputs(
+                inputs,
+                &LanguageDefinitionCache::new(),
+                report.as_ref(),
+                &check_input,
+            )
+            .await;
+
+            if let Some(report) = &report {
+                report.print()?;
+            }
+            result?;
         }
 
-        Commands::Visualise { format, input } => {
+        Commands::Visualise {
+            format,
+            leaf_text,
+            byte_ranges,
+            at,
+            range,
+            kind,
+            depth,
+            input,
+        } => {
+            let subtree = match (at, range, kind) {
+                (Some(at), _, _) => Some(Subtree::At(at)),
+                (_, Some(range), _) => Some(Subtree::Range(range)),
+                (_, _, Some(kind)) => Some(Subtree::Kind(kind)),
+                _ => None,
+            };
+
+            if matches!(
+                format,
+                visualisation::Format::Atoms | visualisation::Format::AtomsRaw
+            ) && (subtree.is_some() || depth.is_some())
+            {
+                return Err(TopiaryError::Bin(
+                    "The --at, --range, --kind and --depth options only apply to parse tree formats"
+                        .into(),
+                    None,
+                ));
+            }
+
             // We are guaranteed (by clap) to have exactly one input, so it's safe to unwrap
             let input = Inputs::new(&config, &input).next().unwrap()?;
             let output = OutputFile::Stdout;
@@ -126,15 +390,28 @@ async fn run() -> CLIResult<()> {
 
             let mut buf_input = BufReader::new(input);
             let mut buf_output = BufWriter::new(output);
+            let mut options = VisualiseOptions::default();
+            options.leaf_text = leaf_text;
+            options.byte_ranges = byte_ranges;
+            options.depth = depth;
+            let operation = Operation::Visualise {
+                output_format: format.into(),
+                options,
+            };
 
-            formatter(
-                &mut buf_input,
-                &mut buf_output,
-                &language,
-                Operation::Visualise {
-                    output_format: format.into(),
-                },
-            )
+            match subtree {
+                Some(subtree) => {
+                    let input_content = read_input(&mut buf_input)?;
+                    visualise_subtree(
+                        &input_content,
+                        &mut buf_output,
+                        &language,
+                        operation,
+                        &subtree,
+                    )
+                }
+                None => formatter(&mut buf_input, &mut buf_output, &language, operation),
+            }
             .map_err(|e| e.with_location(format!("{}", buf_input.get_ref().source())))?;
         }
 
@@ -181,45 +458,396 @@ async fn run() -> CLIResult<()> {
             _ => config.prefetch_languages(force)?,
         },
 
-        Commands::Coverage { input } => {
+        Commands::Coverage {
+            report,
+            covered_by,
+            nodes,
+            grammar,
+            inputs,
+        } => {
+            let report = report.map(report::Report::new);
+            let mut inputs = Inputs::new(&config, &inputs);
+            let cache = LanguageDefinitionCache::new();
+
+            if grammar {
+                // Queries are themselves parsed with the grammar that Topiary formats them with
+                let query_grammar = config.get_language("tree_sitter_query")?.grammar()?;
+
+                // The inputs are only needed to find their languages
+                let mut languages: Vec<(Arc<Language>, QuerySource)> = Vec::new();
+                for input in inputs {
+                    let input = input?;
+                    let language = cache.fetch(&input).await?;
+
+                    if !languages.iter().any(|(l, _)| Arc::ptr_eq(l, &language)) {
+                        languages.push((language, input.query().clone()));
+                    }
+                }
+                languages.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+
+                let mut coverage_res = Ok(());
+                for (language, query) in &languages {
+                    let coverage =
+                        check_grammar_coverage(&language.query, &language.grammar, &query_grammar)?;
+                    coverage::write_unreferenced(
+                        &mut BufWriter::new(OutputFile::Stdout),
+                        language,
+                        query,
+                        &coverage,
+                    )?;
+
+                    if !coverage.is_empty() && coverage_res.is_ok() {
+                        coverage_res = Err(TopiaryError::Bin(
+                            "Some node kinds or fields are not referred to by the query".into(),
+                            Some(CLIError::Unhandled),
+                        ));
+                    }
+                }
+
+                return coverage_res;
+            }
+
+            // Inputs are grouped by language definition, to check each query across all of the
+            // inputs that use it
+            let mut groups = Vec::new();
+            let mut errors = Vec::new();
+
+            while let Some((location, input)) = inputs.next_located() {
+                let parsed = async {
+                    let mut input = input?;
+                    let language = cache.fetch(&input).await?;
+
+                    log::info!(
+                        "Checking query coverage of {}, as {}",
+                        input.source(),
+                        input.language().name,
+                    );
+
+                    let input_content = read_input(&mut input)?;
+                    let tree = topiary_core::parse(&input_content, &language.grammar, false)
+                        .map_err(|e| e.with_location(input.source().to_string()))?;
+
+                    CLIResult::Ok((language, input.query().clone(), tree, input_content))
+                }
+                .await;
+
+                match parsed {
+                    Ok((language, query, tree, input_content)) => coverage::Group::add(
+                        &mut groups,
+                        language,
+                        &query,
+                        (location, tree, input_content),
+                    ),
+
+                    Err(e) => {
+                        if let Some(report) = &report {
+                            report.add_error(&location, &e);
+                        }
+
+                        errors.push(e);
+                    }
+                }
+            }
+
+            groups.sort_by(|a, b| a.language.name.cmp(&b.language.name));
+
+            let mut coverage_res = Ok(());
+            for group in &groups {
+                if nodes {
+                    let unhandled = group.check_nodes();
+                    coverage::write_unhandled(
+                        &mut BufWriter::new(OutputFile::Stdout),
+                        group,
+                        &unhandled,
+                    )?;
+
+                    if !unhandled.is_empty() && coverage_res.is_ok() {
+                        coverage_res = Err(TopiaryError::Bin(
+                            "Some node kinds are not handled by the query".into(),
+                            Some(CLIError::Unhandled),
+                        ));
+                    }
+
+                    continue;
+                }
+
+                let coverage_data = group.check()?;
+                if coverage_res.is_ok() {
+                    coverage_res = coverage_data.get_result().map_err(TopiaryError::from);
+                }
+
+                let language = &group.language;
+                if let Some(report) = &report {
+                    report.add_coverage(
+                        &group.locations().collect::<Vec<_>>(),
+                        &group.query,
+                        &language.query.query_content,
+                        &coverage_data,
+                        covered_by,
+                    );
+                } else {
+                    let mut output = BufWriter::new(OutputFile::Stdout);
+                    let query_source = NamedSource::new(
+                        group.query.to_string(),
+                        language.query.query_content.clone(),
+                    )
+                    .with_language(&language.name);
+                    write!(
+                        &mut output,
+                        "{:?}",
+                        Report::new(coverage_data.clone()).with_source_code(query_source)
+                    )?;
+
+                    if covered_by {
+                        coverage::write_covered(&mut output, group, &coverage_data)?;
+                    }
+                }
+            }
+
+            if let Some(report) = &report {
+                report.print()?;
+            }
+
+            match errors.len() {
+                0 => coverage_res?,
+
+                // If we just had one input, then handle errors as normal
+                1 if groups.is_empty() => return Err(errors.swap_remove(0)),
+
+                _ => {
+                    for e in &errors {
+                        print_error(e);
+                    }
+                    return Err(TopiaryError::Bin(
+                        "Processing of some inputs failed; see warning logs for details".into(),
+                        Some(CLIError::Multiple),
+                    ));
+                }
+            }
+        }
+
+        Commands::Lsp => lsp::serve(config).await?,
+
+        Commands::Playground {
+            tolerate_parsing_errors,
+            language,
+            query,
+            file,
+        } => {
+            let input = ExactlyOneInput {
+                overrides: LanguageOverride {
+                    language,
+                    query,
+                    stdin_filepath: None,
+                    stdin_contents: None,
+                },
+                file: Some(file),
+            };
+
+            let language_cache = LanguageDefinitionCache::new();
+            let (config, input, language_cache) = (&config, &input, &language_cache);
+
+            let show = |_| async move {
+                // We are guaranteed (by clap) to have exactly one input, so it's safe to unwrap
+                let mut input = Inputs::new(config, input).next().unwrap()?;
+                let language = language_cache.fetch(&input).await?;
+                let input_content = read_input(&mut input)?;
+
+                // Horizontal rule, to separate the sections
+                let hr = "-".repeat(
+                    std::env::var("COLUMNS")
+                        .ok()
+                        .and_then(|columns| columns.parse().ok())
+                        .unwrap_or(80),
+                );
+
+                let mut stdout = std::io::stdout().lock();
+                if stdout.is_terminal() {
+                    // Clear the screen and move the cursor to its top left
+                    write!(stdout, "\x1b[2J\x1b[H")?;
+                }
+
+                writeln!(stdout, "{hr}")?;
+                writeln!(stdout, "Language      {}", input.language().name)?;
+                writeln!(stdout, "Query File    {}", input.query())?;
+                writeln!(stdout, "Input Source  {}", input.source())?;
+                writeln!(stdout, "{hr}")?;
+
+                let format = |skip_idempotence, output: &mut Vec<u8>| {
+                    let operation = Operation::Format {
+                        skip_idempotence,
+                        tolerate_parsing_errors,
+                    };
+
+                    formatter_str(&input_content, output, &language, operation)
+                };
+
+                let mut output = Vec::new();
+                match format(true, &mut output) {
+                    Ok(()) => {
+                        stdout.write_all(&output)?;
+
+                        // Keep the rule on its own line, even without a final newline
+                        if !output.is_empty() && !output.ends_with(b"\n") {
+                            writeln!(stdout)?;
+                        }
+                    }
+                    Err(e) => writeln!(stdout, "{e}")?,
+                }
+
+                let idempotent = match format(false, &mut Vec::new()) {
+                    Ok(()) => "Yes",
+                    Err(FormatterError::Idempotence) => "No",
+                    Err(_) => "n/a",
+                };
+
+                writeln!(stdout, "{hr}")?;
+                writeln!(stdout, "Idempotent    {idempotent}")?;
+                stdout.flush()?;
+
+                CLIResult::Ok(())
+            };
+
+            show(Vec::new()).await?;
+
+            let watched = watch::Watched::new(Inputs::new(config, input));
+            watch::run(watched, language_cache, show).await?;
+        }
+
+        Commands::Query {
+            query,
+            format,
+            tolerate_parsing_errors,
+            language,
+            stdin_filepath,
+            file,
+            stdin_contents,
+        } => {
+            let input = ExactlyOneInput {
+                overrides: LanguageOverride {
+                    language,
+                    query: None,
+                    stdin_filepath,
+                    stdin_contents,
+                },
+                file,
+            };
+
+            // The query is read from a file, if there is one by that name
+            let query = match PathBuf::from(&query) {
+                path if path.is_file() => QuerySource::Path(path),
+                _ => QuerySource::BuiltIn(query),
+            };
+
             // We are guaranteed (by clap) to have exactly one input, so it's safe to unwrap
-            let input = Inputs::new(&config, &input).next().unwrap()?;
-            let output = OutputFile::Stdout;
+            let mut input = Inputs::new(&config, InputFrom::from(&input).with_query(query))
+                .next()
+                .unwrap()?;
 
             // We don't need a `LanguageDefinitionCache` when there's only one input,
             // which saves us the thread-safety overhead
             let language = input.to_language().await?;
 
             log::info!(
-                "Checking query coverage of {}, as {}",
+                "Querying {}, as {}, with {}",
                 input.source(),
                 input.language().name,
+                input.query()
             );
 
-            let mut buf_input = BufReader::new(input);
-            let mut buf_output = BufWriter::new(output);
-
-            let input_content = read_input(&mut buf_input)?;
+            let input_content = read_input(&mut input)?;
+            let matches = query_matches(
+                &input_content,
+                &language.query,
+                &language.grammar,
+                tolerate_parsing_errors,
+            )
+            .map_err(|e| e.with_location(input.source().to_string()))?;
 
-            let coverage_data =
-                check_query_coverage(&input_content, &language.query, &language.grammar)
-                    .map_err(|e| e.with_location(buf_input.get_ref().source().to_string()))?;
-            let coverage_res = coverage_data.get_result();
+            query::write(&mut BufWriter::new(OutputFile::Stdout), &matches, format)?;
+        }
 
-            let query_source = NamedSource::new(
-                buf_input.get_ref().query.to_string(),
-                language.query.query_content,
+        #[cfg(unix)]
+        Commands::Daemon { socket } => {
+            daemon::serve(
+                config,
+                args.global.merge_configuration,
+                args.global.configuration,
+                socket,
             )
-            .with_language(&language.name);
-            write!(
-                &mut buf_output,
-                "{:?}",
-                Report::new(coverage_data).with_source_code(query_source)
+            .await?
+        }
+
+        Commands::Explain {
+            at,
+            format,
+            tolerate_parsing_errors,
+            input,
+        } => {
+            // We are guaranteed (by clap) to have exactly one input, so it's safe to unwrap
+            let mut input = Inputs::new(&config, &input).next().unwrap()?;
+
+            // We don't need a `LanguageDefinitionCache` when there's only one input,
+            // which saves us the thread-safety overhead
+            let language = input.to_language().await?;
+
+            log::info!(
+                "Explaining {} at {at}, as {}, with {}",
+                input.source(),
+                input.language().name,
+                input.query()
+            );
+
+            let input_content = read_input(&mut input)?;
+            let provenance = explain(&input_content, &language, tolerate_parsing_errors)
+                .map_err(|e| e.with_location(input.source().to_string()))?;
+
+            let leaf = provenance.leaf_at(at).ok_or_else(|| {
+                TopiaryError::Bin(format!("There is no output at or after {at}"), None)
+            })?;
+
+            explain::write(
+                &mut BufWriter::new(OutputFile::Stdout),
+                &provenance,
+                leaf,
+                format,
             )?;
+        }
 
-            coverage_res?;
+        Commands::Test { dir, bless } => golden::run(&config, &dir, bless).await?,
+
+        Commands::InitLanguage {
+            name,
+            git,
+            rev,
+            subdir,
+            extensions,
+            output,
+        } => scaffold::init_language(scaffold::Options {
+            name,
+            git,
+            rev,
+            subdir,
+            extensions,
+            output,
+        })?,
+
+        Commands::Doctor { language } => {
+            doctor::run(
+                &config,
+                args.global.merge_configuration,
+                file_config,
+                language,
+            )
+            .await?
         }
 
+        Commands::Grammars { command } => match command {
+            cli::GrammarsCommand::List => grammars::list(&config)?,
+            cli::GrammarsCommand::Clean { dry_run } => grammars::clean(&config, dry_run)?,
+            cli::GrammarsCommand::Verify => grammars::verify(&config)?,
+        },
+
         Commands::Completion { shell } => {
             // The CLI parser fails if no shell is provided/detected, so it's safe to unwrap here
             cli::completion(shell.unwrap());
@@ -0,0 +1,41 @@
+//! Output formats for the matches of arbitrary Tree-sitter queries, as run by `topiary query`.
+
+use std::io::{self, Write};
+
+use clap::ValueEnum;
+use topiary_core::QueryMatchData;
+
+/// Query match output formats
+#[derive(Clone, Debug, ValueEnum)]
+pub enum Format {
+    /// Each match's pattern index, followed by one line per capture
+    Text,
+
+    /// JSON serialisation
+    Json,
+}
+
+/// Write the matches to the output, in the given format
+pub fn write(
+    output: &mut impl Write,
+    matches: &[QueryMatchData],
+    format: Format,
+) -> io::Result<()> {
+    match format {
+        Format::Text => {
+            for query_match in matches {
+                writeln!(output, "pattern {}", query_match.pattern_index)?;
+                for capture in &query_match.captures {
+                    writeln!(output, "  {capture}")?;
+                }
+            }
+        }
+
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *output, matches)?;
+            writeln!(output)?;
+        }
+    }
+
+    output.flush()
+}
@@ -0,0 +1,415 @@
+//! Machine-readable reports of per-input results, for consumption by CI and code review tooling.
+
+use std::{
+    error::Error,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::{Value, json};
+use topiary_core::{CoverageData, FormatterError};
+
+use crate::{
+    error::{CLIResult, TopiaryError},
+    io::{InputLocation, QuerySource},
+};
+
+/// Report output formats
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Format {
+    /// JSON serialisation of each input's result
+    Json,
+
+    /// SARIF 2.1.0 log, for code scanning tools
+    Sarif,
+}
+
+/// The outcome of processing an input
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    /// Processing succeeded
+    Ok,
+
+    /// The input would be changed by formatting
+    Unformatted,
+
+    /// Some of the query's patterns do not match the input
+    Uncovered,
+
+    /// Processing failed
+    Error,
+}
+
+/// A position in a file: `line` and `column` are 1-based, with columns counted in characters,
+/// while `byte` is a 0-based offset
+#[derive(Debug, Serialize)]
+struct Position {
+    line: usize,
+    column: usize,
+    byte: usize,
+}
+
+impl Position {
+    fn new(content: &str, byte: usize) -> Self {
+        let prefix = content.get(..byte).unwrap_or(content);
+        let line_start = prefix.rfind('\n').map_or(0, |idx| idx + 1);
+
+        Self {
+            line: prefix.matches('\n').count() + 1,
+            column: prefix[line_start..].chars().count() + 1,
+            byte,
+        }
+    }
+}
+
+/// A region of a file, from its start up to (but excluding) its end
+#[derive(Debug, Serialize)]
+struct Span {
+    start: Position,
+    end: Position,
+}
+
+impl Span {
+    fn new(content: &str, start: usize, end: usize) -> Self {
+        Self {
+            start: Position::new(content, start),
+            end: Position::new(content, end),
+        }
+    }
+
+    /// The span of a parsing error, in the input's source code
+    fn from_error(error: &TopiaryError) -> Option<Self> {
+        let TopiaryError::Lib(FormatterError::Parsing(span)) = error else {
+            return None;
+        };
+
+        let (start, end) = (span.start_byte() as usize, span.end_byte() as usize);
+        Some(match &span.content {
+            Some(content) => Self::new(content, start, end),
+
+            // Without the source code, we can only use Tree-sitter's byte-based columns
+            None => {
+                let position = |point: topiary_tree_sitter_facade::Point, byte| Position {
+                    line: point.row() as usize + 1,
+                    column: point.column() as usize + 1,
+                    byte,
+                };
+
+                Self {
+                    start: position(span.start_point(), start),
+                    end: position(span.end_point(), end),
+                }
+            }
+        })
+    }
+}
+
+/// An error encountered while processing an input
+#[derive(Debug, Serialize)]
+struct Problem {
+    kind: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cause: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    span: Option<Span>,
+}
+
+impl From<&TopiaryError> for Problem {
+    fn from(error: &TopiaryError) -> Self {
+        let span = Span::from_error(error);
+        let message = match &span {
+            // The formatter's own message for parsing errors is a rendered diagnostic
+            Some(span) => format!(
+                "Parsing error between line {}, column {} and line {}, column {}",
+                span.start.line, span.start.column, span.end.line, span.end.column
+            ),
+            None => error.to_string(),
+        };
+
+        Self {
+            kind: error.kind(),
+            message,
+            cause: error.source().map(ToString::to_string),
+            span,
+        }
+    }
+}
+
+/// A query pattern that does not match the input
+#[derive(Debug, Serialize)]
+struct MissingPattern {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    span: Span,
+}
+
+/// A query pattern that matches some of the inputs, where a `None` input is standard input
+#[derive(Debug, Serialize)]
+struct CoveredPattern {
+    span: Span,
+    inputs: Vec<Option<PathBuf>>,
+}
+
+/// The query coverage of an input, or of several inputs of the same language
+#[derive(Debug, Serialize)]
+struct Coverage {
+    cover_percentage: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    query: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    inputs: Vec<PathBuf>,
+    missing_patterns: Vec<MissingPattern>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    covered_patterns: Option<Vec<CoveredPattern>>,
+}
+
+/// The result of processing an input, where a `None` file is standard input; for the coverage of
+/// several inputs, the file is also `None` and the inputs are listed in the coverage
+#[derive(Debug, Serialize)]
+struct Entry {
+    file: Option<PathBuf>,
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<Problem>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    coverage: Option<Coverage>,
+}
+
+/// Collects the results of processing inputs, which may happen concurrently, to be printed once
+/// they are all done
+#[derive(Debug)]
+pub struct Report {
+    format: Format,
+    entries: Mutex<Vec<Entry>>,
+}
+
+impl Report {
+    pub fn new(format: Format) -> Self {
+        Self {
+            format,
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn push(&self, entry: Entry) {
+        self.entries.lock().unwrap().push(entry);
+    }
+
+    /// Record the result of processing an input
+    pub fn add(&self, location: &InputLocation, result: &CLIResult<Status>) {
+        match result {
+            Ok(status) => self.push(Entry {
+                file: location.path().map(Path::to_path_buf),
+                status: *status,
+                error: None,
+                coverage: None,
+            }),
+
+            Err(error) => self.add_error(location, error),
+        }
+    }
+
+    /// Record the failure to process an input
+    pub fn add_error(&self, location: &InputLocation, error: &TopiaryError) {
+        self.push(Entry {
+            file: location.path().map(Path::to_path_buf),
+            status: Status::Error,
+            error: Some(error.into()),
+            coverage: None,
+        });
+    }
+
+    /// Record the query coverage of some inputs, where the patterns' spans are offsets into the
+    /// query's content, optionally with the inputs that each covered pattern matches
+    pub fn add_coverage(
+        &self,
+        locations: &[&InputLocation],
+        query: &QuerySource,
+        query_content: &str,
+        coverage: &CoverageData,
+        covered_by: bool,
+    ) {
+        let missing_patterns = coverage
+            .missing_patterns
+            .iter()
+            .map(|pattern| MissingPattern {
+                label: pattern.label().map(ToString::to_string),
+                span: Span::new(
+                    query_content,
+                    pattern.offset(),
+                    pattern.offset() + pattern.len(),
+                ),
+            })
+            .collect();
+
+        let covered_patterns = covered_by.then(|| {
+            coverage
+                .covered_patterns
+                .iter()
+                .map(|pattern| CoveredPattern {
+                    span: Span::new(
+                        query_content,
+                        pattern.span.offset(),
+                        pattern.span.offset() + pattern.span.len(),
+                    ),
+                    inputs: pattern
+                        .inputs
+                        .iter()
+                        .map(|&idx| locations[idx].path().map(Path::to_path_buf))
+                        .collect(),
+                })
+                .collect()
+        });
+
+        let (file, inputs) = match locations {
+            [location] => (location.path().map(Path::to_path_buf), Vec::new()),
+            _ => (
+                None,
+                locations
+                    .iter()
+                    .filter_map(|location| location.path().map(Path::to_path_buf))
+                    .collect(),
+            ),
+        };
+
+        let status = match coverage.get_result() {
+            Ok(()) => Status::Ok,
+            Err(_) => Status::Uncovered,
+        };
+
+        self.push(Entry {
+            file,
+            status,
+            error: None,
+            coverage: Some(Coverage {
+                cover_percentage: coverage.cover_percentage,
+                query: match query {
+                    QuerySource::Path(path) => Some(path.clone()),
+                    QuerySource::BuiltIn(_) => None,
+                },
+                inputs,
+                missing_patterns,
+                covered_patterns,
+            }),
+        });
+    }
+
+    /// Print the report to standard output
+    #[allow(clippy::result_large_err)]
+    pub fn print(&self) -> CLIResult<()> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.sort_by(|a, b| a.file.cmp(&b.file));
+
+        let report = match self.format {
+            Format::Json => json!({ "results": &*entries }),
+            Format::Sarif => sarif(&entries),
+        };
+
+        let mut stdout = io::stdout().lock();
+        serde_json::to_writer_pretty(&mut stdout, &report).map_err(io::Error::from)?;
+        writeln!(stdout)?;
+
+        Ok(())
+    }
+}
+
+/// A SARIF location for the given file and span
+fn sarif_location(file: &Path, span: Option<&Span>) -> Value {
+    // Paths are relative to the working directory, unless they were canonicalised by traversal
+    let uri = match url::Url::from_file_path(file) {
+        Ok(url) => url.to_string(),
+        Err(()) => file.to_string_lossy().replace('\\', "/"),
+    };
+
+    let mut location = json!({ "physicalLocation": { "artifactLocation": { "uri": uri } } });
+    if let Some(span) = span {
+        location["physicalLocation"]["region"] = json!({
+            "startLine": span.start.line,
+            "startColumn": span.start.column,
+            "endLine": span.end.line,
+            "endColumn": span.end.column,
+            "byteOffset": span.start.byte,
+            "byteLength": span.end.byte - span.start.byte,
+        });
+    }
+
+    location
+}
+
+/// A SARIF result, with an optional location
+fn sarif_result(rule: &str, level: &str, message: &str, location: Option<Value>) -> Value {
+    json!({
+        "ruleId": rule,
+        "level": level,
+        "message": { "text": message },
+        "locations": location.into_iter().collect::<Vec<_>>(),
+    })
+}
+
+/// A SARIF 2.1.0 log of the report's entries, where only problems are reported as results
+fn sarif(entries: &[Entry]) -> Value {
+    let mut results = Vec::new();
+
+    for entry in entries {
+        let file = entry.file.as_deref();
+        let name = file.map_or("standard input".into(), |f| f.display().to_string());
+
+        if let Some(error) = &entry.error {
+            results.push(sarif_result(
+                error.kind,
+                "error",
+                &error.message,
+                file.map(|f| sarif_location(f, error.span.as_ref())),
+            ));
+        }
+
+        if entry.status == Status::Unformatted {
+            results.push(sarif_result(
+                "Unformatted",
+                "warning",
+                &format!("{name} would be changed by formatting"),
+                file.map(|f| sarif_location(f, None)),
+            ));
+        }
+
+        if let Some(coverage) = &entry.coverage {
+            let name = match coverage.inputs.len() {
+                0 => name,
+                n => format!("any of its {n} inputs"),
+            };
+
+            for pattern in &coverage.missing_patterns {
+                results.push(sarif_result(
+                    "PatternDoesNotMatch",
+                    "warning",
+                    &format!("The query contains a pattern that does not match {name}"),
+                    coverage
+                        .query
+                        .as_deref()
+                        .map(|query| sarif_location(query, Some(&pattern.span))),
+                ));
+            }
+        }
+    }
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "topiary",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_HOMEPAGE"),
+                },
+            },
+            "columnKind": "unicodeCodePoints",
+            "results": results,
+        }],
+    })
+}
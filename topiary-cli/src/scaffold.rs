@@ -0,0 +1,316 @@
+//! Scaffolding for a new language, as generated by `topiary init-language`: a configuration entry
+//! for its grammar, and a starter query file generated from the node types of that grammar.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use topiary_config::language::{
+    GitSource, Grammar, GrammarSource, Language, LanguageConfiguration,
+};
+use topiary_core::TopiaryQuery;
+
+use crate::error::{CLIError, CLIResult, TopiaryError};
+
+/// A node kind, as described by a grammar's `node-types.json`
+#[derive(Deserialize)]
+struct NodeType {
+    #[serde(rename = "type")]
+    kind: String,
+    named: bool,
+    #[serde(default)]
+    root: bool,
+    #[serde(default)]
+    fields: BTreeMap<String, Children>,
+    children: Option<Children>,
+    /// Supertypes are hidden, so never appear in a parse tree
+    subtypes: Option<Vec<KindRef>>,
+}
+
+impl NodeType {
+    /// The kinds of node that can be children of this one, in its fields or otherwise
+    fn child_kinds(&self) -> impl Iterator<Item = &KindRef> {
+        self.fields
+            .values()
+            .chain(&self.children)
+            .flat_map(|children| &children.types)
+    }
+}
+
+#[derive(Deserialize)]
+struct Children {
+    multiple: bool,
+    types: Vec<KindRef>,
+}
+
+#[derive(Deserialize)]
+struct KindRef {
+    #[serde(rename = "type")]
+    kind: String,
+    named: bool,
+}
+
+/// The grammar of a new language, as given on the command line
+pub struct Options {
+    pub name: String,
+    pub git: String,
+    pub rev: String,
+    pub subdir: Option<String>,
+    pub extensions: Vec<String>,
+    /// The configuration directory to write to
+    pub output: PathBuf,
+}
+
+fn generic_error(message: String, error: impl std::error::Error + 'static) -> TopiaryError {
+    TopiaryError::Bin(message, Some(CLIError::Generic(Box::new(error))))
+}
+
+/// Fetch and compile the grammar, then write the configuration and a starter query for it
+#[allow(clippy::result_large_err)]
+pub fn init_language(options: Options) -> CLIResult<()> {
+    let Options {
+        name,
+        git,
+        rev,
+        subdir,
+        extensions,
+        output,
+    } = options;
+
+    // The name is a Nickel field name, a query file name and part of the grammar's symbol
+    if name.is_empty()
+        || name.starts_with(|c: char| c.is_ascii_digit())
+        || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return Err(TopiaryError::Bin(
+            format!("Invalid language name {name:?}; use letters, digits and underscores"),
+            None,
+        ));
+    }
+
+    let query_path = output.join("queries").join(&name).with_extension("scm");
+    if query_path.exists() {
+        return Err(TopiaryError::Bin(
+            format!("{} already exists", query_path.display()),
+            None,
+        ));
+    }
+
+    let source = GitSource { git, rev, subdir };
+    let mut language = Language::new(
+        name.clone(),
+        LanguageConfiguration {
+            extensions: HashSet::new(),
+            filenames: HashSet::new(),
+            patterns: Vec::new(),
+            interpreters: HashSet::new(),
+            indent: None,
+            grammar: Grammar {
+                source: GrammarSource::Git(source.clone()),
+                symbol: None,
+            },
+        },
+    );
+
+    // The grammar is always checked out, even if it has been compiled before, for its node types
+    let tmp_dir = tempfile::tempdir()?;
+    source.fetch_and_compile_with_dir(
+        &name,
+        language.library_path()?,
+        true,
+        tmp_dir.path().to_path_buf(),
+    )?;
+
+    let checkout = tmp_dir.path().join(&name);
+    let grammar_dir = match &source.subdir {
+        Some(subdir) => checkout.join(subdir),
+        None => checkout.clone(),
+    };
+
+    let node_types: Vec<NodeType> = read_json(&grammar_dir.join("src/node-types.json"))?;
+    let grammar_name = read_json::<serde_json::Value>(&grammar_dir.join("src/grammar.json"))?
+        .get("name")
+        .and_then(|name| name.as_str())
+        .map(str::to_owned)
+        .unwrap_or_else(|| name.clone());
+
+    // The grammar's symbol is named after the grammar, which need not be the language
+    if grammar_name != name {
+        language.config.grammar.symbol = Some(format!("tree_sitter_{grammar_name}"));
+    }
+
+    let extensions = if extensions.is_empty() {
+        file_types(&checkout, &grammar_name)
+    } else {
+        extensions
+    };
+
+    // Check that the grammar loads, and that the starter query is valid for it
+    let grammar = language.grammar()?;
+    let query = starter_query(&node_types);
+    TopiaryQuery::new(&grammar, &query)?;
+
+    fs::create_dir_all(query_path.parent().unwrap())?;
+    fs::write(&query_path, query)?;
+    println!("Wrote a starter query to {}", query_path.display());
+
+    let entry = config_entry(&language, &source, &extensions);
+    let config_path = output.join("languages.ncl");
+    if config_path.exists() {
+        println!(
+            "Add the following to the languages of {}:\n\n{entry}",
+            config_path.display()
+        );
+    } else {
+        fs::write(
+            &config_path,
+            format!("{{\n  languages = {{\n{entry}  }},\n}}\n"),
+        )?;
+        println!("Wrote the configuration to {}", config_path.display());
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::result_large_err)]
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> CLIResult<T> {
+    let contents = fs::read_to_string(path)?;
+    serde_json::from_str(&contents)
+        .map_err(|e| generic_error(format!("Cannot read {}", path.display()), e))
+}
+
+/// The file types of the grammar, from the repository's `tree-sitter.json`, if it has one
+fn file_types(checkout: &Path, grammar_name: &str) -> Vec<String> {
+    let Ok(metadata) = read_json::<serde_json::Value>(&checkout.join("tree-sitter.json")) else {
+        return Vec::new();
+    };
+
+    metadata["grammars"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|grammar| grammar["name"] == grammar_name)
+        .and_then(|grammar| grammar["file-types"].as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|file_type| file_type.as_str().map(str::to_owned))
+        .collect()
+}
+
+/// The language's entry in the `languages` of a configuration file
+fn config_entry(language: &Language, source: &GitSource, extensions: &[String]) -> String {
+    let mut entry = String::new();
+    let extensions: Vec<String> = extensions.iter().map(|e| format!("{e:?}")).collect();
+
+    writeln!(entry, "    {} = {{", language.name).unwrap();
+    writeln!(entry, "      extensions = [{}],", extensions.join(", ")).unwrap();
+    writeln!(entry, "      grammar.source.git = {{").unwrap();
+    writeln!(entry, "        git = {:?},", source.git).unwrap();
+    writeln!(entry, "        rev = {:?},", source.rev).unwrap();
+    if let Some(subdir) = &source.subdir {
+        writeln!(entry, "        subdir = {subdir:?},").unwrap();
+    }
+    writeln!(entry, "      }},").unwrap();
+    if let Some(symbol) = &language.config.grammar.symbol {
+        writeln!(entry, "      grammar.symbol = {symbol:?},").unwrap();
+    }
+    writeln!(entry, "    }},").unwrap();
+
+    entry
+}
+
+/// A starter query for the grammar: string- and comment-like nodes are leaves, blank lines are
+/// kept between statements, and every other named node kind gets a commented-out pattern to fill
+/// in. Without any further formatting, leaves will run together in the output.
+fn starter_query(node_types: &[NodeType]) -> String {
+    let named: Vec<&NodeType> = node_types
+        .iter()
+        .filter(|node| node.named && node.subtypes.is_none())
+        .collect();
+
+    // The node kinds within leaves need no patterns of their own
+    let leaves: Vec<&str> = named
+        .iter()
+        .filter(|node| node.kind.contains("string") || node.kind.contains("comment"))
+        .map(|node| node.kind.as_str())
+        .collect();
+    let within_leaves: HashSet<&str> = named
+        .iter()
+        .filter(|node| leaves.contains(&node.kind.as_str()))
+        .flat_map(|node| node.child_kinds())
+        .map(|child| child.kind.as_str())
+        .collect();
+    let leaves: Vec<&str> = leaves
+        .into_iter()
+        .filter(|leaf| !within_leaves.contains(leaf))
+        .collect();
+
+    let statement_lists: Vec<&str> = named
+        .iter()
+        .filter(|node| {
+            node.root
+                || node.children.as_ref().is_some_and(|children| {
+                    children.multiple
+                        && children.types.iter().any(|child| {
+                            ["statement", "declaration", "definition"]
+                                .iter()
+                                .any(|s| child.kind.contains(s))
+                        })
+                })
+        })
+        .map(|node| node.kind.as_str())
+        .collect();
+
+    let mut query = String::new();
+
+    if !leaves.is_empty() {
+        query.push_str(
+            "; Sometimes we want to indicate that certain parts of our source text should\n\
+             ; not be formatted, but taken as is. We use the leaf capture name to inform the\n\
+             ; tool of this.\n[\n",
+        );
+        for leaf in &leaves {
+            writeln!(query, "  ({leaf})").unwrap();
+        }
+        query.push_str("] @leaf\n\n");
+    }
+
+    for list in &statement_lists {
+        writeln!(query, "; Allow blank lines between the children of {list}").unwrap();
+        writeln!(query, "({list}\n  (_) @allow_blank_line_before\n)\n").unwrap();
+    }
+
+    query.push_str(
+        "; The remaining node kinds of the grammar. Until their children are given\n\
+         ; whitespace, they are output without any between them.\n",
+    );
+    for node in named {
+        let kind = node.kind.as_str();
+        if leaves.contains(&kind) || within_leaves.contains(kind) {
+            continue;
+        }
+
+        if node.fields.is_empty() {
+            writeln!(query, "\n; ({kind})").unwrap();
+            continue;
+        }
+
+        writeln!(query, "\n; ({kind}").unwrap();
+        for (field, children) in &node.fields {
+            // Fields of only anonymous nodes cannot be matched with a named wildcard
+            let wildcard = if children.types.iter().any(|child| child.named) {
+                "(_)"
+            } else {
+                "_"
+            };
+            writeln!(query, ";   {field}: {wildcard}").unwrap();
+        }
+        query.push_str("; )\n");
+    }
+
+    query
+}
@@ -1,7 +1,7 @@
 use clap::ValueEnum;
 use topiary_core::Visualisation;
 
-/// Visualisation output formats for Tree-sitter parse trees
+/// Visualisation output formats for Tree-sitter parse trees, and the atoms produced from them
 // NOTE While redundant, we cannot implement clap::ValueEnum for topiary_core::Visualisation
 // without breaking the orphan rules. So we have to maintain a local copy for the sake of the CLI.
 #[derive(Clone, Debug, ValueEnum)]
@@ -11,6 +11,21 @@ pub enum Format {
 
     /// JSON serialisation
     Json,
+
+    /// S-expression, in the notation of Tree-sitter's playground
+    Sexp,
+
+    /// Mermaid graph, for embedding in Markdown
+    Mermaid,
+
+    /// Self-contained HTML page, with a collapsible tree that highlights the source on hover
+    Html,
+
+    /// The atoms produced by the query, before and after post-processing, one per line
+    Atoms,
+
+    /// JSON serialisation of the atoms produced by the query, before and after post-processing
+    AtomsRaw,
 }
 
 impl From<Format> for Visualisation {
@@ -18,6 +33,11 @@ impl From<Format> for Visualisation {
         match visualisation {
             Format::Dot => Self::GraphViz,
             Format::Json => Self::Json,
+            Format::Sexp => Self::SExpression,
+            Format::Mermaid => Self::Mermaid,
+            Format::Html => Self::Html,
+            Format::Atoms => Self::Atoms,
+            Format::AtomsRaw => Self::AtomsRaw,
         }
     }
 }
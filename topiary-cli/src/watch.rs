@@ -0,0 +1,151 @@
+//! Watching inputs and their queries for changes, to re-run formatting whenever they're edited.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+
+use crate::{
+    error::{Benign, CLIError, CLIResult, TopiaryError, print_error},
+    io::{Inputs, QuerySource},
+    language::LanguageDefinitionCache,
+};
+
+// Editors often save a file in several steps (e.g., truncate, write, then rename), so we wait for
+// this long after a change for any others, before acting on them
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// The input files and query files to watch, with each query file mapped to its inputs
+#[derive(Debug, Default)]
+pub struct Watched {
+    inputs: BTreeSet<PathBuf>,
+    queries: HashMap<PathBuf, BTreeSet<PathBuf>>,
+}
+
+impl Watched {
+    /// Watch the inputs from disk, and their query files. Standard input, and any inputs that
+    /// could not be resolved, cannot be watched.
+    pub fn new(mut inputs: Inputs) -> Self {
+        let mut watched = Self::default();
+
+        while let Some((location, input)) = inputs.next_located() {
+            let (Some(path), Ok(input)) = (location.path(), input) else {
+                continue;
+            };
+
+            let path = canonicalize(path);
+            if let QuerySource::Path(query) = input.query() {
+                watched
+                    .queries
+                    .entry(canonicalize(query))
+                    .or_default()
+                    .insert(path.clone());
+            }
+
+            watched.inputs.insert(path);
+        }
+
+        watched
+    }
+
+    fn paths(&self) -> impl Iterator<Item = &PathBuf> {
+        self.inputs.iter().chain(self.queries.keys())
+    }
+
+    /// The inputs affected by a change to the given path: either the input itself, or every input
+    /// that uses it as a query
+    fn affected(&self, path: &Path) -> impl Iterator<Item = &PathBuf> {
+        self.inputs
+            .get(path)
+            .into_iter()
+            .chain(self.queries.get(path).into_iter().flatten())
+    }
+}
+
+fn canonicalize(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn watch_error(e: notify::Error) -> TopiaryError {
+    TopiaryError::Bin(
+        "Could not watch files for changes".into(),
+        Some(CLIError::Generic(Box::new(e))),
+    )
+}
+
+/// Wait for changes to the watched files, then call `process` with the inputs they affect; this
+/// repeats until interrupted. Language definitions that use a changed query file are invalidated
+/// beforehand, so they're rebuilt. Errors from `process` are reported, but do not stop watching.
+#[allow(clippy::result_large_err)]
+pub async fn run<F, Fut>(
+    watched: Watched,
+    cache: &LanguageDefinitionCache,
+    mut process: F,
+) -> CLIResult<()>
+where
+    F: FnMut(Vec<PathBuf>) -> Fut,
+    Fut: Future<Output = CLIResult<()>>,
+{
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut watcher = RecommendedWatcher::new(
+        move |event: notify::Result<notify::Event>| {
+            // The receiver only goes away when we stop watching, so there's nothing to do if
+            // this fails
+            let _ = tx.send(event);
+        },
+        notify::Config::default(),
+    )
+    .map_err(watch_error)?;
+
+    // We watch the directories containing the files, rather than the files themselves, as editors
+    // that save by replacing a file would otherwise break the watch
+    let directories: BTreeSet<&Path> = watched.paths().filter_map(|path| path.parent()).collect();
+    for directory in directories {
+        watcher
+            .watch(directory, RecursiveMode::NonRecursive)
+            .map_err(watch_error)?;
+    }
+
+    log::info!("Watching {} file(s) for changes", watched.paths().count());
+
+    while let Some(event) = rx.recv().await {
+        let mut events = vec![event];
+        while let Ok(Some(event)) = tokio::time::timeout(DEBOUNCE, rx.recv()).await {
+            events.push(event);
+        }
+
+        let mut affected = BTreeSet::new();
+        for event in events {
+            let event = event.map_err(watch_error)?;
+            if matches!(event.kind, EventKind::Access(_)) {
+                continue;
+            }
+
+            for path in event.paths {
+                let path = canonicalize(&path);
+                if watched.queries.contains_key(&path) {
+                    log::info!("Query {} changed", path.display());
+                    cache.invalidate(&path).await;
+                }
+
+                affected.extend(watched.affected(&path).cloned());
+            }
+        }
+
+        if affected.is_empty() {
+            continue;
+        }
+
+        if let Err(e) = process(affected.into_iter().collect()).await
+            && !e.benign()
+        {
+            print_error(&e);
+        }
+    }
+
+    Ok(())
+}
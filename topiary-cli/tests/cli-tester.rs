@@ -7,6 +7,16 @@ use {
 
 use assert_cmd::cargo_bin_cmd;
 
+/// The Topiary binary, with its cache of formatted inputs kept out of the user's cache directory
+fn topiary_command() -> assert_cmd::Command {
+    let mut topiary = cargo_bin_cmd!("topiary");
+    topiary.env(
+        "TOPIARY_FORMAT_CACHE_DIR",
+        concat!(env!("CARGO_TARGET_TMPDIR"), "/formatted"),
+    );
+    topiary
+}
+
 // Simple exemplar JSON and TOML state, to verify the formatter
 // is doing something... and hopefully the right thing
 #[cfg(feature = "json")]
@@ -30,7 +40,7 @@ static INIT: Once = Once::new();
 pub fn initialize() {
     INIT.call_once(|| {
         #[cfg(feature = "json")]
-        cargo_bin_cmd!("topiary")
+        topiary_command()
             .arg("fmt")
             .arg("--language")
             .arg("json")
@@ -38,7 +48,7 @@ pub fn initialize() {
             .assert()
             .success();
         #[cfg(feature = "toml")]
-        cargo_bin_cmd!("topiary")
+        topiary_command()
             .arg("fmt")
             .arg("--language")
             .arg("toml")
@@ -81,7 +91,7 @@ impl State {
 #[cfg(feature = "json")]
 fn test_fmt_stdin() {
     initialize();
-    let mut topiary = cargo_bin_cmd!("topiary");
+    let mut topiary = topiary_command();
 
     topiary
         .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
@@ -98,7 +108,7 @@ fn test_fmt_stdin() {
 #[cfg(feature = "json")]
 fn test_fmt_stdin_query() {
     initialize();
-    let mut topiary = cargo_bin_cmd!("topiary");
+    let mut topiary = topiary_command();
 
     topiary
         .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
@@ -117,7 +127,7 @@ fn test_fmt_stdin_query() {
 #[cfg(feature = "json")]
 fn test_fmt_stdin_query_fallback() {
     initialize();
-    let mut topiary = cargo_bin_cmd!("topiary");
+    let mut topiary = topiary_command();
 
     topiary
         // run in topiary-cli/tests directory so that it couldn't find the
@@ -139,7 +149,7 @@ fn test_fmt_files() {
     let json = State::new(JSON_INPUT, "json");
     let toml = State::new(TOML_INPUT, "toml");
 
-    let mut topiary = cargo_bin_cmd!("topiary");
+    let mut topiary = topiary_command();
 
     topiary
         .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
@@ -160,7 +170,7 @@ fn test_fmt_files_query_fallback() {
     let json = State::new(JSON_INPUT, "json");
     let toml = State::new(TOML_INPUT, "toml");
 
-    let mut topiary = cargo_bin_cmd!("topiary");
+    let mut topiary = topiary_command();
 
     topiary
         // run in topiary-cli/tests directory so that it couldn't find the
@@ -176,13 +186,54 @@ fn test_fmt_files_query_fallback() {
     assert_eq!(toml.read(), TOML_EXPECTED);
 }
 
+#[test]
+#[cfg(feature = "json")]
+fn test_fmt_files_language() {
+    initialize();
+    let generated = State::new(JSON_INPUT, "generated");
+
+    let mut topiary = topiary_command();
+
+    // The language override applies to files whose language couldn't otherwise be detected
+    topiary
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("fmt")
+        .arg("--language")
+        .arg("json")
+        .arg("--query")
+        .arg("../topiary-queries/queries/json.scm")
+        .arg(generated.path())
+        .assert()
+        .success();
+
+    assert_eq!(generated.read(), JSON_EXPECTED);
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_fmt_stdin_filepath() {
+    initialize();
+    let mut topiary = topiary_command();
+
+    // The path needn't exist, as it's only used for detection
+    topiary
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("fmt")
+        .arg("--stdin-filepath")
+        .arg("/path/to/some/input.json")
+        .write_stdin(JSON_INPUT)
+        .assert()
+        .success()
+        .stdout(JSON_EXPECTED);
+}
+
 #[test]
 #[cfg(feature = "json")]
 fn test_fmt_dir() {
     initialize();
     let json = State::new(JSON_INPUT, "json");
 
-    let mut topiary = cargo_bin_cmd!("topiary");
+    let mut topiary = topiary_command();
 
     topiary
         .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
@@ -196,93 +247,1667 @@ fn test_fmt_dir() {
 
 #[test]
 #[cfg(feature = "json")]
-fn test_fmt_invalid() {
+fn test_fmt_dir_ignored() {
     initialize();
-    let mut topiary = cargo_bin_cmd!("topiary");
+    let json = State::new(JSON_INPUT, "json");
+    let root = json.path().parent().unwrap();
+
+    // Files that are ignored, excluded or in an unknown language should all be skipped
+    let ignored = root.join("ignored");
+    fs::create_dir(&ignored).unwrap();
+    fs::write(ignored.join("state.json"), JSON_INPUT).unwrap();
+    fs::write(root.join(".gitignore"), "ignored/\n").unwrap();
+    fs::write(root.join(".topiaryignore"), "topiaryignored.json\n").unwrap();
+    fs::write(root.join("topiaryignored.json"), JSON_INPUT).unwrap();
+    fs::write(root.join("excluded.min.json"), JSON_INPUT).unwrap();
+    fs::write(root.join("unknown.txt"), "Not a known language").unwrap();
+
+    let config = root.join("config.ncl");
+    fs::write(&config, r#"{ exclude = ["*.min.json", "config.ncl"] }"#).unwrap();
+
+    let mut topiary = topiary_command();
 
-    // Can't specify --language with input files
     topiary
         .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
         .arg("fmt")
-        .arg("--language")
-        .arg("json")
-        .arg("/path/to/some/input")
+        .arg("--configuration")
+        .arg(&config)
+        .arg(root)
         .assert()
-        .failure();
+        .success();
 
-    // Can't specify --query without --language
+    assert_eq!(json.read(), JSON_EXPECTED);
+    for skipped in [
+        ignored.join("state.json"),
+        root.join("topiaryignored.json"),
+        root.join("excluded.min.json"),
+    ] {
+        assert_eq!(fs::read_to_string(skipped).unwrap(), JSON_INPUT);
+    }
+
+    let mut topiary = topiary_command();
+
+    // Explicitly given files are never skipped, so unknown languages still fail
     topiary
-        .env("TOPIARY_LANGUAGE_DIR", "../whatever")
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
         .arg("fmt")
-        .arg("--query")
-        .arg("/path/to/query")
+        .arg(root.join("unknown.txt"))
         .assert()
         .failure();
 }
 
+#[test]
+#[cfg(all(feature = "json", feature = "bash"))]
+fn test_fmt_detection() {
+    initialize();
+    let dir = TempDir::new().unwrap();
+
+    // Detected by compound extension, file name, configured pattern, shebang and modeline
+    let cases = [
+        ("state.tfstate.backup", JSON_INPUT, JSON_EXPECTED),
+        (".bashrc", "echo   hello\n", "echo hello\n"),
+        ("settings.conf", JSON_INPUT, JSON_EXPECTED),
+        (
+            "script",
+            "#!/usr/bin/env bash\necho   hello\n",
+            "#!/usr/bin/env bash\necho hello\n",
+        ),
+        (
+            "modeline",
+            "# vim: ft=sh\necho   hello\n",
+            "# vim: ft=sh\necho hello\n",
+        ),
+        (
+            "unknown-interpreter",
+            "#!/usr/bin/env foo -*- mode: sh -*-\necho   hello\n",
+            "#!/usr/bin/env foo -*- mode: sh -*-\necho hello\n",
+        ),
+    ];
+
+    for (name, input, _) in cases {
+        fs::write(dir.path().join(name), input).unwrap();
+    }
+
+    let config = dir.path().join("config.ncl");
+    fs::write(&config, r#"{ languages.json.patterns = ["*.conf"] }"#).unwrap();
+
+    let mut topiary = topiary_command();
+
+    topiary
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("fmt")
+        .arg("--configuration")
+        .arg(&config)
+        .args(cases.map(|(name, _, _)| dir.path().join(name)))
+        .assert()
+        .success();
+
+    for (name, _, expected) in cases {
+        assert_eq!(fs::read_to_string(dir.path().join(name)).unwrap(), expected);
+    }
+
+    // When walking a directory, only files that could be scripts are read to detect their
+    // language: those without an extension, or that are executable
+    let walked = dir.path().join("walked");
+    fs::create_dir(&walked).unwrap();
+    for name in ["script", "notes.txt", "tool.run"] {
+        fs::write(walked.join(name), "#!/usr/bin/env bash\necho   hello\n").unwrap();
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(walked.join("tool.run"), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    topiary_command()
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("fmt")
+        .arg(&walked)
+        .assert()
+        .success();
+
+    let formatted = |name: &str| {
+        fs::read_to_string(walked.join(name))
+            .unwrap()
+            .contains("echo hello")
+    };
+    assert!(formatted("script"));
+    assert!(!formatted("notes.txt"));
+    #[cfg(unix)]
+    assert!(formatted("tool.run"));
+
+    // On standard input, the first line is that of the input, even if the path doesn't exist
+    topiary_command()
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("fmt")
+        .arg("--stdin-filepath")
+        .arg(dir.path().join("unsaved"))
+        .write_stdin("# vim: ft=sh\necho   hello\n")
+        .assert()
+        .success()
+        .stdout("# vim: ft=sh\necho hello\n");
+}
+
 #[test]
 #[cfg(feature = "json")]
-fn test_vis() {
-    use predicates::{
-        prelude::PredicateBooleanExt,
-        str::{ends_with, starts_with},
+fn test_fmt_cache() {
+    use predicates::prelude::*;
+    use std::time::{Duration, SystemTime};
+
+    initialize();
+    let input = State::new(JSON_INPUT, "json");
+    let cache = TempDir::new().unwrap();
+    let skipped = predicate::str::contains("which is already formatted");
+
+    let check = |no_cache: bool| {
+        topiary_command()
+            .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+            .env("TOPIARY_FORMAT_CACHE_DIR", cache.path())
+            .arg("-vv")
+            .arg("fmt")
+            .arg("--check")
+            .args(no_cache.then_some("--no-cache"))
+            .arg(input.path())
+            .assert()
+            .success()
+    };
+
+    // The entries of the cache, which are grouped into directories
+    let entries = || -> Vec<PathBuf> {
+        fs::read_dir(cache.path())
+            .unwrap()
+            .flatten()
+            .filter(|prefix| prefix.path().is_dir())
+            .flat_map(|prefix| fs::read_dir(prefix.path()).unwrap().flatten())
+            .map(|entry| entry.path())
+            .collect()
+    };
+
+    // Formatting records the output as formatted, so the next run skips it
+    topiary_command()
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .env("TOPIARY_FORMAT_CACHE_DIR", cache.path())
+        .arg("fmt")
+        .arg(input.path())
+        .assert()
+        .success();
+
+    assert_eq!(input.read(), JSON_EXPECTED);
+    assert_eq!(entries().len(), 1);
+
+    check(false).stderr(skipped.clone());
+    check(true).stderr(skipped.clone().not());
+
+    // Old entries are pruned, at most once a day, but a hit refreshes the entry
+    let days_ago = |days: u64| SystemTime::now() - Duration::from_secs(days * 24 * 60 * 60);
+    let age = |path: &std::path::Path, days: u64| {
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(days_ago(days))
+            .unwrap();
     };
 
+    age(&entries()[0], 31);
+    check(false).stderr(skipped.clone());
+
+    age(&cache.path().join("pruned"), 2);
+    check(false).stderr(skipped.clone());
+
+    age(&entries()[0], 31);
+    age(&cache.path().join("pruned"), 2);
+    check(false).stderr(skipped.not());
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_fmt_check() {
     initialize();
-    let mut topiary = cargo_bin_cmd!("topiary");
+    let unformatted = State::new(JSON_INPUT, "json");
+    let formatted = State::new(JSON_EXPECTED, "json");
 
-    // Sanity check output is a valid DOT graph
-    let is_graph = starts_with("graph {").and(ends_with("}\n"));
+    let mut topiary = topiary_command();
 
+    // Only the unformatted input should be listed, and neither should be written
     topiary
         .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
-        .arg("vis")
+        .arg("fmt")
+        .arg("--check")
+        .arg(unformatted.path())
+        .arg(formatted.path())
+        .assert()
+        .code(11)
+        .stdout(format!("{}\n", unformatted.path().display()));
+
+    assert_eq!(unformatted.read(), JSON_INPUT);
+    assert_eq!(formatted.read(), JSON_EXPECTED);
+
+    let mut topiary = topiary_command();
+
+    topiary
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("fmt")
+        .arg("--check")
+        .arg(formatted.path())
+        .assert()
+        .success()
+        .stdout("");
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_fmt_check_stdin() {
+    initialize();
+    let mut topiary = topiary_command();
+
+    topiary
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("fmt")
+        .arg("--check")
         .arg("--language")
         .arg("json")
         .write_stdin(JSON_INPUT)
         .assert()
+        .code(11)
+        .stdout("standard input\n");
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_fmt_check_report() {
+    use serde_json::Value;
+
+    initialize();
+    let unformatted = State::new(JSON_INPUT, "json");
+    let formatted = State::new(JSON_EXPECTED, "json");
+    let invalid = State::new(r#"{"test":"#, "json");
+
+    let mut topiary = topiary_command();
+
+    // Each input's result is reported, rather than listing the unformatted inputs
+    let output = topiary
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("fmt")
+        .arg("--check")
+        .arg("--report")
+        .arg("json")
+        .arg(unformatted.path())
+        .arg(formatted.path())
+        .arg(invalid.path())
+        .assert()
+        .code(9)
+        .get_output()
+        .stdout
+        .clone();
+
+    let report: Value = serde_json::from_slice(&output).unwrap();
+    let result = |state: &State| {
+        report["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|result| result["file"] == state.path().to_str().unwrap())
+            .unwrap()
+            .clone()
+    };
+
+    assert_eq!(result(&unformatted)["status"], "unformatted");
+    assert_eq!(result(&formatted)["status"], "ok");
+
+    let invalid = result(&invalid);
+    assert_eq!(invalid["status"], "error");
+    assert_eq!(invalid["error"]["kind"], "Parsing");
+    assert_eq!(invalid["error"]["span"]["start"]["line"], 1);
+    assert_eq!(invalid["error"]["span"]["end"]["byte"], 8);
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_check_grammar_report_sarif() {
+    use serde_json::Value;
+
+    initialize();
+    let invalid = State::new("{\n  \"test\": ]\n}\n", "json");
+
+    let mut topiary = topiary_command();
+
+    let output = topiary
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("check-grammar")
+        .arg("--report")
+        .arg("sarif")
+        .arg(invalid.path())
+        .assert()
+        .code(5)
+        .get_output()
+        .stdout
+        .clone();
+
+    let report: Value = serde_json::from_slice(&output).unwrap();
+    assert_eq!(report["version"], "2.1.0");
+
+    let results = report["runs"][0]["results"].as_array().unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0]["ruleId"], "Parsing");
+    assert_eq!(results[0]["level"], "error");
+
+    let location = &results[0]["locations"][0]["physicalLocation"];
+    assert!(
+        location["artifactLocation"]["uri"]
+            .as_str()
+            .unwrap()
+            .ends_with("state.json")
+    );
+    assert_eq!(location["region"]["startLine"], 2);
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_fmt_diff() {
+    use predicates::{prelude::PredicateBooleanExt, str::contains};
+
+    initialize();
+    let unformatted = State::new(JSON_INPUT, "json");
+    let formatted = State::new(JSON_EXPECTED, "json");
+
+    let header = format!(
+        "--- {path}\n+++ {path}\n@@ ",
+        path = unformatted.path().display()
+    );
+
+    let mut topiary = topiary_command();
+
+    // A diff alone succeeds, and only the unformatted input gets one
+    topiary
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("fmt")
+        .arg("--diff")
+        .arg(unformatted.path())
+        .arg(formatted.path())
+        .assert()
         .success()
-        .stdout(is_graph);
+        .stdout(
+            contains(header.as_str()).and(contains(formatted.path().display().to_string()).not()),
+        );
+
+    assert_eq!(unformatted.read(), JSON_INPUT);
+    assert_eq!(formatted.read(), JSON_EXPECTED);
+
+    let mut topiary = topiary_command();
+
+    // ...but fails when checking
+    topiary
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("fmt")
+        .arg("--check")
+        .arg("--diff")
+        .arg("--diff-context")
+        .arg("0")
+        .arg(unformatted.path())
+        .assert()
+        .code(11)
+        .stdout(contains(header.as_str()));
+
+    assert_eq!(unformatted.read(), JSON_INPUT);
 }
 
 #[test]
 #[cfg(feature = "json")]
-fn test_vis_invalid() {
+fn test_fmt_diff_stdin() {
     initialize();
-    let mut topiary = cargo_bin_cmd!("topiary");
+    let mut topiary = topiary_command();
 
-    // Can't specify --language with input file
     topiary
         .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
-        .arg("vis")
+        .arg("fmt")
+        .arg("--diff")
         .arg("--language")
         .arg("json")
-        .arg("/path/to/some/input")
+        .write_stdin("{}")
         .assert()
-        .failure();
+        .success()
+        .stdout(
+            "--- standard input\n+++ standard input\n@@ -1,1 +1,1 @@\n-{}\n\\ No newline at end of file\n+{}\n",
+        );
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_fmt_lines() {
+    initialize();
+    let input = State::new("[\n  1,\n  {\"a\":1},\n  3\n]\n", "json");
+
+    let mut topiary = topiary_command();
 
-    // Can't specify --query without --language
     topiary
         .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
-        .arg("vis")
-        .arg("--query")
-        .arg("/path/to/query")
+        .arg("fmt")
+        .arg("--lines")
+        .arg("3-3")
+        .arg(input.path())
         .assert()
-        .failure();
+        .success();
+
+    assert_eq!(input.read(), "[\n  1,\n  { \"a\": 1 },\n  3\n]\n");
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_fmt_range_stdin() {
+    initialize();
+    let mut topiary = topiary_command();
 
-    // Can't specify multiple input files
     topiary
         .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
-        .arg("vis")
-        .arg("/path/to/some/input")
-        .arg("/path/to/another/input")
+        .arg("fmt")
+        .arg("--range")
+        .arg("5:12")
+        .arg("--language")
+        .arg("json")
+        .write_stdin("[1,  {\"a\":1},  3]\n")
         .assert()
-        .failure();
+        .success()
+        .stdout("[1,  { \"a\": 1 },  3]\n");
+}
+
+// Run git in the given directory, for tests that select inputs from a repository
+#[cfg(feature = "json")]
+fn git(dir: &std::path::Path, args: &[&str]) -> String {
+    let output = std::process::Command::new("git")
+        .args([
+            "-c",
+            "user.name=Topiary",
+            "-c",
+            "user.email=topiary@example.com",
+        ])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "git {args:?} failed");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+// A repository with a committed, a modified and a newly staged input, plus a file in a language
+// that Topiary doesn't know about
+#[cfg(feature = "json")]
+fn git_repo() -> TempDir {
+    let repo = TempDir::new().unwrap();
+    let path = repo.path();
+
+    git(path, &["init", "--quiet"]);
+    fs::write(path.join("committed.json"), JSON_INPUT).unwrap();
+    fs::write(path.join("modified.json"), JSON_EXPECTED).unwrap();
+    git(path, &["add", "."]);
+    git(path, &["commit", "--quiet", "--message", "Initial commit"]);
+
+    fs::write(path.join("modified.json"), JSON_INPUT).unwrap();
+    fs::write(path.join("staged.json"), JSON_INPUT).unwrap();
+    fs::write(path.join("unknown.txt"), "Not a known language").unwrap();
+    git(path, &["add", "staged.json", "unknown.txt"]);
+
+    repo
 }
 
 #[test]
-fn test_cfg() {
-    let mut topiary = cargo_bin_cmd!("topiary");
+#[cfg(feature = "json")]
+fn test_fmt_changed_since() {
+    initialize();
+    let repo = git_repo();
+    let path = repo.path();
+
+    let mut topiary = topiary_command();
+
+    // Only the files changed since HEAD should be formatted, skipping the unknown language
+    topiary
+        .env(
+            "TOPIARY_LANGUAGE_DIR",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/../topiary-queries/queries"),
+        )
+        .current_dir(path)
+        .arg("fmt")
+        .arg("--changed-since")
+        .arg("HEAD")
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(path.join("committed.json")).unwrap(),
+        JSON_INPUT
+    );
+    assert_eq!(
+        fs::read_to_string(path.join("modified.json")).unwrap(),
+        JSON_EXPECTED
+    );
+    assert_eq!(
+        fs::read_to_string(path.join("staged.json")).unwrap(),
+        JSON_EXPECTED
+    );
+
+    // Files selected from Git are detected from their paths, so can't have their language or
+    // query overridden
+    for selection in [&["--changed-since", "HEAD"][..], &["--staged"]] {
+        topiary_command()
+            .current_dir(path)
+            .arg("fmt")
+            .args(selection)
+            .args(["--language", "json", "--query", "json.scm"])
+            .assert()
+            .code(2)
+            .stderr(predicates::str::contains("cannot be used with"));
+    }
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_fmt_staged() {
+    initialize();
+    let repo = git_repo();
+    let path = repo.path();
+
+    // Make an unstaged change to the staged file, which must be left alone
+    fs::write(path.join("staged.json"), "[1,2]").unwrap();
+
+    let mut topiary = topiary_command();
+
+    topiary
+        .env(
+            "TOPIARY_LANGUAGE_DIR",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/../topiary-queries/queries"),
+        )
+        .current_dir(path)
+        .arg("fmt")
+        .arg("--check")
+        .arg("--staged")
+        .assert()
+        .code(11)
+        .stdout(format!(
+            "{}\n",
+            path.canonicalize().unwrap().join("staged.json").display()
+        ));
+
+    // Stage another file, without unstaged changes, so it's updated in the working tree too
+    fs::write(path.join("also_staged.json"), JSON_INPUT).unwrap();
+    git(path, &["add", "also_staged.json"]);
+
+    let mut topiary = topiary_command();
+
+    // Only the staged contents should be formatted, in the index
+    topiary
+        .env(
+            "TOPIARY_LANGUAGE_DIR",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/../topiary-queries/queries"),
+        )
+        .current_dir(path)
+        .arg("fmt")
+        .arg("--staged")
+        .assert()
+        .success();
+
+    assert_eq!(git(path, &["show", ":staged.json"]), JSON_EXPECTED);
+    assert_eq!(git(path, &["show", ":also_staged.json"]), JSON_EXPECTED);
+    assert_eq!(
+        fs::read_to_string(path.join("also_staged.json")).unwrap(),
+        JSON_EXPECTED
+    );
+    assert_eq!(
+        git(path, &["status", "--porcelain", "also_staged.json"]),
+        "A  also_staged.json\n"
+    );
+    assert_eq!(
+        fs::read_to_string(path.join("staged.json")).unwrap(),
+        "[1,2]"
+    );
+    assert_eq!(
+        fs::read_to_string(path.join("modified.json")).unwrap(),
+        JSON_INPUT
+    );
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_fmt_git_ignored() {
+    initialize();
+    let repo = git_repo();
+    let path = repo.path();
+
+    // Files selected from Git are still subject to ignore files and exclude globs, which are
+    // relative to the project root, as when walking any directory within it
+    fs::create_dir(path.join("vendor")).unwrap();
+    fs::write(path.join("vendor/lib.json"), JSON_INPUT).unwrap();
+    fs::write(path.join(".topiaryignore"), "staged.json\n").unwrap();
+    git(path, &["add", "vendor/lib.json"]);
+
+    let config = path.join("config.ncl");
+    fs::write(&config, r#"{ exclude = ["vendor/**"] }"#).unwrap();
+
+    let format = |selection: &[&str]| {
+        let mut topiary = topiary_command();
+        topiary
+            .env(
+                "TOPIARY_LANGUAGE_DIR",
+                concat!(env!("CARGO_MANIFEST_DIR"), "/../topiary-queries/queries"),
+            )
+            .current_dir(path)
+            .arg("fmt")
+            .arg("--configuration")
+            .arg(&config)
+            .args(selection)
+            .assert()
+            .success();
+    };
+
+    format(&["--staged"]);
+    assert_eq!(git(path, &["show", ":staged.json"]), JSON_INPUT);
+    assert_eq!(git(path, &["show", ":vendor/lib.json"]), JSON_INPUT);
+
+    format(&["--changed-since", "HEAD"]);
+    assert_eq!(
+        fs::read_to_string(path.join("modified.json")).unwrap(),
+        JSON_EXPECTED
+    );
+    for skipped in ["staged.json", "vendor/lib.json"] {
+        assert_eq!(fs::read_to_string(path.join(skipped)).unwrap(), JSON_INPUT);
+    }
+
+    format(&["vendor"]);
+    assert_eq!(
+        fs::read_to_string(path.join("vendor/lib.json")).unwrap(),
+        JSON_INPUT
+    );
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_fmt_invalid() {
+    initialize();
+
+    // Can't specify --stdin-filepath with input files
+    topiary_command()
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("fmt")
+        .arg("--stdin-filepath")
+        .arg("input.json")
+        .arg("/path/to/some/input")
+        .assert()
+        .code(2);
+
+    // Can't specify --query without --language
+    topiary_command()
+        .env("TOPIARY_LANGUAGE_DIR", "../whatever")
+        .arg("fmt")
+        .arg("--query")
+        .arg("/path/to/query")
+        .assert()
+        .code(2);
+
+    // Can't specify both --range and --lines
+    topiary_command()
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("fmt")
+        .arg("--range")
+        .arg("0:1")
+        .arg("--lines")
+        .arg("1-1")
+        .arg("/path/to/some/input")
+        .assert()
+        .code(2)
+        .stderr(predicates::str::contains("cannot be used with"));
+
+    // Ranges beyond the input are bad arguments, too
+    for (flag, range) in [("--range", "5:99"), ("--lines", "2-3")] {
+        topiary_command()
+            .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+            .arg("fmt")
+            .arg("--language")
+            .arg("json")
+            .arg(flag)
+            .arg(range)
+            .write_stdin("[1]\n")
+            .assert()
+            .code(2)
+            .stderr(predicates::str::contains("is not valid for the input"));
+    }
+
+    // Can't watch standard input
+    topiary_command()
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("fmt")
+        .arg("--watch")
+        .arg("--language")
+        .arg("json")
+        .write_stdin(JSON_INPUT)
+        .assert()
+        .failure();
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_playground() {
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Command, Stdio},
+    };
+
+    initialize();
+    let input = State::new(JSON_INPUT, "json");
+    let query = State::new(
+        &fs::read_to_string("../topiary-queries/queries/json.scm").unwrap(),
+        "scm",
+    );
+
+    let mut topiary = Command::new(assert_cmd::cargo::cargo_bin!("topiary"))
+        .arg("-vv")
+        .arg("playground")
+        .arg("--language")
+        .arg("json")
+        .arg("--query")
+        .arg(query.path())
+        .arg(input.path())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdout = BufReader::new(topiary.stdout.take().unwrap()).lines();
+    let mut stderr = BufReader::new(topiary.stderr.take().unwrap()).lines();
+
+    // Read the playground's output, up to its idempotence result
+    let mut render = || {
+        let mut lines = Vec::new();
+        for line in stdout.by_ref() {
+            let line = line.unwrap();
+            if line.starts_with("Idempotent") {
+                break;
+            }
+            lines.push(line);
+        }
+        lines
+    };
+
+    assert!(render().contains(&JSON_EXPECTED.trim_end().to_string()));
+
+    // Wait until the playground is watching, before changing the query
+    stderr
+        .by_ref()
+        .find(|line| line.as_ref().unwrap().contains("Watching"))
+        .unwrap()
+        .unwrap();
+    fs::write(query.path(), "(comment) @leaf\n").unwrap();
+
+    let rendered = render();
+    topiary.kill().unwrap();
+    topiary.wait().unwrap();
+
+    // The new query doesn't add any spacing, so the input's tokens are run together
+    assert!(rendered.contains(&r#"{"test":123}"#.to_string()));
+}
+
+#[test]
+#[cfg(all(unix, feature = "json"))]
+fn test_daemon() {
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Command, Stdio},
+    };
+
+    initialize();
+    let tmp_dir = TempDir::new().unwrap();
+    let socket = tmp_dir.path().join("daemon.sock");
+
+    // The daemon's own standard input is held open, so it would block on reading it
+    let mut daemon = Command::new(assert_cmd::cargo::cargo_bin!("topiary"))
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .env("TOPIARY_DAEMON_SOCKET", &socket)
+        .arg("-vv")
+        .arg("daemon")
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Wait until the daemon is listening, before sending it requests
+    BufReader::new(daemon.stderr.take().unwrap())
+        .lines()
+        .find(|line| line.as_ref().unwrap().contains("Listening"))
+        .unwrap()
+        .unwrap();
+
+    let client = |args: &[&str]| {
+        let mut topiary = topiary_command();
+        topiary
+            .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+            .env("TOPIARY_DAEMON_SOCKET", &socket)
+            .timeout(std::time::Duration::from_secs(30))
+            .arg("-vv")
+            .arg("fmt")
+            .args(args);
+        topiary
+    };
+
+    // Detecting the language from a path must not hold up the requests that follow
+    let detected = client(&["--stdin-filepath", "input.json"])
+        .write_stdin(JSON_INPUT)
+        .assert();
+    #[cfg(feature = "bash")]
+    let first_line = client(&["--stdin-filepath", "unsaved"])
+        .write_stdin("# vim: ft=sh\necho   hello\n")
+        .assert();
+    let formatted = client(&["--language", "json"])
+        .write_stdin(JSON_INPUT)
+        .assert();
+    let parsing_error = client(&["--language", "json"]).write_stdin("{").assert();
+
+    daemon.kill().unwrap();
+    daemon.wait().unwrap();
+
+    for assert in [detected, formatted] {
+        assert
+            .success()
+            .stdout(JSON_EXPECTED)
+            .stderr(predicates::str::contains("Forwarding to the daemon"));
+    }
+
+    // The first line is that of the request, rather than of the daemon's standard input
+    #[cfg(feature = "bash")]
+    first_line.success().stdout("# vim: ft=sh\necho hello\n");
+
+    // Errors exit with the same code as they would have locally
+    parsing_error.failure().code(5);
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_lsp() {
+    use std::{
+        io::{BufRead, BufReader, Read},
+        process::{Command, Stdio},
+    };
+
+    use serde_json::{Value, json};
+
+    initialize();
+    let mut topiary = Command::new(assert_cmd::cargo::cargo_bin!("topiary"))
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = topiary.stdin.take().unwrap();
+    let mut stdout = BufReader::new(topiary.stdout.take().unwrap());
+
+    let mut send = |message: Value| {
+        let message = message.to_string();
+        write!(stdin, "Content-Length: {}\r\n\r\n{message}", message.len()).unwrap();
+        stdin.flush().unwrap();
+    };
+
+    // Read messages until we get one with the given method (for notifications) or id (for responses)
+    let mut receive = |key: &str, value: Value| loop {
+        let mut header = String::new();
+        stdout.read_line(&mut header).unwrap();
+        let length: usize = header["Content-Length: ".len()..].trim().parse().unwrap();
+        stdout.read_line(&mut String::new()).unwrap();
+
+        let mut body = vec![0; length];
+        stdout.read_exact(&mut body).unwrap();
+
+        let message: Value = serde_json::from_slice(&body).unwrap();
+        if message[key] == value {
+            break message;
+        }
+    };
+
+    let uri = "file:///path/to/document.json";
+    let formatting = |id: u64| {
+        json!({"jsonrpc": "2.0", "id": id, "method": "textDocument/formatting", "params": {
+            "textDocument": {"uri": uri}, "options": {"tabSize": 2, "insertSpaces": true}
+        }})
+    };
+
+    send(
+        json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"capabilities": {}}}),
+    );
+    let response = receive("id", json!(1));
+    assert_eq!(
+        response["result"]["capabilities"]["documentRangeFormattingProvider"],
+        json!(true)
+    );
+
+    send(json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}));
+    send(
+        json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+            "textDocument": {"uri": uri, "languageId": "json", "version": 1, "text": JSON_INPUT}
+        }}),
+    );
+
+    let diagnostics = receive("method", json!("textDocument/publishDiagnostics"));
+    assert_eq!(diagnostics["params"]["diagnostics"], json!([]));
+
+    // The edit only spans what changed
+    send(formatting(2));
+    let response = receive("id", json!(2));
+    assert_eq!(
+        response["result"],
+        json!([{
+            "range": {"start": {"line": 0, "character": 2}, "end": {"line": 0, "character": 17}},
+            "newText": "\"test\": 123 }\n"
+        }])
+    );
+
+    // Parsing errors are reported as diagnostics, rather than failing formatting
+    send(
+        json!({"jsonrpc": "2.0", "method": "textDocument/didChange", "params": {
+            "textDocument": {"uri": uri, "version": 2},
+            "contentChanges": [{"text": "{\"foo\" \"bar\"}"}]
+        }}),
+    );
+
+    let diagnostics = receive("method", json!("textDocument/publishDiagnostics"));
+    assert_eq!(
+        diagnostics["params"]["diagnostics"][0]["severity"],
+        json!(1)
+    );
+
+    send(formatting(3));
+    assert_eq!(receive("id", json!(3))["result"], Value::Null);
+
+    send(json!({"jsonrpc": "2.0", "id": 4, "method": "shutdown"}));
+    receive("id", json!(4));
+    send(json!({"jsonrpc": "2.0", "method": "exit"}));
+
+    drop(stdin);
+    assert!(topiary.wait().unwrap().success());
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_vis() {
+    use predicates::{
+        prelude::PredicateBooleanExt,
+        str::{ends_with, starts_with},
+    };
+
+    initialize();
+    let mut topiary = topiary_command();
+
+    // Sanity check output is a valid DOT graph
+    let is_graph = starts_with("graph {").and(ends_with("}\n"));
+
+    topiary
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("vis")
+        .arg("--language")
+        .arg("json")
+        .write_stdin(JSON_INPUT)
+        .assert()
+        .success()
+        .stdout(is_graph);
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_vis_formats() {
+    use predicates::{prelude::PredicateBooleanExt, str::contains};
+
+    initialize();
+
+    let visualise = |format: &str| {
+        let mut topiary = topiary_command();
+        topiary
+            .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+            .arg("vis")
+            .arg("--format")
+            .arg(format)
+            .arg("--language")
+            .arg("json")
+            .write_stdin(JSON_INPUT)
+            .assert()
+            .success()
+    };
+
+    visualise("sexp").stdout(concat!(
+        "(document [0, 0] - [0, 17]\n",
+        "  (object [0, 0] - [0, 17]\n",
+        "    (pair [0, 4] - [0, 16]\n",
+        "      key: (string [0, 4] - [0, 10]\n",
+        "        (string_content [0, 5] - [0, 9]))\n",
+        "      value: (number [0, 13] - [0, 16]))))\n",
+    ));
+
+    visualise("mermaid").stdout(
+        predicates::str::starts_with("graph TD\n")
+            .and(contains("(\"pair\")"))
+            .and(contains("[\"#quot;\"]"))
+            .and(contains(" -->|value| ")),
+    );
+
+    visualise("html").stdout(
+        predicates::str::starts_with("<!DOCTYPE html>")
+            .and(contains(
+                "<pre id=\"source\">{   &quot;test&quot;  :123}</pre>",
+            ))
+            .and(contains("<summary data-start=\"4\" data-end=\"16\">")),
+    );
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_vis_details() {
+    use predicates::{prelude::PredicateBooleanExt, str::contains};
+
+    initialize();
+
+    let visualise = |format: &str, input: &str| {
+        let mut topiary = topiary_command();
+        topiary
+            .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+            .arg("vis")
+            .arg("--format")
+            .arg(format)
+            .arg("--leaf-text")
+            .arg("--byte-ranges")
+            .arg("--language")
+            .arg("json")
+            .write_stdin(input.to_string())
+            .assert()
+            .success()
+    };
+
+    visualise("json", JSON_INPUT).stdout(
+        contains(r#""field":"key""#).and(contains(r#""start_byte":13,"end_byte":16,"text":"123""#)),
+    );
+
+    visualise("dot", JSON_INPUT).stdout(
+        contains(r#"[label="value"];"#).and(contains(r#"[label="number\n[13..16]\n\"123\"""#)),
+    );
+
+    // Leaf text need not be ASCII
+    visualise("dot", r#"{"é": 1}"#).stdout(contains(
+        r#"[label="string_content\n[2..4]\n\"é\"", shape=ellipse];"#,
+    ));
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_vis_subtree() {
+    use predicates::str::{contains, starts_with};
+
+    initialize();
+
+    let visualise = |args: &[&str]| {
+        let mut topiary = topiary_command();
+        topiary
+            .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+            .arg("vis")
+            .args(args)
+            .arg("--language")
+            .arg("json")
+            .write_stdin(JSON_INPUT)
+            .assert()
+    };
+
+    // Pruned children are elided
+    visualise(&["--format", "sexp", "--kind", "pair", "--depth", "1"])
+        .success()
+        .stdout(concat!(
+            "(pair [0, 4] - [0, 16]\n",
+            "  key: (string [0, 4] - [0, 10] ...)\n",
+            "  value: (number [0, 13] - [0, 16]))\n",
+        ));
+
+    visualise(&["--format", "json", "--at", "1:14"])
+        .success()
+        .stdout(starts_with(r#"{"kind":"number","#));
+
+    visualise(&["--format", "dot", "--range", "4:10", "--depth", "0"])
+        .success()
+        .stdout(contains(r#"[label="... 3 more", shape=plaintext];"#));
+
+    // Selections that aren't in the input are bad arguments
+    visualise(&["--kind", "array"]).code(2).stderr(contains(
+        "There are no nodes of kind \"array\" in the input",
+    ));
+
+    visualise(&["--at", "9:1"])
+        .code(2)
+        .stderr(contains("Position (9,1) is beyond the end of the input"));
+
+    visualise(&["--format", "atoms", "--depth", "1"])
+        .failure()
+        .stderr(contains("only apply to parse tree formats"));
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_vis_atoms() {
+    use predicates::str::contains;
+    use serde_json::Value;
+
+    initialize();
+
+    let query = State::new(
+        concat!(
+            "((object) @prepend_begin_scope @append_end_scope (#scope_id! \"obj\"))\n",
+            "((pair \":\" @append_spaced_scoped_softline) (#scope_id! \"obj\"))\n",
+        ),
+        "scm",
+    );
+
+    // Scopes are listed, and scoped softlines are shown with their expansion
+    let mut topiary = topiary_command();
+    topiary
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("vis")
+        .arg("--format")
+        .arg("atoms")
+        .arg("--language")
+        .arg("json")
+        .arg("--query")
+        .arg(query.path())
+        .write_stdin(JSON_INPUT)
+        .assert()
+        .success()
+        .stdout(contains("ScopeBegin \"obj\" at line 1"))
+        .stdout(contains("\"123\" at (1,14)\n"))
+        .stdout(contains(
+            "ScopedSoftline #1 in \"obj\" (spaced)   =>  Space\n",
+        ));
+
+    let mut topiary = topiary_command();
+    let output = topiary
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("vis")
+        .arg("--format")
+        .arg("atoms-raw")
+        .arg("--language")
+        .arg("json")
+        .arg("--query")
+        .arg(query.path())
+        .write_stdin(JSON_INPUT)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let atoms: Value = serde_json::from_slice(&output).unwrap();
+    let (before, after) = (
+        atoms["before"].as_array().unwrap(),
+        atoms["after"].as_array().unwrap(),
+    );
+    assert_eq!(before.len(), after.len());
+    assert_eq!(before[0]["ScopeBegin"]["scope_id"], "obj");
+    assert_eq!(after[0], "Empty");
+    assert_eq!(before[6]["ScopedSoftline"]["spaced"], true);
+    assert_eq!(after[6], "Space");
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_vis_invalid() {
+    initialize();
+    let mut topiary = topiary_command();
+
+    // Can't specify --stdin-filepath with input file
+    topiary
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("vis")
+        .arg("--stdin-filepath")
+        .arg("input.json")
+        .arg("/path/to/some/input")
+        .assert()
+        .failure();
+
+    // Can't specify --query without --language
+    topiary
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("vis")
+        .arg("--query")
+        .arg("/path/to/query")
+        .assert()
+        .failure();
+
+    // Can't specify multiple input files
+    topiary
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("vis")
+        .arg("/path/to/some/input")
+        .arg("/path/to/another/input")
+        .assert()
+        .failure();
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_coverage_report() {
+    use serde_json::Value;
+
+    initialize();
+    let mut topiary = topiary_command();
+
+    let output = topiary
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("coverage")
+        .arg("--report")
+        .arg("json")
+        .arg("--language")
+        .arg("json")
+        .write_stdin(JSON_INPUT)
+        .assert()
+        .code(1)
+        .get_output()
+        .stdout
+        .clone();
+
+    let report: Value = serde_json::from_slice(&output).unwrap();
+    let result = &report["results"][0];
+    assert_eq!(result["file"], Value::Null);
+    assert_eq!(result["status"], "uncovered");
+    assert!(
+        result["coverage"]["query"]
+            .as_str()
+            .unwrap()
+            .ends_with("json.scm")
+    );
+
+    // Missing patterns are located within the query file
+    let missing = result["coverage"]["missing_patterns"].as_array().unwrap();
+    assert!(!missing.is_empty());
+    assert!(missing[0]["span"]["start"]["line"].as_u64().unwrap() > 1);
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_coverage_many() {
+    use serde_json::Value;
+
+    initialize();
+
+    // The first input does not cover the query on its own, but the inputs do between them
+    let pair = State::new(JSON_INPUT, "json");
+    let pairs = State::new(r#"{"a": [1, 2], "b": 3}"#, "json");
+
+    let coverage = |input: &State| {
+        let mut topiary = topiary_command();
+        topiary
+            .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+            .arg("coverage")
+            .arg(input.path())
+            .assert()
+    };
+    coverage(&pair).code(1);
+
+    let mut topiary = topiary_command();
+    let output = topiary
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("coverage")
+        .arg("--report")
+        .arg("json")
+        .arg("--covered-by")
+        .arg(pair.path())
+        .arg(pairs.path())
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let report: Value = serde_json::from_slice(&output).unwrap();
+    let results = report["results"].as_array().unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0]["status"], "ok");
+
+    let coverage = &results[0]["coverage"];
+    assert_eq!(coverage["inputs"].as_array().unwrap().len(), 2);
+    assert!(coverage["missing_patterns"].as_array().unwrap().is_empty());
+
+    // Only the input with several pairs has commas in an object
+    let pairs_path = pairs.path().to_str().unwrap();
+    let covered = coverage["covered_patterns"].as_array().unwrap();
+    assert!(covered.iter().any(|pattern| {
+        pattern["inputs"].as_array().unwrap() == &vec![Value::from(pairs_path)]
+    }));
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_coverage_nodes() {
+    use predicates::str::contains;
+
+    initialize();
+
+    let coverage = |input: &str| {
+        let mut topiary = topiary_command();
+        topiary
+            .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+            .arg("coverage")
+            .arg("--nodes")
+            .arg("--language")
+            .arg("json")
+            .write_stdin(input)
+            .assert()
+    };
+
+    // Strings are leaves, and pairs contain a captured colon, but nothing touches numbers
+    coverage(r#"{"a": 1}"#)
+        .code(1)
+        .stdout(contains("json.scm:\n  number      1  standard input:1:7\n"));
+
+    coverage(r#"{"a": "b"}"#)
+        .success()
+        .stdout(contains("All node kinds are handled by"));
+}
+
+#[test]
+#[cfg(all(feature = "json", feature = "tree_sitter_query"))]
+fn test_coverage_grammar() {
+    use predicates::{prelude::PredicateBooleanExt, str::contains};
+
+    initialize();
+
+    // No input is read, so nothing is written to standard input
+    let mut topiary = topiary_command();
+    topiary
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("coverage")
+        .arg("--grammar")
+        .arg("--language")
+        .arg("json")
+        .assert()
+        .code(1)
+        .stdout(contains(
+            "Node kinds of the json grammar not referred to by",
+        ))
+        .stdout(contains("  null\n"))
+        .stdout(contains("Fields of the json grammar not referred to by"))
+        .stdout(contains("  value\n"))
+        .stdout(contains("  pair\n").not());
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_query() {
+    use serde_json::Value;
+
+    initialize();
+
+    // Inline patterns; captures are printed as they are, rather than applied as formatting
+    let mut topiary = topiary_command();
+    topiary
+        .arg("query")
+        .arg("--language")
+        .arg("json")
+        .arg("--query")
+        .arg("(pair value: (number) @append_space)")
+        .write_stdin(JSON_INPUT)
+        .assert()
+        .success()
+        .stdout("pattern 0\n  @append_space {Node \"number\" (1,14) - (1,17)} \"123\"\n");
+
+    // A query file, with JSON output
+    let query = State::new("(string) @a\n(number) @b\n", "scm");
+    let input = State::new(JSON_INPUT, "json");
+
+    let mut topiary = topiary_command();
+    let output = topiary
+        .arg("query")
+        .arg("--format")
+        .arg("json")
+        .arg("--query")
+        .arg(query.path())
+        .arg(input.path())
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let matches: Value = serde_json::from_slice(&output).unwrap();
+    let matches = matches.as_array().unwrap();
+    assert_eq!(matches.len(), 2);
+    assert_eq!(matches[1]["pattern_index"], 1);
+    assert_eq!(matches[1]["captures"][0]["name"], "b");
+    assert_eq!(matches[1]["captures"][0]["kind"], "number");
+    assert_eq!(matches[1]["captures"][0]["start"]["column"], 14);
+    assert_eq!(matches[1]["captures"][0]["text"], "123");
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_explain() {
+    use serde_json::Value;
+
+    initialize();
+
+    // The space is created, then dropped in favour of the hardline
+    let query = State::new(
+        "(pair \":\" @append_space)\n(pair value: (_) @prepend_hardline)\n",
+        "scm",
+    );
+
+    let mut topiary = topiary_command();
+    topiary
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("explain")
+        .arg("--language")
+        .arg("json")
+        .arg("--query")
+        .arg(query.path())
+        .arg("--at")
+        .arg("1:14")
+        .write_stdin(JSON_INPUT)
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "  #0 Space: @append_space on {Node \":\" (1,13) - (1,14)}, from pattern 0 at (1,1)\n      dropped: dominated by the adjacent Hardline (#1)\n",
+        ))
+        .stdout(predicates::str::contains("  = Hardline\n"));
+
+    // JSON output, with the configured query
+    let input = State::new(JSON_INPUT, "json");
+
+    let mut topiary = topiary_command();
+    let output = topiary
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("explain")
+        .arg("--format")
+        .arg("json")
+        .arg("--at")
+        .arg("1:14")
+        .arg(input.path())
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let explanation: Value = serde_json::from_slice(&output).unwrap();
+    assert_eq!(explanation["leaf"]["text"], "123");
+    assert_eq!(explanation["before"]["atoms"][0]["atom"], "Space");
+    assert_eq!(
+        explanation["before"]["atoms"][0]["origin"]["capture"],
+        "append_space"
+    );
+
+    // Nothing is output past the end of the input
+    let mut topiary = topiary_command();
+    topiary
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("explain")
+        .arg("--language")
+        .arg("json")
+        .arg("--at")
+        .arg("2:1")
+        .write_stdin(JSON_INPUT)
+        .assert()
+        .failure();
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_golden() {
+    use predicates::str::contains;
+
+    initialize();
+
+    let tmp_dir = TempDir::new().unwrap();
+    let dir = tmp_dir.path();
+    fs::create_dir_all(dir.join("input/nested")).unwrap();
+    fs::create_dir_all(dir.join("expected")).unwrap();
+    fs::write(dir.join("input/good.json"), JSON_INPUT).unwrap();
+    fs::write(dir.join("expected/good.json"), JSON_EXPECTED).unwrap();
+    fs::write(dir.join("input/nested/bad.json"), JSON_INPUT).unwrap();
+
+    let test = |bless: bool| {
+        let mut topiary = topiary_command();
+        topiary
+            .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+            .arg("test")
+            .arg(dir);
+
+        if bless {
+            topiary.arg("--bless");
+        }
+
+        topiary.assert()
+    };
+
+    // An input without expected output fails, until it's blessed
+    test(false)
+        .code(1)
+        .stdout(contains("test good.json ... ok"))
+        .stdout(contains("test nested/bad.json ... FAILED"))
+        .stdout(contains("test result: FAILED. 1 passed; 1 failed"));
+
+    test(true)
+        .success()
+        .stdout(contains("test nested/bad.json ... blessed"));
+    assert_eq!(
+        fs::read_to_string(dir.join("expected/nested/bad.json")).unwrap(),
+        JSON_EXPECTED
+    );
+
+    // Differences from the expected output are given as a diff
+    fs::write(dir.join("expected/good.json"), "{}\n").unwrap();
+    test(false)
+        .code(1)
+        .stdout(contains("test good.json ... FAILED"))
+        .stdout(contains("-{}\n+{ \"test\": 123 }\n"));
+
+    test(false).stdout(contains("test nested/bad.json ... ok"));
+}
+
+#[test]
+fn test_init_language() {
+    use predicates::str::contains;
+
+    let tmp_dir = TempDir::new().unwrap();
+    fs::create_dir_all(tmp_dir.path().join("queries")).unwrap();
+    fs::write(tmp_dir.path().join("queries/mylang.scm"), "").unwrap();
+
+    let init_language = |name: &str| {
+        let mut topiary = topiary_command();
+        topiary
+            .arg("init-language")
+            .arg(name)
+            .arg("--git")
+            .arg("https://example.com/tree-sitter-mylang.git")
+            .arg("--rev")
+            .arg("main")
+            .arg("--output")
+            .arg(tmp_dir.path())
+            .assert()
+    };
+
+    // Both are checked before the grammar is fetched
+    init_language("my-lang")
+        .failure()
+        .stderr(contains("Invalid language name"));
+
+    init_language("mylang")
+        .failure()
+        .stderr(contains("mylang.scm already exists"));
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_doctor() {
+    use predicates::str::contains;
+
+    let mut topiary = topiary_command();
+    topiary
+        .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
+        .arg("doctor")
+        .arg("json")
+        .assert()
+        .success()
+        .stdout(contains("│ json "))
+        .stdout(contains("json.scm"))
+        .stdout(contains("<built-in>"));
+
+    let mut topiary = topiary_command();
+    topiary.arg("doctor").arg("nonexistent").assert().failure();
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_grammars() {
+    use predicates::{prelude::PredicateBooleanExt, str::contains};
+
+    // The cache directory is only taken from the environment on Linux
+    let tmp_dir = TempDir::new().unwrap();
+    let cache_dir = tmp_dir.path().join("topiary");
+    fs::create_dir_all(cache_dir.join("json")).unwrap();
+    fs::create_dir_all(cache_dir.join("formatted")).unwrap();
+    fs::write(cache_dir.join("json/deadbeef.so"), "not a library").unwrap();
+    fs::write(cache_dir.join("formatted/0123abcd"), "").unwrap();
+
+    let grammars = |args: &[&str]| {
+        let mut topiary = topiary_command();
+        topiary
+            .env("XDG_CACHE_HOME", tmp_dir.path())
+            .arg("grammars")
+            .args(args)
+            .assert()
+    };
+
+    grammars(&["list"])
+        .success()
+        .stdout(contains("deadbeef"))
+        .stdout(contains("13 B"))
+        .stdout(contains("formatted").not());
+
+    grammars(&["verify"])
+        .failure()
+        .stdout(contains("deadbeef.so ... FAILED (tree_sitter_json)"));
+
+    // A dry run only lists what would be removed
+    grammars(&["clean", "--dry-run"])
+        .success()
+        .stdout(contains("Would remove 1 unused grammar, freeing 13 B"));
+
+    assert!(cache_dir.join("json/deadbeef.so").exists());
+
+    grammars(&["clean"])
+        .success()
+        .stdout(contains("Removed 1 unused grammar, freeing 13 B"));
+
+    assert!(!cache_dir.join("json").exists());
+    assert!(cache_dir.join("formatted/0123abcd").exists());
+
+    grammars(&["list"])
+        .success()
+        .stdout(contains("No grammars in"));
+}
+
+#[test]
+fn test_cfg() {
+    let mut topiary = topiary_command();
 
     topiary
         .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries")
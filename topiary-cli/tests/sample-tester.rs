@@ -5,6 +5,16 @@ use topiary_core::test_utils::pretty_assert_eq;
 
 use tempfile::TempDir;
 
+/// The Topiary binary, with its cache of formatted inputs kept out of the user's cache directory
+fn topiary_command() -> assert_cmd::Command {
+    let mut topiary = cargo_bin_cmd!("topiary");
+    topiary.env(
+        "TOPIARY_FORMAT_CACHE_DIR",
+        concat!(env!("CARGO_TARGET_TMPDIR"), "/formatted"),
+    );
+    topiary
+}
+
 fn get_file_extension(language: &str) -> &str {
     match language {
         "bash" => "sh",
@@ -60,7 +70,7 @@ mod test_fmt {
         fs::copy(input, &staged).unwrap();
 
         // Run Topiary against the staged input file
-        let mut topiary = cargo_bin_cmd!("topiary");
+        let mut topiary = topiary_command();
         let output = topiary
             .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries/")
             .arg("fmt")
@@ -116,7 +126,7 @@ mod test_fmt {
             fs::copy(file.path(), &input_file).unwrap();
 
             // Run topiary on the input file in the temp dir
-            let mut topiary = cargo_bin_cmd!("topiary");
+            let mut topiary = topiary_command();
             topiary
                 .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries/")
                 .arg("fmt")
@@ -145,7 +155,7 @@ mod test_coverage {
         assert!(input.exists());
 
         // Run `topiary coverage` against the input file
-        let mut topiary = cargo_bin_cmd!("topiary");
+        let mut topiary = topiary_command();
         let output = topiary
             .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries/")
             .arg("coverage")
@@ -198,7 +208,7 @@ fn formatted_query_tester() {
         fs::copy(file.path(), &input_file).unwrap();
 
         // Run topiary on the input file in the temp dir
-        let mut topiary = cargo_bin_cmd!("topiary");
+        let mut topiary = topiary_command();
         topiary
             .env("TOPIARY_LANGUAGE_DIR", "../topiary-queries/queries/")
             .arg("fmt")
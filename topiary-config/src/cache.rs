@@ -0,0 +1,133 @@
+//! The grammars that Topiary has compiled, which are cached by language and revision, so they only
+//! need to be fetched and compiled once.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    Configuration,
+    error::TopiaryConfigFetchingError,
+    language::{GrammarSource, default_symbol, load_grammar},
+};
+
+/// A compiled grammar in the cache
+#[derive(Debug)]
+pub struct CachedGrammar {
+    /// The name of the language that the grammar was compiled for
+    pub language: String,
+    /// The revision of the grammar, as given by the name of its library
+    pub revision: String,
+    pub path: PathBuf,
+    /// The size of the library, in bytes
+    pub size: u64,
+}
+
+/// The directory of compiled grammars
+pub fn cache_dir() -> PathBuf {
+    crate::project_dirs().cache_dir().to_path_buf()
+}
+
+/// The path of the compiled grammar of the language at the given revision
+pub(crate) fn library_path(language: &str, revision: &str) -> PathBuf {
+    let mut library_path = cache_dir();
+    library_path.push(language);
+
+    // Set the output path as the revision of the grammar,
+    // with a platform-appropriate extension
+    library_path.push(revision);
+    library_path.set_extension(std::env::consts::DLL_EXTENSION);
+
+    library_path
+}
+
+/// All of the compiled grammars in the cache, ordered by language and revision. Libraries are in
+/// a directory per language; anything else in the cache is not a grammar.
+pub fn cached_grammars() -> io::Result<Vec<CachedGrammar>> {
+    let mut grammars = Vec::new();
+
+    let languages = match fs::read_dir(cache_dir()) {
+        Ok(languages) => languages,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(grammars),
+        Err(e) => return Err(e),
+    };
+
+    for language in languages {
+        let language = language?;
+        if !language.file_type()?.is_dir() {
+            continue;
+        }
+
+        for library in fs::read_dir(language.path())? {
+            let library = library?;
+            let path = library.path();
+            let metadata = library.metadata()?;
+
+            if !metadata.is_file()
+                || path.extension() != Some(std::env::consts::DLL_EXTENSION.as_ref())
+            {
+                continue;
+            }
+
+            grammars.push(CachedGrammar {
+                language: language.file_name().to_string_lossy().into_owned(),
+                revision: path
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+                path,
+                size: metadata.len(),
+            });
+        }
+    }
+
+    grammars.sort_by(|a, b| (&a.language, &a.revision).cmp(&(&b.language, &b.revision)));
+    Ok(grammars)
+}
+
+impl CachedGrammar {
+    /// Whether the configuration has a language that uses this grammar
+    pub fn is_used_by(&self, config: &Configuration) -> bool {
+        config.languages().iter().any(|language| {
+            language.name == self.language
+                && matches!(
+                    &language.config.grammar.source,
+                    GrammarSource::Git(git_source)
+                        if library_path(&language.name, &git_source.rev) == self.path
+                )
+        })
+    }
+
+    /// The symbol that the grammar is expected to have: that of its language, if the
+    /// configuration has it, or otherwise the default
+    pub fn symbol(&self, config: &Configuration) -> String {
+        config
+            .get_language(&self.language)
+            .map_or_else(|_| default_symbol(&self.language), |l| l.symbol())
+    }
+
+    /// Load the grammar, through its expected symbol
+    pub fn load(
+        &self,
+        config: &Configuration,
+    ) -> Result<topiary_tree_sitter_facade::Language, TopiaryConfigFetchingError> {
+        load_grammar(&self.path, &self.symbol(config))
+    }
+
+    /// Remove the grammar, and its language's directory if nothing else is left in it
+    pub fn remove(&self) -> io::Result<()> {
+        fs::remove_file(&self.path)?;
+
+        if let Some(dir) = self.path.parent().filter(|dir| is_empty_dir(dir)) {
+            fs::remove_dir(dir)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn is_empty_dir(dir: &Path) -> bool {
+    fs::read_dir(dir).is_ok_and(|mut entries| entries.next().is_none())
+}
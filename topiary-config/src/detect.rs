@@ -0,0 +1,65 @@
+//! Helpers to detect a file's language from its first line, for when its path is not enough.
+
+/// The interpreter named by a shebang (e.g., "bash", for both "#!/bin/bash" and
+/// "#!/usr/bin/env bash")
+pub(crate) fn interpreter(line: &str) -> Option<&str> {
+    let mut words = line.strip_prefix("#!")?.split_whitespace();
+    let program = basename(words.next()?);
+
+    if program != "env" {
+        return Some(program);
+    }
+
+    // Skip over env's options and variable assignments, to the program that it runs
+    words
+        .find(|word| !word.starts_with('-') && !word.contains('='))
+        .map(basename)
+}
+
+/// The file type given by an Emacs (e.g., "-*- mode: sh -*-") or Vim (e.g., "vim: set ft=sh:")
+/// modeline, in lowercase
+pub(crate) fn modeline(line: &str) -> Option<String> {
+    emacs_mode(line)
+        .or_else(|| vim_filetype(line))
+        .map(str::to_lowercase)
+}
+
+fn basename(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+fn emacs_mode(line: &str) -> Option<&str> {
+    let (_, rest) = line.split_once("-*-")?;
+    let (variables, _) = rest.split_once("-*-")?;
+
+    // The modeline is either just the mode, or a list of variables that may include it
+    if !variables.contains(':') {
+        return Some(variables.trim()).filter(|mode| !mode.is_empty());
+    }
+
+    variables
+        .split(';')
+        .filter_map(|variable| variable.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("mode"))
+        .map(|(_, mode)| mode.trim())
+}
+
+fn vim_filetype(line: &str) -> Option<&str> {
+    // The modeline marker must start the line, or follow whitespace (e.g., after a comment)
+    let start = ["vim:", "vi:", "ex:"]
+        .iter()
+        .filter_map(|marker| {
+            line.match_indices(marker)
+                .find(|(idx, _)| *idx == 0 || line[..*idx].ends_with(char::is_whitespace))
+                .map(|(idx, marker)| idx + marker.len())
+        })
+        .min()?;
+
+    line[start..]
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .find_map(|option| {
+            option
+                .strip_prefix("ft=")
+                .or_else(|| option.strip_prefix("filetype="))
+        })
+}
@@ -51,7 +51,7 @@ impl fmt::Display for TopiaryConfigError {
             ),
             TopiaryConfigError::NoExtension(path) => write!(
                 f,
-                "You tried to format {} without specifying a language, but we cannot automatically detect the language because we can't find the filetype extension.",
+                "You tried to format {} without specifying a language, but we cannot automatically detect the language from its name, extension or first line.",
                 path.display()
             ),
             #[cfg(not(target_arch = "wasm32"))]
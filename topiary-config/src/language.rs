@@ -15,7 +15,7 @@ use std::collections::HashSet;
 #[cfg(not(target_arch = "wasm32"))]
 use std::num::NonZero;
 #[cfg(not(target_arch = "wasm32"))]
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::error::TopiaryConfigResult;
 #[cfg(not(target_arch = "wasm32"))]
@@ -40,6 +40,21 @@ pub struct LanguageConfiguration {
     /// switch to the right language based on the input filename.
     pub extensions: HashSet<String>,
 
+    /// A set of file names (without their directory) associated with this language, for files
+    /// that have no distinguishing extension (e.g., ".bashrc").
+    #[serde(default)]
+    pub filenames: HashSet<String>,
+
+    /// Glob patterns associated with this language. Patterns without a slash are matched against
+    /// the file name; otherwise, against the whole path.
+    #[serde(default)]
+    pub patterns: Vec<String>,
+
+    /// A set of interpreters associated with this language. These are matched against the shebang
+    /// of files that cannot otherwise be detected (e.g., "bash" matches "#!/usr/bin/env bash").
+    #[serde(default)]
+    pub interpreters: HashSet<String>,
+
     /// The indentation string used for this language; defaults to "  " (i.e., two spaces). Any
     /// string can be provided, but in most instances it will be some whitespace (e.g., "    ",
     /// "\t", etc.)
@@ -118,14 +133,8 @@ impl Language {
     pub fn library_path(&self) -> std::io::Result<PathBuf> {
         match &self.config.grammar.source {
             GrammarSource::Git(git_source) => {
-                let mut library_path = crate::project_dirs().cache_dir().to_path_buf();
-                library_path.push(self.name.clone());
-                std::fs::create_dir_all(&library_path)?;
-
-                // Set the output path as the revision of the grammar,
-                // with a platform-appropriate extension
-                library_path.push(git_source.rev.clone());
-                library_path.set_extension(std::env::consts::DLL_EXTENSION);
+                let library_path = crate::cache::library_path(&self.name, &git_source.rev);
+                std::fs::create_dir_all(library_path.parent().unwrap())?;
 
                 Ok(library_path)
             }
@@ -157,24 +166,16 @@ impl Language {
         }
 
         assert!(library_path.is_file());
-        log::debug!("Loading grammar from {}", library_path.display());
-
-        use libloading::{Library, Symbol};
-
-        let library = unsafe { Library::new(&library_path) }?;
-        let language_fn_name = if let Some(symbol_name) = self.config.grammar.symbol.clone() {
-            symbol_name
-        } else {
-            format!("tree_sitter_{}", self.name.replace('-', "_"))
-        };
+        load_grammar(&library_path, &self.symbol())
+    }
 
-        let language = unsafe {
-            let language_fn: Symbol<unsafe extern "C" fn() -> *const ()> =
-                library.get(language_fn_name.as_bytes())?;
-            tree_sitter_language::LanguageFn::from_raw(*language_fn)
-        };
-        std::mem::forget(library);
-        Ok(topiary_tree_sitter_facade::Language::from(language))
+    /// The symbol of the language in its compiled grammar
+    pub fn symbol(&self) -> String {
+        self.config
+            .grammar
+            .symbol
+            .clone()
+            .unwrap_or_else(|| default_symbol(&self.name))
     }
 
     #[cfg(target_arch = "wasm32")]
@@ -200,6 +201,31 @@ impl Language {
     }
 }
 
+/// The symbol of a language in its compiled grammar, unless the configuration says otherwise
+pub(crate) fn default_symbol(language: &str) -> String {
+    format!("tree_sitter_{}", language.replace('-', "_"))
+}
+
+/// Load a compiled grammar, through the function with the given symbol
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn load_grammar(
+    library_path: &Path,
+    symbol: &str,
+) -> Result<topiary_tree_sitter_facade::Language, TopiaryConfigFetchingError> {
+    log::debug!("Loading grammar from {}", library_path.display());
+
+    use libloading::{Library, Symbol};
+
+    let library = unsafe { Library::new(library_path) }?;
+    let language = unsafe {
+        let language_fn: Symbol<unsafe extern "C" fn() -> *const ()> =
+            library.get(symbol.as_bytes())?;
+        tree_sitter_language::LanguageFn::from_raw(*language_fn)
+    };
+    std::mem::forget(library);
+    Ok(topiary_tree_sitter_facade::Language::from(language))
+}
+
 type Result<T, E = TopiaryConfigFetchingError> = std::result::Result<T, E>;
 
 trait GitResult<T> {
@@ -1,16 +1,24 @@
 //! Topiary can be configured using the `Configuration` struct.
 //! A basic configuration, written in Nickel, is included at build time and parsed at runtime.
 //! Additional configuration has to be provided by the user of the library.
+#[cfg(not(target_arch = "wasm32"))]
+pub mod cache;
+mod detect;
 pub mod error;
 pub mod language;
 pub mod source;
 
 use std::{
+    cmp::Reverse,
     collections::HashMap,
     fmt,
+    fs::File,
+    io::{BufRead, BufReader, Read},
     path::{Path, PathBuf},
 };
 
+use globset::{Glob, GlobMatcher};
+
 use language::{Language, LanguageConfiguration};
 use nickel_lang_core::{
     error::NullReporter, eval::cache::CacheImpl, program::Program, term::RichTerm,
@@ -34,12 +42,29 @@ pub use source::Source;
 #[derive(Debug)]
 pub struct Configuration {
     languages: Vec<Language>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    patterns: Vec<Pattern>,
+}
+
+/// A language's glob pattern, compiled for matching against paths
+#[derive(Debug)]
+struct Pattern {
+    matcher: GlobMatcher,
+    // Patterns with a slash match the whole path; otherwise, just the file name
+    whole_path: bool,
+    // Index into the configuration's languages
+    language: usize,
 }
 
 /// Internal struct to help with deserialisation, converted to the actual Configuration in deserialization
 #[derive(Debug, serde::Deserialize, PartialEq, serde::Serialize, Clone)]
 struct SerdeConfiguration {
     languages: HashMap<String, LanguageConfiguration>,
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
 }
 
 impl Configuration {
@@ -93,6 +118,23 @@ impl Configuration {
             .ok_or(TopiaryConfigError::UnknownLanguage(name.to_string()))
     }
 
+    /// Glob patterns, in `.gitignore` syntax, that files found by traversing directories must
+    /// match to be formatted. An empty list matches everything.
+    pub fn include(&self) -> &[String] {
+        &self.include
+    }
+
+    /// Glob patterns, in `.gitignore` syntax, that exclude files found by traversing directories
+    /// from being formatted
+    pub fn exclude(&self) -> &[String] {
+        &self.exclude
+    }
+
+    /// The configured languages
+    pub fn languages(&self) -> &[Language] {
+        &self.languages
+    }
+
     /// Prefetch a language per its configuration
     ///
     /// # Errors
@@ -198,23 +240,135 @@ impl Configuration {
         Ok(())
     }
 
-    /// Convenience alias to detect the Language from a Path-like value's extension.
+    /// Detect the Language of a Path-like value. In order of precedence, this is by:
+    ///
+    /// 1. The file name, exactly (e.g., ".bashrc");
+    /// 2. The longest glob pattern that matches;
+    /// 3. The longest extension that matches, including compound extensions (e.g.,
+    ///    "tfstate.backup");
+    /// 4. The interpreter in the file's shebang, if it can be read;
+    /// 5. The file type in an Emacs or Vim modeline on the file's first line, if it can be read.
+    ///
+    /// Where more than one language matches equally well, the one whose name comes first,
+    /// alphabetically, is taken.
     ///
     /// # Errors
     ///
-    /// If the file extension is not supported, a `FormatterError` will be returned.
+    /// If the language cannot be detected, a `TopiaryConfigError` will be returned.
     #[allow(clippy::result_large_err)]
     pub fn detect<P: AsRef<Path>>(&self, path: P) -> TopiaryConfigResult<&Language> {
-        let pb = &path.as_ref().to_path_buf();
-        if let Some(extension) = pb.extension().and_then(|ext| ext.to_str()) {
-            for lang in &self.languages {
-                if lang.config.extensions.contains(extension) {
-                    return Ok(lang);
-                }
+        let path = path.as_ref();
+        self.detect_with(path, || first_line(path))
+    }
+
+    /// Detect the Language of a Path-like value, as with `detect`, but with the first line of
+    /// its contents given, rather than read from the file. This is for contents that aren't on
+    /// disk, or differ from what is (e.g., an editor's buffer, given on standard input).
+    ///
+    /// # Errors
+    ///
+    /// If the language cannot be detected, a `TopiaryConfigError` will be returned.
+    #[allow(clippy::result_large_err)]
+    pub fn detect_with_first_line<P: AsRef<Path>>(
+        &self,
+        path: P,
+        first_line: &str,
+    ) -> TopiaryConfigResult<&Language> {
+        self.detect_with(path.as_ref(), || Some(first_line.to_string()))
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn detect_with(
+        &self,
+        path: &Path,
+        first_line: impl FnOnce() -> Option<String>,
+    ) -> TopiaryConfigResult<&Language> {
+        if let Some(language) = self.detect_by_path(path) {
+            return Ok(language);
+        }
+
+        if let Some(language) = first_line().and_then(|line| self.detect_by_first_line(&line)) {
+            return Ok(language);
+        }
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(extension) => Err(TopiaryConfigError::UnknownExtension(extension.to_string())),
+            None => Err(TopiaryConfigError::NoExtension(path.to_path_buf())),
+        }
+    }
+
+    /// Detect the Language of a Path-like value from the path alone, without reading the file: by
+    /// its file name, patterns and extensions, as with the first three steps of `detect`.
+    pub fn detect_by_path<P: AsRef<Path>>(&self, path: P) -> Option<&Language> {
+        let path = path.as_ref();
+
+        let name = path.file_name()?.to_str()?;
+
+        if let Some(language) = self
+            .languages
+            .iter()
+            .find(|language| language.config.filenames.contains(name))
+        {
+            return Some(language);
+        }
+
+        if let Some(pattern) = self
+            .patterns
+            .iter()
+            .filter(|pattern| match pattern.whole_path {
+                true => pattern.matcher.is_match(path),
+                false => pattern.matcher.is_match(name),
+            })
+            // The first of the longest, as `max_by_key` would give the last
+            .min_by_key(|pattern| Reverse(pattern.matcher.glob().glob().len()))
+        {
+            return Some(&self.languages[pattern.language]);
+        }
+
+        // The extension must follow a non-empty stem (e.g., ".bashrc" has no extension)
+        self.languages
+            .iter()
+            .flat_map(|language| {
+                language
+                    .config
+                    .extensions
+                    .iter()
+                    .map(move |extension| (language, extension))
+            })
+            .filter(|(_, extension)| {
+                name.strip_suffix(extension.as_str())
+                    .and_then(|stem| stem.strip_suffix('.'))
+                    .is_some_and(|stem| !stem.is_empty())
+            })
+            .min_by_key(|(_, extension)| Reverse(extension.len()))
+            .map(|(language, _)| language)
+    }
+
+    fn detect_by_first_line(&self, line: &str) -> Option<&Language> {
+        if let Some(interpreter) = detect::interpreter(line) {
+            // Also try without any version suffix (e.g., "ocaml5.1")
+            let unversioned =
+                interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+
+            if let Some(language) = self.languages.iter().find(|language| {
+                language.config.interpreters.contains(interpreter)
+                    || language.config.interpreters.contains(unversioned)
+            }) {
+                return Some(language);
             }
-            return Err(TopiaryConfigError::UnknownExtension(extension.to_string()));
         }
-        Err(TopiaryConfigError::NoExtension(pb.clone()))
+
+        // Otherwise, modelines may use the language name, one of its extensions or interpreters
+        let mode = detect::modeline(line)?;
+        self.languages
+            .iter()
+            .find(|language| language.name == mode)
+            .or_else(|| {
+                self.languages.iter().find(|language| {
+                    language.config.extensions.contains(&mode)
+                        || language.config.interpreters.contains(&mode)
+                })
+            })
     }
 
     #[allow(clippy::result_large_err)]
@@ -289,23 +443,66 @@ impl PartialEq for Configuration {
         let lhs: HashMap<String, Language> = self.into();
         let rhs: HashMap<String, Language> = other.into();
 
-        lhs == rhs
+        lhs == rhs && self.include == other.include && self.exclude == other.exclude
     }
 }
 
 impl From<SerdeConfiguration> for Configuration {
     fn from(value: SerdeConfiguration) -> Self {
-        let languages = value
+        let mut languages: Vec<Language> = value
             .languages
             .into_iter()
             .map(|(name, config)| Language::new(name, config))
             .collect();
 
-        Self { languages }
+        // Languages are deserialised in an arbitrary order, so sort them by name, such that
+        // detection settles ties the same way on every run
+        languages.sort_by(|a, b| a.name.cmp(&b.name));
+
+        // Compile each language's glob patterns once, up front
+        let patterns = languages
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, language)| {
+                language.config.patterns.iter().filter_map(move |pattern| {
+                    match Glob::new(pattern) {
+                        Ok(glob) => Some(Pattern {
+                            matcher: glob.compile_matcher(),
+                            whole_path: pattern.contains('/'),
+                            language: idx,
+                        }),
+
+                        Err(e) => {
+                            log::warn!("Ignoring pattern for {}: {e}", language.name);
+                            None
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            languages,
+            include: value.include,
+            exclude: value.exclude,
+            patterns,
+        }
     }
 }
 
-pub(crate) fn project_dirs() -> directories::ProjectDirs {
+/// The first line of a file, if it can be read as text
+fn first_line(path: &Path) -> Option<String> {
+    // Limit how much we read, in case the file has no newlines (e.g., it's binary)
+    let mut reader = BufReader::new(File::open(path).ok()?.take(1024));
+
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+
+    Some(line)
+}
+
+/// Topiary's platform-specific project directories, for its configuration and caches
+pub fn project_dirs() -> directories::ProjectDirs {
     directories::ProjectDirs::from("", "", "topiary")
         .expect("Could not access the OS's Home directory")
 }
@@ -1,6 +1,7 @@
 //! Configuration for Topiary can be sourced from either that which is built-in, or from disk.
 
 use std::{
+    collections::{HashMap, HashSet},
     env::current_dir,
     ffi::OsString,
     fmt,
@@ -8,6 +9,9 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use nickel_lang_core::{error::NullReporter, eval::cache::CacheImpl, program::Program};
+use serde::{Deserialize, de::IgnoredAny};
+
 use crate::error::{TopiaryConfigError, TopiaryConfigResult};
 
 /// Sources of Nickel configuration
@@ -132,6 +136,32 @@ impl Source {
         source
     }
 
+    /// The names of the languages that the configuration from this source defines, when evaluated
+    /// on its own. A source that cannot be evaluated on its own is taken to define none.
+    pub fn language_names(&self) -> HashSet<String> {
+        #[derive(Deserialize)]
+        struct Languages {
+            #[serde(default)]
+            languages: HashMap<String, IgnoredAny>,
+        }
+
+        let Ok(mut program) = Program::<CacheImpl>::new_from_input(
+            self.clone().into(),
+            std::io::sink(),
+            NullReporter {},
+        ) else {
+            return HashSet::new();
+        };
+
+        program
+            .eval_full_for_export()
+            .ok()
+            .and_then(|term| Languages::deserialize(term).ok())
+            .map_or_else(HashSet::new, |config| {
+                config.languages.into_keys().collect()
+            })
+    }
+
     #[allow(clippy::result_large_err)]
     pub fn read(&self) -> TopiaryConfigResult<Vec<u8>> {
         match self {
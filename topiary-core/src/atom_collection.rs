@@ -9,6 +9,7 @@ use topiary_tree_sitter_facade::Node;
 
 use crate::{
     Atom, Capitalisation, FormatterError, FormatterResult, ScopeCondition, ScopeInformation,
+    provenance::{Placement, Provenance, Tracer},
     tree_sitter::NodeExt,
 };
 
@@ -61,6 +62,9 @@ pub struct AtomCollection {
     line_break_after: HashSet<usize>,
     /// Used to generate unique IDs
     counter: usize,
+    /// When set, the provenance of every atom is recorded, to explain
+    /// formatting decisions.
+    trace: Option<Tracer>,
 }
 
 impl AtomCollection {
@@ -80,6 +84,7 @@ impl AtomCollection {
             line_break_before: HashSet::new(),
             line_break_after: HashSet::new(),
             counter: 0,
+            trace: None,
         }
     }
 
@@ -88,6 +93,25 @@ impl AtomCollection {
         root: &Node,
         source: &[u8],
         specified_leaf_nodes: HashSet<usize>,
+    ) -> FormatterResult<Self> {
+        Self::collect(root, source, specified_leaf_nodes, None)
+    }
+
+    /// Like `collect_leaves`, but the provenance of every atom is recorded.
+    /// It can be retrieved with `into_provenance`, after post-processing.
+    pub(crate) fn collect_leaves_traced(
+        root: &Node,
+        source: &[u8],
+        specified_leaf_nodes: HashSet<usize>,
+    ) -> FormatterResult<Self> {
+        Self::collect(root, source, specified_leaf_nodes, Some(Tracer::default()))
+    }
+
+    fn collect(
+        root: &Node,
+        source: &[u8],
+        specified_leaf_nodes: HashSet<usize>,
+        trace: Option<Tracer>,
     ) -> FormatterResult<Self> {
         // Flatten the tree, from the root node, in a depth-first traversal
         let dfs_nodes = dfs_flatten(root);
@@ -108,6 +132,7 @@ impl AtomCollection {
             line_break_before: line_break_nodes.before,
             line_break_after: line_break_nodes.after,
             counter: 0,
+            trace,
         };
 
         atoms.collect_leaves_inner(root, source, &Vec::new(), 0)?;
@@ -115,10 +140,36 @@ impl AtomCollection {
         Ok(atoms)
     }
 
+    /// The tracer recording the provenance of atoms, if any
+    pub(crate) fn tracer(&mut self) -> Option<&mut Tracer> {
+        self.trace.as_mut()
+    }
+
+    /// The provenance of every atom, if it was recorded
+    pub(crate) fn into_provenance(self) -> Option<Provenance> {
+        let atoms = self.atoms;
+        self.trace.map(|tracer| tracer.finish(&atoms))
+    }
+
+    // Record a decision about the next atom, if tracing
+    fn decide(&mut self, decision: impl FnOnce() -> String) {
+        if let Some(tracer) = &mut self.trace {
+            tracer.decide(decision());
+        }
+    }
+
+    // Record that the current capture creates no atoms, if tracing
+    fn skip(&mut self, reason: &str) {
+        if let Some(tracer) = &mut self.trace {
+            tracer.skip(reason);
+        }
+    }
+
     // wrap inside a conditional atom if #single/multi_line_scope_only! is set
     fn wrap(&mut self, atom: Atom, predicates: &QueryPredicates) -> Atom {
         if let Some(scope_id) = &predicates.single_line_scope_only {
             let id = self.next_id();
+            self.decide(|| format!("only applies if scope {scope_id:?} is single-line"));
             Atom::ScopedConditional {
                 id,
                 scope_id: scope_id.to_string(),
@@ -127,6 +178,7 @@ impl AtomCollection {
             }
         } else if let Some(scope_id) = &predicates.multi_line_scope_only {
             let id = self.next_id();
+            self.decide(|| format!("only applies if scope {scope_id:?} is multi-line"));
             Atom::ScopedConditional {
                 id,
                 scope_id: scope_id.to_string(),
@@ -138,6 +190,50 @@ impl AtomCollection {
         }
     }
 
+    /// The capture names that `resolve_capture` handles, along with `@do_nothing`, which is
+    /// handled before it is called. This must be kept in step with `resolve_capture`.
+    pub(crate) const CAPTURE_NAMES: &[&str] = &[
+        "allow_blank_line_before",
+        "append_delimiter",
+        "append_empty_softline",
+        "append_hardline",
+        "append_indent_start",
+        "append_indent_end",
+        "append_input_softline",
+        "append_space",
+        "append_antispace",
+        "append_spaced_softline",
+        "prepend_delimiter",
+        "prepend_empty_softline",
+        "prepend_hardline",
+        "prepend_indent_start",
+        "prepend_indent_end",
+        "prepend_input_softline",
+        "prepend_space",
+        "prepend_antispace",
+        "prepend_spaced_softline",
+        "leaf",
+        "delete",
+        "upper_case",
+        "lower_case",
+        "prepend_begin_scope",
+        "append_begin_scope",
+        "prepend_end_scope",
+        "append_end_scope",
+        "prepend_begin_measuring_scope",
+        "append_begin_measuring_scope",
+        "prepend_end_measuring_scope",
+        "append_end_measuring_scope",
+        "append_empty_scoped_softline",
+        "append_spaced_scoped_softline",
+        "prepend_empty_scoped_softline",
+        "prepend_spaced_scoped_softline",
+        "single_line_no_indent",
+        "multi_line_indent_all",
+        "keep_whitespace",
+        "do_nothing",
+    ];
+
     /// Resolves a capture name by modifying the AtomCollection based on the
     /// instructions provided by the capture name on the Node.
     ///
@@ -200,10 +296,12 @@ impl AtomCollection {
         }
         if is_multi_line && predicates.single_line_only {
             log::debug!("Skipping because context is multi-line and #single_line_only! is set");
+            self.skip("the context is multi-line and #single_line_only! is set");
             return Ok(());
         }
         if !is_multi_line && predicates.multi_line_only {
             log::debug!("Skipping because context is single-line and #multi_line_only! is set");
+            self.skip("the context is single-line and #multi_line_only! is set");
             return Ok(());
         }
         if let Some(parent_id) = self.parent_leaf_nodes.get(&node.id())
@@ -213,13 +311,17 @@ impl AtomCollection {
                 "Skipping because the match occurred below a leaf node: {}",
                 node.display_one_based()
             );
+            self.skip("the match occurred below a leaf node");
             return Ok(());
         }
 
         match name {
             "allow_blank_line_before" => {
                 if self.blank_lines_before.contains(&node.id()) {
+                    self.decide(|| "there is a blank line before the node in the input".into());
                     self.prepend(Atom::Blankline, node, predicates);
+                } else {
+                    self.skip("there is no blank line before the node in the input");
                 }
             }
             "append_delimiter" => self.append(
@@ -235,8 +337,10 @@ impl AtomCollection {
             "append_indent_end" => self.append(Atom::IndentEnd, node, predicates),
             "append_input_softline" => {
                 let space = if self.line_break_after.contains(&node.id()) {
+                    self.decide(|| "there is a line break after the node in the input".into());
                     Atom::Hardline
                 } else {
+                    self.decide(|| "there is no line break after the node in the input".into());
                     Atom::Space
                 };
 
@@ -260,8 +364,10 @@ impl AtomCollection {
             "prepend_indent_end" => self.prepend(Atom::IndentEnd, node, predicates),
             "prepend_input_softline" => {
                 let space = if self.line_break_before.contains(&node.id()) {
+                    self.decide(|| "there is a line break before the node in the input".into());
                     Atom::Hardline
                 } else {
+                    self.decide(|| "there is no line break before the node in the input".into());
                     Atom::Space
                 };
 
@@ -473,12 +579,40 @@ impl AtomCollection {
             }
         }
 
+        // The traces of atoms, if any, are sorted in step with them
+        fn sort(atoms: &mut Vec<Atom>, traces: Option<&mut Vec<usize>>) {
+            if let Some(traces) = traces {
+                let mut traced: Vec<_> = mem::take(atoms)
+                    .into_iter()
+                    .zip(mem::take(traces))
+                    .collect();
+                traced.sort_by_key(|(atom, _)| atom_key(atom));
+                (*atoms, *traces) = traced.into_iter().unzip();
+            } else {
+                atoms.sort_by_key(atom_key);
+            }
+        }
+
         for atom in &mut self.atoms {
             if let Atom::Leaf { id, .. } = atom {
                 let prepends = self.prepend.entry(*id).or_default();
-                prepends.sort_by_key(atom_key);
+                let prepend_traces = self
+                    .trace
+                    .as_mut()
+                    .map(|t| t.prepend.entry(*id).or_default());
+                sort(prepends, prepend_traces);
                 let appends = self.append.entry(*id).or_default();
-                appends.sort_by_key(atom_key);
+                let append_traces = self
+                    .trace
+                    .as_mut()
+                    .map(|t| t.append.entry(*id).or_default());
+                sort(appends, append_traces);
+
+                if let Some(tracer) = &mut self.trace {
+                    let prepends = tracer.prepend.remove(id).unwrap_or_default();
+                    let appends = tracer.append.remove(id).unwrap_or_default();
+                    tracer.expand_leaf(*id, &prepends, &appends);
+                }
 
                 // Rather than cloning the atom from the old vector, we
                 // simply take it. This will leave a default (empty) atom
@@ -499,6 +633,10 @@ impl AtomCollection {
             } else {
                 log::debug!("Not a leaf: {atom:?}");
                 expanded.push(mem::take(atom));
+
+                if let Some(tracer) = &mut self.trace {
+                    tracer.expand();
+                }
             }
         }
 
@@ -565,6 +703,9 @@ impl AtomCollection {
                 keep_whitespace: false,
                 capitalisation: Capitalisation::Pass,
             });
+            if let Some(tracer) = &mut self.trace {
+                tracer.leaf(node, &node.utf8_text(source)?);
+            }
             // Mark all sub-nodes as having this node as a "leaf parent"
             self.mark_leaf_parent(node, node.id());
         } else {
@@ -594,6 +735,10 @@ impl AtomCollection {
             target_node.display_one_based()
         );
 
+        if let Some(tracer) = &mut self.trace {
+            tracer.created(&atom, &target_node, Placement::Prepended);
+        }
+
         self.prepend.entry(target_node.id()).or_default().push(atom);
     }
 
@@ -614,6 +759,10 @@ impl AtomCollection {
             target_node.display_one_based()
         );
 
+        if let Some(tracer) = &mut self.trace {
+            tracer.created(&atom, &target_node, Placement::Appended);
+        }
+
         self.append.entry(target_node.id()).or_default().push(atom);
     }
 
@@ -637,7 +786,7 @@ impl AtomCollection {
     /// # Returns
     ///
     /// A new atom after expanding the softline if applicable.
-    fn expand_multiline(&self, atom: Atom, node: &Node) -> Atom {
+    fn expand_multiline(&mut self, atom: Atom, node: &Node) -> Atom {
         if let Atom::Softline { spaced } = atom {
             if let Some(parent) = node.parent() {
                 let parent_id = parent.id();
@@ -649,6 +798,12 @@ impl AtomCollection {
                         parent_id,
                         parent.display_one_based()
                     );
+                    self.decide(|| {
+                        format!(
+                            "softline expanded to a hardline, as the parent {} is multi-line",
+                            parent.display_one_based()
+                        )
+                    });
                     Atom::Hardline
                 } else if spaced {
                     log::debug!(
@@ -657,11 +812,24 @@ impl AtomCollection {
                         parent_id,
                         parent.display_one_based()
                     );
+                    self.decide(|| {
+                        format!(
+                            "softline expanded to a space, as the parent {} is single-line",
+                            parent.display_one_based()
+                        )
+                    });
                     Atom::Space
                 } else {
+                    self.decide(|| {
+                        format!(
+                            "softline discarded, as the parent {} is single-line",
+                            parent.display_one_based()
+                        )
+                    });
                     Atom::Empty
                 }
             } else {
+                self.decide(|| "softline discarded, as the node has no parent".into());
                 Atom::Empty
             }
         } else {
@@ -700,6 +868,9 @@ impl AtomCollection {
         // of the processing, even if the `modifications` map is empty. This is to ensure we will
         // get rid of misplaced scoped atoms.
         let mut force_apply_modifications = false;
+        // When tracing, we also record why each modification was made
+        let tracing = self.trace.is_some();
+        let mut reasons: HashMap<ScopedNodeId, String> = HashMap::new();
 
         for atom in &self.atoms {
             if let Atom::ScopeBegin(ScopeInformation {
@@ -725,6 +896,18 @@ impl AtomCollection {
                         line_start != *line_end
                     };
                     for atom in atoms {
+                        if tracing
+                            && let Atom::ScopedSoftline { id, .. }
+                            | Atom::ScopedConditional { id, .. } = atom
+                        {
+                            let context = if multiline {
+                                "multi-line"
+                            } else {
+                                "single-line"
+                            };
+                            reasons.insert(*id, format!("scope {scope_id:?} is {context}"));
+                        }
+
                         if let Atom::ScopedSoftline { id, spaced, .. } = atom {
                             let new_atom = if multiline {
                                 Atom::Hardline
@@ -844,12 +1027,18 @@ impl AtomCollection {
         }
 
         // Remove scopes from the atom list
-        for atom in &mut self.atoms {
+        for (i, atom) in self.atoms.iter_mut().enumerate() {
             match atom {
                 Atom::ScopeBegin(_)
                 | Atom::ScopeEnd(_)
                 | Atom::MeasuringScopeBegin(_)
-                | Atom::MeasuringScopeEnd(_) => *atom = Atom::Empty,
+                | Atom::MeasuringScopeEnd(_) => {
+                    *atom = Atom::Empty;
+
+                    if let Some(tracer) = &mut self.trace {
+                        tracer.dropped(i, "scope markers are removed once resolved", None);
+                    }
+                }
                 _ => {}
             }
         }
@@ -857,21 +1046,31 @@ impl AtomCollection {
         // Apply modifications.
         // For performance reasons, skip this step if there are no modifications to make
         if !modifications.is_empty() || force_apply_modifications {
-            for atom in &mut self.atoms {
-                if let Atom::ScopedSoftline { id, .. } = atom {
-                    if let Some(replacement) = modifications.remove(id) {
-                        *atom = replacement;
-                    } else {
-                        log::warn!("Found scoped softline {atom:?}, but was unable to replace it.");
-                        *atom = Atom::Empty;
-                    }
-                } else if let Atom::ScopedConditional { id, .. } = atom {
+            for (i, atom) in self.atoms.iter_mut().enumerate() {
+                if let Atom::ScopedSoftline { id, .. } | Atom::ScopedConditional { id, .. } = atom {
+                    let reason = reasons.remove(id);
+
                     if let Some(replacement) = modifications.remove(id) {
+                        if let Some(tracer) = &mut self.trace {
+                            tracer.replaced(i, &replacement, &reason.unwrap_or_default());
+                        }
+
                         *atom = replacement;
                     } else {
-                        log::warn!(
-                            "Found scoped conditional {atom:?}, but was unable to replace it."
-                        );
+                        if let Atom::ScopedSoftline { .. } = atom {
+                            log::warn!(
+                                "Found scoped softline {atom:?}, but was unable to replace it."
+                            );
+                        } else {
+                            log::warn!(
+                                "Found scoped conditional {atom:?}, but was unable to replace it."
+                            );
+                        }
+
+                        if let Some(tracer) = &mut self.trace {
+                            tracer.dropped(i, "found outside of its scope", None);
+                        }
+
                         *atom = Atom::Empty;
                     }
                 }
@@ -882,19 +1081,35 @@ impl AtomCollection {
     /// Separate post_processing of Delete sections, to avoid interference with whitespace logic
     fn post_process_deletes(&mut self) {
         let mut delete_level = 0;
-        for atom in &mut self.atoms {
+        // The positions of the open DeleteBegin atoms, when tracing
+        let mut deletes: Vec<usize> = Vec::new();
+        for (i, atom) in self.atoms.iter_mut().enumerate() {
             match atom {
                 Atom::DeleteBegin => {
                     delete_level += 1;
                     *atom = Atom::Empty;
+
+                    if let Some(tracer) = &mut self.trace {
+                        tracer.dropped(i, "delete markers are removed once applied", None);
+                        deletes.push(i);
+                    }
                 }
                 Atom::DeleteEnd => {
                     delete_level -= 1;
                     *atom = Atom::Empty;
+
+                    if let Some(tracer) = &mut self.trace {
+                        tracer.dropped(i, "delete markers are removed once applied", None);
+                        deletes.pop();
+                    }
                 }
                 _ => {
                     if delete_level > 0 {
                         *atom = Atom::Empty;
+
+                        if let Some(tracer) = &mut self.trace {
+                            tracer.dropped(i, "deleted", deletes.last().copied());
+                        }
                     }
                 }
             }
@@ -907,15 +1122,23 @@ impl AtomCollection {
     /// Separate post processing of capitalisation, to avoid confusion around whitespacing.
     fn post_process_capitalization(&mut self) {
         let mut case_context: Vec<Capitalisation> = Vec::new();
-        for atom in &mut self.atoms {
+        for (i, atom) in self.atoms.iter_mut().enumerate() {
             match atom {
                 Atom::CaseBegin(case) => {
                     case_context.push(case.clone());
                     *atom = Atom::Empty;
+
+                    if let Some(tracer) = &mut self.trace {
+                        tracer.dropped(i, "case markers are removed once applied", None);
+                    }
                 }
                 Atom::CaseEnd => {
                     case_context.pop();
                     *atom = Atom::Empty;
+
+                    if let Some(tracer) = &mut self.trace {
+                        tracer.dropped(i, "case markers are removed once applied", None);
+                    }
                 }
                 Atom::Leaf { capitalisation, .. } => {
                     *capitalisation = case_context.last().unwrap_or(&Capitalisation::Pass).clone()
@@ -937,7 +1160,7 @@ impl AtomCollection {
 
         // We have taken care of spaces following an antispace. Now fix the
         // preceding spaces.
-        collapse_spaces_before_antispace(&mut self.atoms);
+        collapse_spaces_before_antispace(&mut self.atoms, self.trace.as_mut());
 
         // We have to do one more post-processing pass, as the collapsing of
         // antispaces may have produced more empty atoms.
@@ -949,84 +1172,86 @@ impl AtomCollection {
     /// This function post-processes the atoms in the collection.
     /// It modifies the collection in-place, removing unnecessary atoms and adjusting the position of others.
     fn post_process_inner(&mut self) {
-        // A mutable reference to the atoms in the collection.
-        let mut remaining = &mut self.atoms[..];
-
-        // The previous atom in the collection. Initialized to the first atom, if it exists.
-        // We a mutable reference to the previous atom so that we can modify it in-place.
-        // This atoms holds the last atom encountered that is not empty.
-        let mut prev: &mut Atom = if let [head, tail @ ..] = remaining {
-            remaining = tail;
-            head
-        } else {
+        let atoms = &mut self.atoms;
+        let mut trace = self.trace.as_mut();
+
+        // The position of the previous atom in the collection, initialized to the first atom,
+        // if it exists. This is the last atom encountered that is not empty.
+        let mut prev = 0;
+        // The position of the atom to process next.
+        let mut next = 1;
+
+        if atoms.is_empty() {
             return;
-        };
+        }
 
         // Set all leading whitespace atoms to empty.
-        while let Atom::Space | Atom::Antispace | Atom::Hardline | Atom::Blankline = *prev {
-            *prev = Atom::Empty;
-            if let [head, tail @ ..] = remaining {
-                prev = head;
-                remaining = tail;
+        while let Atom::Space | Atom::Antispace | Atom::Hardline | Atom::Blankline = atoms[prev] {
+            atoms[prev] = Atom::Empty;
+
+            if let Some(tracer) = trace.as_mut() {
+                tracer.dropped(prev, "leading whitespace is removed", None);
+            }
+
+            if next < atoms.len() {
+                prev = next;
+                next += 1;
             } else {
                 return;
             }
         }
 
         // Process the remaining atoms in the collection.
-        while !remaining.is_empty() {
-            match (prev, remaining) {
+        while next < atoms.len() {
+            match (&atoms[prev], &atoms[next]) {
                 // If an antispace atom is followed by a space or another antispace, remove the following atom.
-                (
-                    moved_prev @ Atom::Antispace,
-                    [head @ (Atom::Space | Atom::Antispace), tail @ ..],
-                ) => {
-                    *head = Atom::Empty;
+                (Atom::Antispace, Atom::Space | Atom::Antispace) => {
+                    atoms[next] = Atom::Empty;
 
-                    prev = moved_prev;
-                    remaining = tail;
+                    if let Some(tracer) = trace.as_mut() {
+                        tracer.dropped(next, "follows an antispace", Some(prev));
+                    }
+
+                    next += 1;
                 }
                 // If two whitespace atoms follow each other, remove the non-dominant one.
                 (
-                    moved_prev @ (Atom::Space | Atom::Hardline | Atom::Blankline),
-                    [
-                        head @ (Atom::Space | Atom::Hardline | Atom::Blankline),
-                        tail @ ..,
-                    ],
+                    Atom::Space | Atom::Hardline | Atom::Blankline,
+                    Atom::Space | Atom::Hardline | Atom::Blankline,
                 ) => {
-                    if head.dominates(moved_prev) {
-                        *moved_prev = Atom::Empty;
+                    let (dropped, by) = if atoms[next].dominates(&atoms[prev]) {
+                        (prev, next)
                     } else {
-                        *head = Atom::Empty;
+                        (next, prev)
+                    };
+
+                    if let Some(tracer) = trace.as_mut() {
+                        let reason = format!("dominated by the adjacent {:?}", atoms[by]);
+                        tracer.dropped(dropped, &reason, Some(by));
                     }
 
-                    prev = moved_prev;
-                    remaining = tail;
+                    atoms[dropped] = Atom::Empty;
+                    next += 1;
                 }
                 // If a whitespace or antispace atom is followed by an indent atom, swap their positions.
                 (
-                    moved_prev @ (Atom::Antispace | Atom::Space | Atom::Hardline | Atom::Blankline),
-                    moved_remaining @ [Atom::IndentStart | Atom::IndentEnd, ..],
+                    Atom::Antispace | Atom::Space | Atom::Hardline | Atom::Blankline,
+                    Atom::IndentStart | Atom::IndentEnd,
                 ) => {
-                    let old_prev = moved_prev.clone();
-                    let indent = moved_remaining.first_mut().unwrap();
-                    *moved_prev = indent.clone();
-                    *indent = old_prev;
+                    if let Some(tracer) = trace.as_mut() {
+                        let reason = format!("moved after the following {:?}", atoms[next]);
+                        tracer.swapped(prev, next, &reason);
+                    }
 
-                    prev = moved_prev;
-                    remaining = moved_remaining;
+                    atoms.swap(prev, next);
                 }
                 // If the current atom is not empty, update the previous atom.
-                (moved_prev, [head, tail @ ..]) => {
-                    prev = if matches!(head, Atom::Empty) {
-                        moved_prev
-                    } else {
-                        head
-                    };
-                    remaining = tail;
+                (_, head) => {
+                    if !matches!(head, Atom::Empty) {
+                        prev = next;
+                    }
+                    next += 1;
                 }
-                // This case should never be reached because we check that `remaining` is not empty at the start of the loop.
-                (_, []) => unreachable!("remaining cannot be empty"),
             }
         }
     }
@@ -1111,19 +1336,29 @@ pub struct QueryPredicates {
 /// # Arguments
 ///
 /// * `v` - A mutable reference to a vector of atoms.
+/// * `trace` - The tracer recording the provenance of atoms, if any.
 ///
-fn collapse_spaces_before_antispace(v: &mut [Atom]) {
-    let mut antispace_mode = false;
+fn collapse_spaces_before_antispace(v: &mut [Atom], mut trace: Option<&mut Tracer>) {
+    // The position of the antispace being applied, if any
+    let mut antispace = None;
 
-    for a in v.iter_mut().rev() {
+    for (i, a) in v.iter_mut().enumerate().rev() {
         if *a == Atom::Antispace {
             *a = Atom::Empty;
-            antispace_mode = true;
-        } else if *a == Atom::Space && antispace_mode {
+            antispace = Some(i);
+
+            if let Some(tracer) = trace.as_mut() {
+                tracer.dropped(i, "antispaces are removed once applied", None);
+            }
+        } else if *a == Atom::Space && antispace.is_some() {
             *a = Atom::Empty;
+
+            if let Some(tracer) = trace.as_mut() {
+                tracer.dropped(i, "precedes an antispace", antispace);
+            }
         } else if *a != Atom::Empty && *a != Atom::IndentStart && *a != Atom::IndentEnd {
             // Don't change mode when encountering Empty or Indent atoms
-            antispace_mode = false;
+            antispace = None;
         }
     }
 }
@@ -1145,7 +1380,7 @@ fn collapse_spaces_before_antispace(v: &mut [Atom]) {
 ///
 /// This function uses an iterative approach instead of a recursive one for performance reasons.
 /// See https://github.com/tweag/topiary/pull/417#issuecomment-1499085230 for more details.
-fn dfs_flatten<'tree>(node: &Node<'tree>) -> Vec<Node<'tree>> {
+pub(crate) fn dfs_flatten<'tree>(node: &Node<'tree>) -> Vec<Node<'tree>> {
     // Flatten the tree, depth-first, into a vector of nodes
     let mut walker = node.walk();
     let mut dfs_nodes = Vec::new();
@@ -0,0 +1,138 @@
+//! Listings of the atoms produced by applying a query, before and after post-processing. This is
+//! the step between the parse tree and the rendered output.
+
+use std::io;
+
+use serde::Serialize;
+
+use crate::{Atom, Capitalisation, FormatterResult, ScopeCondition, ScopeInformation};
+
+/// A readable description of a single atom
+fn describe(atom: &Atom) -> String {
+    let scope =
+        |ScopeInformation {
+             line_number,
+             scope_id,
+         }: &ScopeInformation| { format!("{scope_id:?} at line {}", line_number + 1) };
+
+    match atom {
+        Atom::Leaf {
+            content,
+            id,
+            original_position,
+            single_line_no_indent,
+            multi_line_indent_all,
+            keep_whitespace,
+            capitalisation,
+        } => {
+            let mut description = format!("Leaf #{id} {content:?} at {original_position}");
+
+            let flags: Vec<&str> = [
+                (*single_line_no_indent, "single_line_no_indent"),
+                (*multi_line_indent_all, "multi_line_indent_all"),
+                (*keep_whitespace, "keep_whitespace"),
+                (*capitalisation == Capitalisation::UpperCase, "upper_case"),
+                (*capitalisation == Capitalisation::LowerCase, "lower_case"),
+            ]
+            .into_iter()
+            .filter_map(|(set, flag)| set.then_some(flag))
+            .collect();
+
+            if !flags.is_empty() {
+                description += &format!(" [{}]", flags.join(", "));
+            }
+
+            description
+        }
+
+        Atom::Literal(literal) => format!("Literal {literal:?}"),
+        Atom::Softline { spaced: true } => "Softline (spaced)".into(),
+        Atom::Softline { spaced: false } => "Softline (empty)".into(),
+        Atom::CaseBegin(Capitalisation::UpperCase) => "CaseBegin (upper_case)".into(),
+        Atom::CaseBegin(Capitalisation::LowerCase) => "CaseBegin (lower_case)".into(),
+        Atom::CaseBegin(Capitalisation::Pass) => "CaseBegin".into(),
+
+        Atom::ScopeBegin(information) => format!("ScopeBegin {}", scope(information)),
+        Atom::ScopeEnd(information) => format!("ScopeEnd {}", scope(information)),
+        Atom::MeasuringScopeBegin(information) => {
+            format!("MeasuringScopeBegin {}", scope(information))
+        }
+        Atom::MeasuringScopeEnd(information) => {
+            format!("MeasuringScopeEnd {}", scope(information))
+        }
+
+        Atom::ScopedSoftline {
+            id,
+            scope_id,
+            spaced,
+        } => format!(
+            "ScopedSoftline #{id} in {scope_id:?} ({})",
+            if *spaced { "spaced" } else { "empty" }
+        ),
+
+        Atom::ScopedConditional {
+            id,
+            scope_id,
+            condition,
+            atom,
+        } => format!(
+            "ScopedConditional #{id} in {scope_id:?}, if {}: {}",
+            match condition {
+                ScopeCondition::SingleLineOnly => "single-line",
+                ScopeCondition::MultiLineOnly => "multi-line",
+            },
+            describe(atom)
+        ),
+
+        // The remaining atoms carry no information beyond their name
+        atom => format!("{atom:?}"),
+    }
+}
+
+/// Write a readable listing of the atoms, one per line. Each atom before post-processing is
+/// followed by what it became, if post-processing changed it.
+pub fn write(output: &mut dyn io::Write, before: &[Atom], after: &[Atom]) -> FormatterResult<()> {
+    let descriptions: Vec<String> = before.iter().map(describe).collect();
+    let index_width = before.len().saturating_sub(1).to_string().len();
+    let width = descriptions.iter().map(|d| d.chars().count()).max();
+
+    for (index, (description, (before, after))) in descriptions
+        .iter()
+        .zip(before.iter().zip(after))
+        .enumerate()
+    {
+        if before == after {
+            writeln!(output, "{index:>index_width$}  {description}")?;
+        } else {
+            let after = match after {
+                Atom::Empty => "(removed)".into(),
+                atom => describe(atom),
+            };
+
+            writeln!(
+                output,
+                "{index:>index_width$}  {description:<width$}  =>  {after}",
+                width = width.unwrap_or_default()
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct AtomStream<'a> {
+    before: &'a [Atom],
+    after: &'a [Atom],
+}
+
+/// Write the atoms, before and after post-processing, as JSON
+pub fn write_json(
+    output: &mut dyn io::Write,
+    before: &[Atom],
+    after: &[Atom],
+) -> FormatterResult<()> {
+    serde_json::to_writer(output, &AtomStream { before, after })?;
+
+    Ok(())
+}
@@ -35,6 +35,10 @@ pub enum FormatterError {
 
     /// I/O-related errors
     Io(IoError),
+
+    /// The part of the input that was selected, by a range, position or node kind, is not valid
+    /// for the input
+    InvalidSelection(String),
 }
 
 /// A subtype of `FormatterError::Io`
@@ -107,6 +111,7 @@ impl fmt::Display for FormatterError {
 
             Self::Internal(message, _)
             | Self::Query(message, _)
+            | Self::InvalidSelection(message)
             | Self::Io(IoError::Filesystem(message, _) | IoError::Generic(message, _)) => {
                 write!(f, "{message}")
             }
@@ -120,6 +125,7 @@ impl Error for FormatterError {
             Self::Idempotence
             | Self::Parsing(_)
             | Self::PatternDoesNotMatch
+            | Self::InvalidSelection(_)
             | Self::Io(IoError::Generic(_, None)) => None,
             Self::Internal(_, source) => source.as_ref().map(Deref::deref),
             Self::Query(_, source) => source.as_ref().map(|e| e as &dyn Error),
@@ -13,7 +13,8 @@ fn escape(input: &str) -> Cow<'_, str> {
     let mut start: usize = 0;
     let length = input.len();
 
-    let append = |buffer: &mut String, from: &mut usize, to: usize, suffix: &str| {
+    // `to` is the byte index of the character being escaped, which is `width` bytes long
+    let append = |buffer: &mut String, from: &mut usize, to: usize, width: usize, suffix: &str| {
         // Allocate buffer only when necessary
         if buffer.is_empty() {
             // Best case:  length + 1  (i.e., single escaped character in input)
@@ -28,16 +29,19 @@ fn escape(input: &str) -> Cow<'_, str> {
         *buffer += suffix;
 
         // Fast-forward the tracking cursor to the next character
-        *from = to + 1;
+        *from = to + width;
     };
 
-    for (idx, current) in input.chars().enumerate() {
+    for (idx, current) in input.char_indices() {
+        let width = current.len_utf8();
+
         match current {
             // Double-escape whitespace characters
-            '\n' => append(&mut buffer, &mut start, idx, r#"\\n"#),
-            '\t' => append(&mut buffer, &mut start, idx, r#"\\t"#),
+            '\n' => append(&mut buffer, &mut start, idx, width, r#"\\n"#),
+            '\t' => append(&mut buffer, &mut start, idx, width, r#"\\t"#),
 
-            otherwise => {
+            // GraphViz reads UTF-8, so non-ASCII characters are left as they are
+            otherwise if otherwise.is_ascii() => {
                 // If char::escape_default starts with a backslash, then we
                 // have an escaped character and we're off the happy path
                 let mut escaped = otherwise.escape_default().peekable();
@@ -46,10 +50,13 @@ fn escape(input: &str) -> Cow<'_, str> {
                         &mut buffer,
                         &mut start,
                         idx,
+                        width,
                         &otherwise.escape_default().to_string(),
                     );
                 }
             }
+
+            _ => {}
         }
     }
 
@@ -66,26 +73,51 @@ impl fmt::Display for SyntaxNode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let shape = if self.is_named { "ellipse" } else { "box" };
 
-        writeln!(
-            f,
-            "  {} [label=\"{}\", shape={shape}];",
-            self.id,
-            escape(&self.kind)
-        )?;
+        // Byte ranges and leaf text, when present, go on lines of their own
+        let mut label = escape(&self.kind).into_owned();
+        if let (Some(start), Some(end)) = (self.start_byte, self.end_byte) {
+            label += &format!("\\n[{start}..{end}]");
+        }
+        if let Some(text) = &self.text {
+            label += &format!("\\n{}", escape(&format!("{text:?}")));
+        }
+
+        writeln!(f, "  {} [label=\"{label}\", shape={shape}];", self.id)?;
 
         for child in &self.children {
-            writeln!(f, "  {} -- {};", self.id, child.id)?;
+            match &child.field {
+                Some(field) => writeln!(
+                    f,
+                    "  {} -- {} [label=\"{}\"];",
+                    self.id,
+                    child.id,
+                    escape(field)
+                )?,
+                None => writeln!(f, "  {} -- {};", self.id, child.id)?,
+            }
             write!(f, "{child}")?;
         }
 
+        // Pruned children are stood in for by a single marker
+        if let Some(elided) = self.elided {
+            writeln!(f, "  {} -- elided{} [style=dashed];", self.id, self.id)?;
+            writeln!(
+                f,
+                "  elided{} [label=\"... {elided} more\", shape=plaintext];",
+                self.id
+            )?;
+        }
+
         Ok(())
     }
 }
 
 /// Writes the Graphviz Graph in the dot format to the specified output buffer.
-pub fn write(output: &mut dyn io::Write, root: &SyntaxNode) -> FormatterResult<()> {
+pub fn write(output: &mut dyn io::Write, roots: &[SyntaxNode]) -> FormatterResult<()> {
     writeln!(output, "graph {{")?;
-    write!(output, "{root}")?;
+    for root in roots {
+        write!(output, "{root}")?;
+    }
     writeln!(output, "}}")?;
 
     Ok(())
@@ -109,6 +141,13 @@ mod test {
         );
     }
 
+    #[test]
+    fn escape_non_ascii() {
+        assert_eq!(escape("\"é\""), r#"\"é\""#);
+        assert_eq!(escape("café\n'"), r#"café\\n\'"#);
+        assert_eq!(escape("日本\t語"), r#"日本\\t語"#);
+    }
+
     #[test]
     fn escape_borrowed() {
         match escape("foo") {
@@ -0,0 +1,183 @@
+//! HTML visualisation for our SyntaxTree representation: a self-contained page with a collapsible
+//! tree beside the source, in which hovering over a node highlights its text.
+use std::{borrow::Cow, io};
+
+use crate::{
+    FormatterResult,
+    tree_sitter::{Position, SyntaxNode},
+};
+
+const STYLE: &str = r#"
+body { display: flex; margin: 0; font-family: monospace; }
+#tree, #source { flex: 1; height: 100vh; overflow: auto; margin: 0; padding: 1em; box-sizing: border-box; }
+#source { border-left: 1px solid #ccc; }
+details, .leaf { margin-left: 1.5em; }
+summary, .leaf { cursor: default; white-space: nowrap; }
+summary:hover, .leaf:hover { background: #fff3b0; }
+.anonymous { color: #777; }
+.error { color: #c00; font-weight: bold; }
+.field { color: #07a; }
+.range { color: #999; }
+.elided { color: #999; font-style: italic; }
+mark { background: #fff3b0; }
+"#;
+
+const SCRIPT: &str = r#"
+const source = document.getElementById("source");
+const text = source.textContent;
+
+for (const node of document.querySelectorAll("[data-start]")) {
+  node.addEventListener("mouseenter", () => {
+    const [start, end] = [Number(node.dataset.start), Number(node.dataset.end)];
+    const mark = document.createElement("mark");
+    mark.textContent = text.slice(start, end);
+    source.replaceChildren(text.slice(0, start), mark, text.slice(end));
+    mark.scrollIntoView({ block: "nearest" });
+  });
+  node.addEventListener("mouseleave", () => source.replaceChildren(text));
+}
+"#;
+
+fn escape(input: &str) -> Cow<'_, str> {
+    if !input.contains(['&', '<', '>', '"', '\'']) {
+        return input.into();
+    }
+
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+        .into()
+}
+
+/// Maps positions in the source to offsets in UTF-16 code units, as JavaScript strings use
+struct Offsets<'a> {
+    source: &'a str,
+    // The byte offset and UTF-16 offset of the start of each line
+    lines: Vec<(usize, usize)>,
+}
+
+impl<'a> Offsets<'a> {
+    fn new(source: &'a str) -> Self {
+        let mut lines = vec![(0, 0)];
+        let mut utf16 = 0;
+
+        for (byte, c) in source.char_indices() {
+            utf16 += c.len_utf16();
+            if c == '\n' {
+                lines.push((byte + 1, utf16));
+            }
+        }
+
+        Self { source, lines }
+    }
+
+    fn get(&self, position: Position) -> usize {
+        let Some(&(line_start, line_utf16)) = self.lines.get(position.row as usize - 1) else {
+            return self.source.encode_utf16().count();
+        };
+
+        let column = self
+            .source
+            .get(line_start..line_start + position.column as usize - 1)
+            .map_or(0, |line| line.encode_utf16().count());
+
+        line_utf16 + column
+    }
+}
+
+fn write_node(output: &mut dyn io::Write, node: &SyntaxNode, offsets: &Offsets) -> io::Result<()> {
+    let mut label = String::new();
+    if let Some(field) = &node.field {
+        label += &format!("<span class=\"field\">{}:</span> ", escape(field));
+    }
+
+    let class = if node.is_error || node.is_missing {
+        "error"
+    } else if node.is_named {
+        "named"
+    } else {
+        "anonymous"
+    };
+    let kind = if node.is_missing {
+        format!("MISSING {}", node.kind)
+    } else {
+        node.kind.clone()
+    };
+    label += &format!(
+        "<span class=\"{class}\">{}</span> <span class=\"range\">{} - {}</span>",
+        escape(&kind),
+        node.start,
+        node.end
+    );
+
+    let range = format!(
+        "data-start=\"{}\" data-end=\"{}\"",
+        offsets.get(node.start),
+        offsets.get(node.end)
+    );
+
+    if node.children.is_empty() && node.elided.is_none() {
+        writeln!(output, "<div class=\"leaf\" {range}>{label}</div>")?;
+    } else {
+        writeln!(output, "<details open><summary {range}>{label}</summary>")?;
+        for child in &node.children {
+            write_node(output, child, offsets)?;
+        }
+        if let Some(elided) = node.elided {
+            writeln!(output, "<div class=\"leaf elided\">... {elided} more</div>")?;
+        }
+        writeln!(output, "</details>")?;
+    }
+
+    Ok(())
+}
+
+pub fn write(
+    output: &mut dyn io::Write,
+    roots: &[SyntaxNode],
+    source: &str,
+) -> FormatterResult<()> {
+    writeln!(output, "<!DOCTYPE html>")?;
+    writeln!(output, "<html lang=\"en\">")?;
+    writeln!(output, "<head>")?;
+    writeln!(output, "<meta charset=\"utf-8\">")?;
+    writeln!(output, "<title>Parse tree</title>")?;
+    writeln!(output, "<style>{STYLE}</style>")?;
+    writeln!(output, "</head>")?;
+    writeln!(output, "<body>")?;
+
+    writeln!(output, "<div id=\"tree\">")?;
+    let offsets = Offsets::new(source);
+    for root in roots {
+        write_node(output, root, &offsets)?;
+    }
+    writeln!(output, "</div>")?;
+    writeln!(output, "<pre id=\"source\">{}</pre>", escape(source))?;
+
+    writeln!(output, "<script>{SCRIPT}</script>")?;
+    writeln!(output, "</body>")?;
+    writeln!(output, "</html>")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::Offsets;
+    use crate::tree_sitter::Position;
+
+    #[test]
+    fn utf16_offsets() {
+        // "é" is two bytes, but one UTF-16 code unit; "𝄞" is four bytes, but two code units
+        let offsets = Offsets::new("é = 1\n𝄞 = 2\n");
+
+        assert_eq!(offsets.get(Position { row: 1, column: 1 }), 0);
+        assert_eq!(offsets.get(Position { row: 1, column: 3 }), 1);
+        assert_eq!(offsets.get(Position { row: 2, column: 1 }), 6);
+        assert_eq!(offsets.get(Position { row: 2, column: 5 }), 8);
+        assert_eq!(offsets.get(Position { row: 3, column: 1 }), 13);
+    }
+}
@@ -10,37 +10,48 @@
 //! More details can be found on
 //! [GitHub](https://github.com/tweag/topiary).
 
-use std::io;
+use std::{io, ops};
 
 use pretty_assertions::StrComparison;
-use tree_sitter::Position;
+use serde::Serialize;
+use tree_sitter::NodeExt;
 
 pub use crate::{
     error::{FormatterError, IoError},
     language::Language,
+    provenance::{
+        AtomTrace, Fate, Gap, LeafTrace, NodeSummary, Origin, Placement, Provenance, SkippedCapture,
+    },
     tree_sitter::{
-        CoverageData, SyntaxNode, TopiaryQuery, Visualisation, apply_query, check_query_coverage,
-        parse,
+        CaptureData, CoverageData, CoveredPattern, GrammarCoverage, Position, QueryMatchData,
+        SyntaxNode, TopiaryQuery, UnhandledKind, Visualisation, apply_query,
+        check_grammar_coverage, check_node_coverage, check_query_coverage,
+        check_query_coverage_inputs, parse, query_matches,
     },
 };
 
 mod atom_collection;
+mod atoms;
 mod error;
 mod graphviz;
+mod html;
 mod language;
+mod mermaid;
 mod pretty;
+mod provenance;
+mod sexp;
 mod tree_sitter;
 
 #[doc(hidden)]
 pub mod test_utils;
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
 pub struct ScopeInformation {
     line_number: u32,
     scope_id: String,
 }
 
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
 pub enum Capitalisation {
     UpperCase,
     LowerCase,
@@ -50,7 +61,7 @@ pub enum Capitalisation {
 /// An atom represents a small piece of the output. We turn Tree-sitter nodes
 /// into atoms, and we add white-space atoms where appropriate. The final list
 /// of atoms is rendered to the output.
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
 pub enum Atom {
     /// We don't allow consecutive `Hardline`, but a `Blankline` will render two
     /// newlines to produce a blank line.
@@ -155,7 +166,7 @@ impl Atom {
 
 /// Used in `Atom::ScopedConditional` to apply the containing Atoms only if
 /// the matched node spans a single line or multiple lines
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
 pub enum ScopeCondition {
     /// The Atom is only applied if the matching node spans exactly one line
     SingleLineOnly,
@@ -179,13 +190,156 @@ pub enum Operation {
         /// and continues formatting instead of exiting with an error
         tolerate_parsing_errors: bool,
     },
-    /// Visualises the parsed file's tree-sitter tree
+    /// Visualises the parsed file's tree-sitter tree, or the atoms produced from it
     Visualise {
         /// Choose the type of visualation Topiary should output
         output_format: Visualisation,
+        /// What to include in the parse tree visualisations
+        options: VisualiseOptions,
     },
 }
 
+/// Options of the parse tree visualisations, which the atoms visualisations ignore. More may be
+/// added, so this is built from its `Default`, which includes nothing extra.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct VisualiseOptions {
+    /// If true, the source text of leaf nodes is included in the DOT and JSON visualisations
+    pub leaf_text: bool,
+    /// If true, the byte range of each node is included in the DOT and JSON visualisations
+    pub byte_ranges: bool,
+    /// If set, the parse tree is pruned below this many levels under the visualised node(s)
+    pub depth: Option<usize>,
+}
+
+/// A range of the input to format, used with [`formatter_range`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum InputRange {
+    /// A half-open range of 0-based byte offsets
+    Bytes(ops::Range<usize>),
+    /// An inclusive range of 1-based line numbers
+    Lines(ops::RangeInclusive<usize>),
+}
+
+impl InputRange {
+    /// Resolve the range to byte offsets within the input, excluding any surrounding whitespace,
+    /// so that it doesn't needlessly widen the syntax node that encloses it.
+    fn to_byte_range(&self, input: &str) -> FormatterResult<ops::Range<usize>> {
+        let range = match self {
+            Self::Bytes(range) => {
+                if range.start > range.end
+                    || range.end > input.len()
+                    || !input.is_char_boundary(range.start)
+                    || !input.is_char_boundary(range.end)
+                {
+                    return Err(FormatterError::InvalidSelection(format!(
+                        "Byte range {range:?} is not valid for the input"
+                    )));
+                }
+
+                range.clone()
+            }
+
+            Self::Lines(range) => {
+                let lines: Vec<(usize, &str)> = input
+                    .split_inclusive('\n')
+                    .scan(0, |offset, line| {
+                        let start = *offset;
+                        *offset += line.len();
+                        Some((start, line))
+                    })
+                    .collect();
+
+                let (first, last) = (*range.start(), *range.end());
+                if first == 0 || first > last || last > lines.len() {
+                    return Err(FormatterError::InvalidSelection(format!(
+                        "Line range {first}-{last} is not valid for the input, which has {} line(s)",
+                        lines.len()
+                    )));
+                }
+
+                let (last_start, last_line) = lines[last - 1];
+                lines[first - 1].0..last_start + last_line.len()
+            }
+        };
+
+        let selected = &input[range.clone()];
+        let start = range.start + (selected.len() - selected.trim_start().len());
+
+        Ok(start..start + selected.trim().len())
+    }
+}
+
+/// The part of the parse tree to visualise, used with [`visualise_subtree`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Subtree {
+    /// The smallest node at the given position
+    At(Position),
+    /// The smallest node that encloses the given range
+    Range(InputRange),
+    /// Every node of the given kind, other than those within another such node
+    Kind(String),
+}
+
+impl Subtree {
+    /// The roots of the selected subtrees, in the order they appear in the input
+    fn select<'tree>(
+        &self,
+        root: topiary_tree_sitter_facade::Node<'tree>,
+        input: &str,
+    ) -> FormatterResult<Vec<topiary_tree_sitter_facade::Node<'tree>>> {
+        let invalid = FormatterError::InvalidSelection;
+
+        match self {
+            Self::At(position) => {
+                if *position >= Position::from(root.end_position()) {
+                    return Err(invalid(format!(
+                        "Position {position} is beyond the end of the input"
+                    )));
+                }
+
+                let point =
+                    topiary_tree_sitter_facade::Point::new(position.row - 1, position.column - 1);
+                Ok(root
+                    .descendant_for_point_range(point.clone(), point)
+                    .into_iter()
+                    .collect())
+            }
+
+            Self::Range(range) => {
+                let range = range.to_byte_range(input)?;
+                Ok(root
+                    .descendant_for_byte_range(range.start as u32, range.end as u32)
+                    .into_iter()
+                    .collect())
+            }
+
+            Self::Kind(kind) => {
+                let mut nodes = Vec::new();
+                let mut pending = vec![root];
+                while let Some(node) = pending.pop() {
+                    if node.kind() == kind.as_str() {
+                        nodes.push(node);
+                    } else {
+                        // Push in reverse, so that children are visited in order
+                        let mut cursor = node.walk();
+                        let children: Vec<_> = node.children(&mut cursor).collect();
+                        pending.extend(children.into_iter().rev());
+                    }
+                }
+
+                if nodes.is_empty() {
+                    return Err(invalid(format!(
+                        "There are no nodes of kind {kind:?} in the input"
+                    )));
+                }
+
+                Ok(nodes)
+            }
+        }
+    }
+}
+
 /// The function that takes an input and formats, or visualises an output.
 ///
 /// # Errors
@@ -314,18 +468,279 @@ pub fn formatter_tree(
             write!(output, "{rendered}")?;
         }
 
-        Operation::Visualise { output_format } => {
-            let root: SyntaxNode = tree.root_node().into();
+        Operation::Visualise {
+            output_format:
+                output_format @ (Visualisation::GraphViz
+                | Visualisation::Json
+                | Visualisation::SExpression
+                | Visualisation::Mermaid
+                | Visualisation::Html),
+            options:
+                VisualiseOptions {
+                    leaf_text,
+                    byte_ranges,
+                    depth,
+                },
+        } => {
+            let mut root = SyntaxNode::new(tree.root_node(), input_content, leaf_text, byte_ranges);
+            if let Some(depth) = depth {
+                root.prune(depth);
+            }
 
             match output_format {
-                Visualisation::GraphViz => graphviz::write(output, &root)?,
                 Visualisation::Json => serde_json::to_writer(output, &root)?,
+                _ => write_syntax_trees(output, output_format, &[root], input_content)?,
+            };
+        }
+
+        Operation::Visualise {
+            output_format: output_format @ (Visualisation::Atoms | Visualisation::AtomsRaw),
+            ..
+        } => {
+            let mut atoms = tree_sitter::apply_query_tree(tree, input_content, &language.query)?;
+
+            // Post-processing replaces atoms in place, so the two sequences line up
+            let before = atoms[..].to_vec();
+            atoms.post_process();
+
+            match output_format {
+                Visualisation::Atoms => atoms::write(output, &before, &atoms[..])?,
+                _ => atoms::write_json(output, &before, &atoms[..])?,
             };
         }
     };
     Ok(())
 }
 
+/// Writes the given subtrees of the parse tree in a visualisation format; JSON is written as a list
+fn write_syntax_trees(
+    output: &mut impl io::Write,
+    output_format: Visualisation,
+    roots: &[SyntaxNode],
+    input_content: &str,
+) -> FormatterResult<()> {
+    match output_format {
+        Visualisation::GraphViz => graphviz::write(output, roots)?,
+        Visualisation::SExpression => sexp::write(output, roots)?,
+        Visualisation::Mermaid => mermaid::write(output, roots)?,
+        Visualisation::Html => html::write(output, roots, input_content)?,
+        _ => serde_json::to_writer(output, roots)?,
+    };
+
+    Ok(())
+}
+
+/// The function that takes a string slice and visualises only the selected subtrees of its parse
+/// tree. A single selected subtree is visualised as the whole tree would be, except that its JSON
+/// is wrapped in a list when selecting nodes by kind. Formatting, and the atoms visualisations,
+/// ignore the selection.
+///
+/// # Errors
+///
+/// If visualisation fails for any reason, a `FormatterError` will be returned. This includes the
+/// selection not matching anything in the input.
+pub fn visualise_subtree(
+    input: &str,
+    output: &mut impl io::Write,
+    language: &Language,
+    operation: Operation,
+    subtree: &Subtree,
+) -> FormatterResult<()> {
+    let Operation::Visualise {
+        output_format:
+            output_format @ (Visualisation::GraphViz
+            | Visualisation::Json
+            | Visualisation::SExpression
+            | Visualisation::Mermaid
+            | Visualisation::Html),
+        options:
+            VisualiseOptions {
+                leaf_text,
+                byte_ranges,
+                depth,
+            },
+    } = operation
+    else {
+        return formatter_str(input, output, language, operation);
+    };
+
+    let tree = tree_sitter::parse(input, &language.grammar, false)?;
+    let roots: Vec<SyntaxNode> = subtree
+        .select(tree.root_node(), input)?
+        .into_iter()
+        .map(|node| {
+            let mut root = SyntaxNode::new(node, input, leaf_text, byte_ranges);
+            if let Some(depth) = depth {
+                root.prune(depth);
+            }
+            root
+        })
+        .collect();
+
+    match (output_format, subtree, &roots[..]) {
+        (Visualisation::Json, Subtree::At(_) | Subtree::Range(_), [root]) => {
+            serde_json::to_writer(output, root)?
+        }
+        _ => write_syntax_trees(output, output_format, &roots, input)?,
+    };
+
+    Ok(())
+}
+
+/// The function that takes a string slice and formats only the given range of it. The smallest
+/// syntax node that encloses the range is formatted, and indented to fit where it sits in the
+/// input; everything outside of that node is left untouched. Visualisation ignores the range.
+///
+/// The query is matched against the whole input, so the node is formatted as it would be when
+/// formatting everything, including by patterns that involve its ancestors or neighbours. Only
+/// what lies between the node's first and last leaves is formatted, however, so anything that the
+/// query adds before or after the node itself (e.g., a hardline appended to it) is not.
+///
+/// # Errors
+///
+/// If formatting fails for any reason, a `FormatterError` will be returned. This includes the
+/// range not being valid for the input.
+pub fn formatter_range(
+    input: &str,
+    output: &mut impl io::Write,
+    language: &Language,
+    operation: Operation,
+    range: &InputRange,
+) -> FormatterResult<()> {
+    let Operation::Format {
+        skip_idempotence,
+        tolerate_parsing_errors,
+    } = operation
+    else {
+        return formatter_str(input, output, language, operation);
+    };
+
+    let range = range.to_byte_range(input)?;
+    let (rendered, fragment) = format_range(input, language, range, tolerate_parsing_errors)?;
+
+    if !skip_idempotence {
+        log::info!("Checking for idempotence ...");
+
+        match format_range(&rendered, language, fragment, tolerate_parsing_errors) {
+            Ok((reformatted, _)) if reformatted == rendered => (),
+
+            Ok((reformatted, _)) => {
+                log::error!("Failed idempotence check");
+                log::error!("{}", StrComparison::new(&rendered, &reformatted));
+                return Err(FormatterError::Idempotence);
+            }
+
+            Err(error @ FormatterError::Parsing { .. }) => {
+                return Err(FormatterError::IdempotenceParsing(Box::new(error)));
+            }
+
+            Err(error) => return Err(error),
+        }
+    }
+
+    write!(output, "{rendered}")?;
+
+    Ok(())
+}
+
+/// Format the smallest syntax node that encloses the given byte range, returning the full output
+/// along with the byte range of the formatted node within it.
+fn format_range(
+    input: &str,
+    language: &Language,
+    range: ops::Range<usize>,
+    tolerate_parsing_errors: bool,
+) -> FormatterResult<(String, ops::Range<usize>)> {
+    // Parse the whole input, even when there's nothing to format, so parsing errors are reported
+    let tree = tree_sitter::parse(input, &language.grammar, tolerate_parsing_errors)?;
+
+    if range.is_empty() {
+        return Ok((input.to_string(), range));
+    }
+
+    let root = tree.root_node();
+    let node = root
+        .named_descendant_for_byte_range(range.start as u32, range.end as u32)
+        .unwrap_or(root);
+
+    log::debug!("Formatting range as {}", node.display_one_based());
+
+    let mut atoms = tree_sitter::apply_query_node(&root, input, &language.query, false)?;
+    atoms.post_process();
+
+    // The atoms from the node's first leaf to its last; where the node has none of its own (e.g.,
+    // it is within a leaf), there's nothing to format
+    let (from, to) = (
+        Position::from(node.start_position()),
+        Position::from(node.end_position()),
+    );
+    let within = |atom: &Atom| matches!(atom, Atom::Leaf { original_position, .. } if (from..to).contains(original_position));
+    let (start, end) = (node.start_byte() as usize, node.end_byte() as usize);
+    let (Some(first), Some(last)) = (
+        atoms[..].iter().position(within),
+        atoms[..].iter().rposition(within),
+    ) else {
+        return Ok((input.to_string(), start..end));
+    };
+
+    // Indentation blocks that the fragment closes were opened before it, so are dropped
+    let mut depth = 0;
+    let fragment: Vec<Atom> = atoms[first..=last]
+        .iter()
+        .filter(|atom| match atom {
+            Atom::IndentStart => {
+                depth += 1;
+                true
+            }
+            Atom::IndentEnd if depth == 0 => false,
+            Atom::IndentEnd => {
+                depth -= 1;
+                true
+            }
+            _ => true,
+        })
+        .cloned()
+        .collect();
+
+    // The node's surroundings on its first line determine how the rendered fragment is indented
+    let line_prefix = &input[input[..start].rfind('\n').map_or(0, |idx| idx + 1)..start];
+    let base_indent = &line_prefix[..line_prefix.len() - line_prefix.trim_start().len()];
+
+    log::debug!("Pretty-print output");
+    let rendered = pretty::render_fragment(
+        &fragment,
+        // Default to "  " if the language has no indentation specified
+        language.indent.as_ref().map_or("  ", |v| v.as_str()),
+        line_prefix,
+        base_indent,
+    )?;
+
+    Ok((
+        format!("{}{rendered}{}", &input[..start], &input[end..]),
+        start..start + rendered.len(),
+    ))
+}
+
+/// Formats the input as [`formatter_str`] would, but rather than the output, returns the
+/// provenance of every atom: the query pattern and capture that created it, and what became of it
+/// during post-processing. This is to explain formatting decisions.
+///
+/// # Errors
+///
+/// If the input cannot be parsed, or the query cannot be applied, a `FormatterError` will be
+/// returned.
+pub fn explain(
+    input: &str,
+    language: &Language,
+    tolerate_parsing_errors: bool,
+) -> FormatterResult<Provenance> {
+    let tree = tree_sitter::parse(input, &language.grammar, tolerate_parsing_errors)?;
+    let mut atoms = tree_sitter::apply_query_node(&tree.root_node(), input, &language.query, true)?;
+    atoms.post_process();
+
+    Ok(atoms.into_provenance().unwrap_or_default())
+}
+
 /// Simple helper function to read the full content of an io Read stream
 fn read_input(input: &mut dyn io::Read) -> Result<String, io::Error> {
     let mut content = String::new();
@@ -386,7 +801,8 @@ mod tests {
     use test_log::test;
 
     use crate::{
-        Language, Operation, TopiaryQuery, error::FormatterError, formatter,
+        Fate, InputRange, Language, Operation, Position, TopiaryQuery, error::FormatterError,
+        explain, formatter, formatter_range, formatter_str, query_matches,
         test_utils::pretty_assert_eq,
     };
 
@@ -454,4 +870,233 @@ mod tests {
 
         pretty_assert_eq(expected, &formatted);
     }
+
+    fn json() -> Language {
+        let query_content = fs::read_to_string("../topiary-queries/queries/json.scm").unwrap();
+        let grammar = tree_sitter_json::LANGUAGE.into();
+
+        Language {
+            name: "json".to_owned(),
+            query: TopiaryQuery::new(&grammar, &query_content).unwrap(),
+            grammar,
+            indent: None,
+        }
+    }
+
+    fn format_range(input: &str, range: InputRange) -> Result<String, FormatterError> {
+        let mut output = Vec::new();
+
+        formatter_range(
+            input,
+            &mut output,
+            &json(),
+            Operation::Format {
+                skip_idempotence: false,
+                tolerate_parsing_errors: false,
+            },
+            &range,
+        )?;
+
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    /// Only the node enclosing the range is formatted, indented for where it sits
+    #[test(tokio::test)]
+    async fn range_formatting() {
+        let input =
+            "{\"a\":   1,\n  \"b\": {\"c\":[1,2],\n        \"d\":   \"x\"},\n    \"e\":    3}\n";
+        let expected = "{\"a\":   1,\n  \"b\": {\n    \"c\": [ 1, 2 ],\n    \"d\": \"x\"\n  },\n    \"e\":    3}\n";
+
+        let start = input.find("\"b\"").unwrap();
+        let end = input.find("\"x\"}").unwrap() + 4;
+
+        pretty_assert_eq(
+            expected,
+            &format_range(input, InputRange::Bytes(start..end)).unwrap(),
+        );
+    }
+
+    /// Surrounding whitespace doesn't widen the range, and whole lines can be selected
+    #[test(tokio::test)]
+    async fn line_range_formatting() {
+        let input = "[\n  1,\n  {\"a\":1},\n  3\n]\n";
+        let expected = "[\n  1,\n  { \"a\": 1 },\n  3\n]\n";
+
+        pretty_assert_eq(
+            expected,
+            &format_range(input, InputRange::Lines(3..=3)).unwrap(),
+        );
+    }
+
+    /// A range is formatted as it is when formatting everything, including by patterns rooted at
+    /// the node's ancestors, without losing the whitespace within it
+    #[test(tokio::test)]
+    async fn range_formatting_matches_whole_input() {
+        let query_content = fs::read_to_string("../topiary-queries/queries/json.scm").unwrap()
+            + "(pair value: (object (pair \":\" @prepend_space)))";
+        let grammar = tree_sitter_json::LANGUAGE.into();
+        let language = Language {
+            name: "json".to_owned(),
+            query: TopiaryQuery::new(&grammar, &query_content).unwrap(),
+            grammar,
+            indent: None,
+        };
+        let operation = Operation::Format {
+            skip_idempotence: false,
+            tolerate_parsing_errors: false,
+        };
+
+        for (input, selected) in [
+            ("{ \"a\": {\"b\":1} }\n", "{\"b\":1}"),
+            (
+                "{\n  \"a\": {\"b\":1,\n\"c\":2}\n}\n",
+                "{\"b\":1,\n\"c\":2}",
+            ),
+        ] {
+            let mut whole = Vec::new();
+            formatter_str(input, &mut whole, &language, operation).unwrap();
+
+            let start = input.find(selected).unwrap();
+            let mut ranged = Vec::new();
+            formatter_range(
+                input,
+                &mut ranged,
+                &language,
+                operation,
+                &InputRange::Bytes(start..start + selected.len()),
+            )
+            .unwrap();
+
+            pretty_assert_eq(
+                &String::from_utf8(whole).unwrap(),
+                &String::from_utf8(ranged).unwrap(),
+            );
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn invalid_range_fails_formatting() {
+        let input = "[1]\n";
+
+        for range in [InputRange::Bytes(0..10), InputRange::Lines(2..=3)] {
+            match format_range(input, range) {
+                Err(FormatterError::InvalidSelection(_)) => {}
+                result => panic!("Expected an invalid range error, but got {result:?}"),
+            }
+        }
+    }
+
+    /// Every match is reported with its captures, which are not applied as formatting directives
+    #[test(tokio::test)]
+    async fn query_matches_report_captures() {
+        let grammar = tree_sitter_json::LANGUAGE.into();
+        let query = TopiaryQuery::new(
+            &grammar,
+            "(pair key: (_) @append_space value: (number) @value)",
+        )
+        .unwrap();
+
+        let matches = query_matches("{\"a\": 1,\n \"b\": \"c\"}", &query, &grammar, false).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].pattern_index, 0);
+
+        let captures: Vec<_> = matches[0]
+            .captures
+            .iter()
+            .map(|capture| {
+                (
+                    capture.name.as_str(),
+                    capture.kind.as_str(),
+                    capture.text.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            captures,
+            [
+                ("append_space", "string", "\"a\""),
+                ("value", "number", "1")
+            ]
+        );
+
+        assert_eq!(matches[0].captures[1].start, Position { row: 1, column: 7 });
+        assert_eq!(
+            matches[0].captures[1].to_string(),
+            "@value {Node \"number\" (1,7) - (1,8)} \"1\""
+        );
+    }
+
+    /// Atoms are traced back to the pattern that created them, including those dropped during
+    /// post-processing and captures that created none
+    #[test(tokio::test)]
+    async fn explain_traces_atoms() {
+        let grammar = tree_sitter_json::LANGUAGE.into();
+        let query_content = r#"
+            (
+              (pair ":" @append_space)
+              (#query_name! "pair spacing")
+            )
+            (pair value: (_) @prepend_hardline)
+            ((pair) @append_space (#multi_line_only!))
+        "#;
+        let language = Language {
+            name: "json".to_owned(),
+            query: TopiaryQuery::new(&grammar, query_content).unwrap(),
+            grammar,
+            indent: None,
+        };
+
+        let provenance = explain("{\"a\":1}", &language, false).unwrap();
+
+        let leaf = provenance.leaf_at(Position { row: 1, column: 6 }).unwrap();
+        assert_eq!(provenance.leaves[leaf].text, "1");
+
+        let gap = provenance.gap_before(leaf);
+        assert_eq!(gap.atoms.len(), 2);
+
+        let (space, hardline) = (gap.atoms[0], gap.atoms[1]);
+        assert_eq!(space.atom, "Space");
+        assert_eq!(space.origin.pattern_index, 0);
+        assert_eq!(
+            space.origin.pattern_position,
+            Position { row: 2, column: 13 }
+        );
+        assert_eq!(space.origin.query_name.as_deref(), Some("pair spacing"));
+        assert_eq!(space.origin.capture, "append_space");
+        assert!(matches!(
+            &space.fate,
+            Fate::Dropped { by: Some(by), .. } if *by == hardline.id
+        ));
+
+        assert_eq!(hardline.origin.pattern_index, 1);
+        assert!(matches!(&hardline.fate, Fate::Kept { atom } if atom == "Hardline"));
+
+        let gap = provenance.gap_after(leaf);
+        assert!(gap.atoms.is_empty());
+        assert_eq!(gap.skipped.len(), 1);
+        assert_eq!(gap.skipped[0].origin.pattern_index, 2);
+    }
+
+    /// Unknown capture names and predicates are found without any input
+    #[test]
+    fn query_check_finds_problems() {
+        let grammar = tree_sitter_json::LANGUAGE.into();
+        let query_content = r#"
+            (pair ":" @append_space)
+            (pair ":" @apend_space)
+            ((pair) @append_space (#single_line_only!) (#multi_line_only!))
+            ((pair) @append_space (#single_line!))
+        "#;
+        let query = TopiaryQuery::new(&grammar, query_content).unwrap();
+
+        assert_eq!(
+            query.check(),
+            [
+                "@apend_space is not a valid capture name",
+                "Pattern at (4,13): A query can contain at most one \
+                 #single/multi_line[_scope]_only! predicate",
+                "Pattern at (5,13): single_line! is an unknown predicate. Maybe you forgot a \"!\"?",
+            ]
+        );
+    }
 }
@@ -0,0 +1,74 @@
+//! Mermaid visualisation for our SyntaxTree representation, for embedding in Markdown.
+//! Named syntax nodes are rounded; anonymous are rectangular. Edges are labelled with field names.
+use std::{borrow::Cow, io};
+
+use crate::{FormatterResult, tree_sitter::SyntaxNode};
+
+/// Replaces the characters that Mermaid would otherwise interpret, within a quoted label, with
+/// its entity codes
+fn escape(input: &str) -> Cow<'_, str> {
+    if !input.contains(['#', '"', '<', '>']) {
+        return input.into();
+    }
+
+    // The entity codes themselves start with #, so that must be replaced first
+    input
+        .replace('#', "#35;")
+        .replace('"', "#quot;")
+        .replace('<', "#lt;")
+        .replace('>', "#gt;")
+        .into()
+}
+
+fn write_node(output: &mut dyn io::Write, node: &SyntaxNode) -> io::Result<()> {
+    let kind = escape(&node.kind);
+    if node.is_named {
+        writeln!(output, "  n{}(\"{kind}\")", node.id)?;
+    } else {
+        writeln!(output, "  n{}[\"{kind}\"]", node.id)?;
+    }
+
+    for child in &node.children {
+        match &child.field {
+            Some(field) => writeln!(
+                output,
+                "  n{} -->|{}| n{}",
+                node.id,
+                escape(field),
+                child.id
+            )?,
+            None => writeln!(output, "  n{} --> n{}", node.id, child.id)?,
+        }
+
+        write_node(output, child)?;
+    }
+
+    if let Some(elided) = node.elided {
+        writeln!(output, "  n{} -.- n{}_elided", node.id, node.id)?;
+        writeln!(output, "  n{}_elided[\"... {elided} more\"]", node.id)?;
+    }
+
+    Ok(())
+}
+
+pub fn write(output: &mut dyn io::Write, roots: &[SyntaxNode]) -> FormatterResult<()> {
+    writeln!(output, "graph TD")?;
+    for root in roots {
+        write_node(output, root)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::escape;
+    use std::borrow::Cow;
+
+    #[test]
+    fn escape_entities() {
+        assert!(matches!(escape("foo"), Cow::Borrowed("foo")));
+        assert_eq!(escape("\""), "#quot;");
+        assert_eq!(escape("#<>"), "#35;#lt;#gt;");
+    }
+}
@@ -15,16 +15,34 @@ use crate::{Atom, Capitalisation, FormatterError, FormatterResult};
 ///
 /// If an unexpected Atom is encountered, a `FormatterError::Internal` is returned.
 pub fn render(atoms: &[Atom], indent: &str) -> FormatterResult<String> {
-    let mut buffer = String::new();
+    render_fragment(atoms, indent, "", "")
+}
+
+/// Renders a slice of Atoms that will be placed after `line_prefix`, the text that precedes them
+/// on their first line. Each subsequent line is additionally indented by `base_indent`, so that
+/// the fragment sits correctly within its surroundings. The returned string does not include
+/// `line_prefix`.
+///
+/// # Errors
+///
+/// If an unexpected Atom is encountered, a `FormatterError::Internal` is returned.
+pub(crate) fn render_fragment(
+    atoms: &[Atom],
+    indent: &str,
+    line_prefix: &str,
+    base_indent: &str,
+) -> FormatterResult<String> {
+    // We start with the line prefix, so that column computations are correct on the first line
+    let mut buffer = String::from(line_prefix);
     let mut indent_level: usize = 0;
 
     for atom in atoms {
         match atom {
-            Atom::Blankline => write!(buffer, "\n\n{}", indent.repeat(indent_level))?,
+            Atom::Blankline => write!(buffer, "\n\n{base_indent}{}", indent.repeat(indent_level))?,
 
             Atom::Empty => (),
 
-            Atom::Hardline => write!(buffer, "\n{}", indent.repeat(indent_level))?,
+            Atom::Hardline => write!(buffer, "\n{base_indent}{}", indent.repeat(indent_level))?,
 
             Atom::IndentEnd => {
                 if indent_level == 0 {
@@ -102,7 +120,7 @@ pub fn render(atoms: &[Atom], indent: &str) -> FormatterResult<String> {
         };
     }
 
-    Ok(buffer)
+    Ok(buffer.split_off(line_prefix.len()))
 }
 
 fn current_column(s: &str) -> usize {
@@ -0,0 +1,368 @@
+//! The provenance of atoms: which query pattern and capture created each atom, and what became of
+//! it during post-processing. This is only recorded on request, to explain formatting decisions.
+
+use std::{collections::HashMap, fmt::Display};
+
+use serde::Serialize;
+use topiary_tree_sitter_facade::Node;
+
+use crate::{Atom, tree_sitter::Position};
+
+/// A syntax node, as referred to by provenance
+#[derive(Clone, Debug, Serialize)]
+pub struct NodeSummary {
+    pub kind: String,
+    pub start: Position,
+    pub end: Position,
+}
+
+impl From<&Node<'_>> for NodeSummary {
+    fn from(node: &Node) -> Self {
+        Self {
+            kind: node.kind().into(),
+            start: node.start_position().into(),
+            end: node.end_position().into(),
+        }
+    }
+}
+
+impl Display for NodeSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{{Node {:?} {} - {}}}", self.kind, self.start, self.end)
+    }
+}
+
+/// The query pattern and capture that created an atom
+#[derive(Clone, Debug, Serialize)]
+pub struct Origin {
+    pub pattern_index: usize,
+    /// The position of the pattern in the query
+    pub pattern_position: Position,
+    /// The name given to the pattern by `#query_name!`, if any
+    pub query_name: Option<String>,
+    pub capture: String,
+    /// The captured node
+    pub node: NodeSummary,
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "@{} on {}, from pattern {} at {}",
+            self.capture, self.node, self.pattern_index, self.pattern_position
+        )?;
+
+        if let Some(name) = &self.query_name {
+            write!(f, " ({name:?})")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether an atom was prepended or appended to its leaf
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Placement {
+    Prepended,
+    Appended,
+}
+
+/// What became of an atom
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Fate {
+    /// The atom is output, possibly after being replaced by another
+    Kept { atom: String },
+
+    /// The atom was removed, possibly because of another atom
+    Dropped { reason: String, by: Option<usize> },
+}
+
+/// An atom created by a capture, with its provenance
+#[derive(Clone, Debug, Serialize)]
+pub struct AtomTrace {
+    pub id: usize,
+    /// The atom, as it was created
+    pub atom: String,
+    pub origin: Origin,
+    pub placement: Placement,
+    /// The leaf that the atom was attached to, if that was output
+    pub leaf: Option<usize>,
+    /// The decisions made about the atom, in order
+    pub decisions: Vec<String>,
+    pub fate: Fate,
+
+    // The atom's position in the atom collection, once prepends and appends have been applied
+    #[serde(skip)]
+    position: Option<usize>,
+}
+
+/// A leaf of the output: a syntax node whose contents are output as they are
+#[derive(Clone, Debug, Serialize)]
+pub struct LeafTrace {
+    pub node: NodeSummary,
+    pub text: String,
+
+    #[serde(skip)]
+    id: usize,
+    #[serde(skip)]
+    position: Option<usize>,
+}
+
+/// A capture that did not create any atoms, and why
+#[derive(Clone, Debug, Serialize)]
+pub struct SkippedCapture {
+    pub origin: Origin,
+    pub reason: String,
+}
+
+/// The provenance of every atom created while formatting an input
+#[derive(Debug, Default, Serialize)]
+pub struct Provenance {
+    pub leaves: Vec<LeafTrace>,
+    pub atoms: Vec<AtomTrace>,
+    pub skipped: Vec<SkippedCapture>,
+}
+
+/// Everything that went into the whitespace between two leaves
+#[derive(Debug, Serialize)]
+pub struct Gap<'a> {
+    /// The atoms between the leaves, in output order
+    pub atoms: Vec<&'a AtomTrace>,
+    /// The captures on nodes bordering the gap that did not create atoms
+    pub skipped: Vec<&'a SkippedCapture>,
+}
+
+impl Provenance {
+    /// The index of the leaf at the given position, or of the first leaf after it
+    pub fn leaf_at(&self, position: Position) -> Option<usize> {
+        self.leaves.iter().position(|leaf| position < leaf.node.end)
+    }
+
+    /// The gap between the given leaf and the one before it
+    pub fn gap_before(&self, leaf: usize) -> Gap<'_> {
+        self.gap(leaf.checked_sub(1), Some(leaf))
+    }
+
+    /// The gap between the given leaf and the one after it
+    pub fn gap_after(&self, leaf: usize) -> Gap<'_> {
+        self.gap(
+            Some(leaf),
+            Some(leaf + 1).filter(|&next| next < self.leaves.len()),
+        )
+    }
+
+    fn gap(&self, before: Option<usize>, after: Option<usize>) -> Gap<'_> {
+        let before = before.map(|leaf| &self.leaves[leaf]);
+        let after = after.map(|leaf| &self.leaves[leaf]);
+
+        let start = before.and_then(|leaf| leaf.position).map_or(0, |p| p + 1);
+        let end = after.and_then(|leaf| leaf.position).unwrap_or(usize::MAX);
+
+        let mut atoms: Vec<&AtomTrace> = self
+            .atoms
+            .iter()
+            .filter(|atom| atom.position.is_some_and(|p| (start..end).contains(&p)))
+            .collect();
+        atoms.sort_by_key(|atom| atom.position);
+
+        // Captures are skipped before their target leaf is known, but appends go to the end of
+        // the captured node and prepends to its start
+        let skipped = self
+            .skipped
+            .iter()
+            .filter(|skipped| {
+                let origin = &skipped.origin;
+                let appended_to_before = before.is_some_and(|leaf| {
+                    origin.capture.starts_with("append") && origin.node.end == leaf.node.end
+                });
+                let prepended_to_after = after.is_some_and(|leaf| {
+                    !origin.capture.starts_with("append") && origin.node.start == leaf.node.start
+                });
+
+                appended_to_before || prepended_to_after
+            })
+            .collect();
+
+        Gap { atoms, skipped }
+    }
+}
+
+/// Records provenance while an atom collection is built and post-processed. Each atom created by
+/// a capture is traced, by its position, through the atom collection.
+#[derive(Debug, Default)]
+pub(crate) struct Tracer {
+    provenance: Provenance,
+
+    // The capture being resolved, and the decisions made for the next atom it creates
+    origin: Option<Origin>,
+    decisions: Vec<String>,
+
+    // The traced atoms to prepend and append to each leaf node, in step with the atom collection
+    pub(crate) prepend: HashMap<usize, Vec<usize>>,
+    pub(crate) append: HashMap<usize, Vec<usize>>,
+
+    // The traced atom at each position of the atom collection, once expanded
+    positions: Vec<Option<usize>>,
+}
+
+impl Tracer {
+    /// Record a leaf, as it is collected
+    pub(crate) fn leaf(&mut self, node: &Node, text: &str) {
+        self.provenance.leaves.push(LeafTrace {
+            node: node.into(),
+            text: text.into(),
+            id: node.id(),
+            position: None,
+        });
+    }
+
+    /// Start resolving a capture
+    pub(crate) fn capture(&mut self, origin: Origin) {
+        self.origin = Some(origin);
+        self.decisions.clear();
+    }
+
+    /// Record that the current capture creates no atoms
+    pub(crate) fn skip(&mut self, reason: impl Into<String>) {
+        if let Some(origin) = self.origin.clone() {
+            self.provenance.skipped.push(SkippedCapture {
+                origin,
+                reason: reason.into(),
+            });
+        }
+    }
+
+    /// Record a decision about the next atom created by the current capture
+    pub(crate) fn decide(&mut self, decision: impl Into<String>) {
+        self.decisions.push(decision.into());
+    }
+
+    /// Record an atom created by the current capture, and attached to the given leaf node
+    pub(crate) fn created(&mut self, atom: &Atom, leaf: &Node, placement: Placement) {
+        let Some(origin) = self.origin.clone() else {
+            return;
+        };
+
+        let id = self.provenance.atoms.len();
+        let decisions = std::mem::take(&mut self.decisions);
+        let fate = match atom {
+            Atom::Empty => Fate::Dropped {
+                reason: decisions.last().cloned().unwrap_or_default(),
+                by: None,
+            },
+            _ => Fate::Kept {
+                atom: format!("{atom:?}"),
+            },
+        };
+
+        self.provenance.atoms.push(AtomTrace {
+            id,
+            atom: format!("{atom:?}"),
+            origin,
+            placement,
+            leaf: None,
+            decisions,
+            fate,
+            position: None,
+        });
+
+        let traces = match placement {
+            Placement::Prepended => &mut self.prepend,
+            Placement::Appended => &mut self.append,
+        };
+        traces.entry(leaf.id()).or_default().push(id);
+    }
+
+    /// Record an untraced atom at the next position of the expanded atom collection
+    pub(crate) fn expand(&mut self) {
+        self.positions.push(None);
+    }
+
+    /// Record the position of a leaf, in the expanded atom collection, and attach its atoms to it
+    pub(crate) fn expand_leaf(&mut self, id: usize, prepends: &[usize], appends: &[usize]) {
+        self.positions.extend(prepends.iter().copied().map(Some));
+
+        let leaf = self.provenance.leaves.iter().position(|leaf| leaf.id == id);
+        if let Some(leaf) = leaf {
+            self.provenance.leaves[leaf].position = Some(self.positions.len());
+        }
+        self.positions.push(None);
+        self.positions.extend(appends.iter().copied().map(Some));
+
+        for &trace in prepends.iter().chain(appends) {
+            self.provenance.atoms[trace].leaf = leaf;
+        }
+    }
+
+    fn trace_at(&mut self, position: usize) -> Option<&mut AtomTrace> {
+        let trace = (*self.positions.get(position)?)?;
+        self.provenance.atoms.get_mut(trace)
+    }
+
+    /// Record that the atom at the given position was replaced by another
+    pub(crate) fn replaced(&mut self, position: usize, replacement: &Atom, reason: &str) {
+        if let Some(trace) = self.trace_at(position) {
+            trace
+                .decisions
+                .push(format!("replaced by {replacement:?}: {reason}"));
+        }
+
+        if *replacement == Atom::Empty {
+            self.dropped(position, reason, None);
+        }
+    }
+
+    /// Record that the atom at the given position was removed, possibly because of the atom at
+    /// another position
+    pub(crate) fn dropped(&mut self, position: usize, reason: &str, by: Option<usize>) {
+        let by = by.and_then(|by| *self.positions.get(by)?);
+
+        if let Some(trace) = self.trace_at(position)
+            && !matches!(trace.fate, Fate::Dropped { .. })
+        {
+            trace.fate = Fate::Dropped {
+                reason: reason.into(),
+                by,
+            };
+        }
+    }
+
+    /// Record that the atoms at the given positions were swapped
+    pub(crate) fn swapped(&mut self, moved: usize, other: usize, reason: &str) {
+        if let Some(trace) = self.trace_at(moved) {
+            trace.decisions.push(reason.into());
+        }
+
+        self.positions.swap(moved, other);
+    }
+
+    /// The provenance of every atom, given the post-processed atom collection
+    pub(crate) fn finish(mut self, atoms: &[Atom]) -> Provenance {
+        for (position, trace) in self.positions.iter().enumerate() {
+            let Some(trace) = trace.and_then(|trace| self.provenance.atoms.get_mut(trace)) else {
+                continue;
+            };
+
+            trace.position = Some(position);
+            if let Fate::Kept { atom } = &mut trace.fate {
+                *atom = format!("{:?}", atoms[position]);
+            }
+        }
+
+        // Atoms attached to leaves that are not output (i.e., empty nodes) never make it into
+        // the atom collection
+        for trace in &mut self.provenance.atoms {
+            if trace.position.is_none() && matches!(trace.fate, Fate::Kept { .. }) {
+                trace.fate = Fate::Dropped {
+                    reason: "attached to an empty node, which is not output".into(),
+                    by: None,
+                };
+            }
+        }
+
+        self.provenance
+    }
+}
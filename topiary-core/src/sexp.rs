@@ -0,0 +1,58 @@
+//! S-expression visualisation for our SyntaxTree representation, in the notation of Tree-sitter's
+//! CLI and playground: named nodes only, with their field names and 0-based ranges.
+use std::io;
+
+use crate::{FormatterResult, tree_sitter::SyntaxNode};
+
+fn write_node(output: &mut dyn io::Write, node: &SyntaxNode, depth: usize) -> io::Result<()> {
+    if depth > 0 {
+        write!(output, "\n{}", "  ".repeat(depth))?;
+    }
+
+    if let Some(field) = &node.field {
+        write!(output, "{field}: ")?;
+    }
+
+    write!(output, "(")?;
+    if node.is_missing {
+        write!(output, "MISSING ")?;
+    }
+
+    if node.is_named {
+        write!(output, "{}", node.kind)?;
+    } else {
+        write!(output, "{:?}", node.kind)?;
+    }
+
+    write!(
+        output,
+        " [{}, {}] - [{}, {}]",
+        node.start.row - 1,
+        node.start.column - 1,
+        node.end.row - 1,
+        node.end.column - 1
+    )?;
+
+    for child in node
+        .children
+        .iter()
+        .filter(|child| child.is_named || child.is_missing)
+    {
+        write_node(output, child, depth + 1)?;
+    }
+
+    if node.elided.is_some() {
+        write!(output, " ...")?;
+    }
+
+    write!(output, ")")
+}
+
+pub fn write(output: &mut dyn io::Write, roots: &[SyntaxNode]) -> FormatterResult<()> {
+    for root in roots {
+        write_node(output, root, 0)?;
+        writeln!(output)?;
+    }
+
+    Ok(())
+}
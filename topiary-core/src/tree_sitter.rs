@@ -2,7 +2,10 @@
 // streaming_iterator::StreamingIterator
 #![cfg_attr(target_arch = "wasm32", allow(unused_imports))]
 
-use std::{collections::HashSet, fmt::Display};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
 
 use miette::{LabeledSpan, Severity, SourceSpan};
 use serde::Serialize;
@@ -15,8 +18,9 @@ use streaming_iterator::StreamingIterator;
 
 use crate::{
     FormatterResult,
-    atom_collection::{AtomCollection, QueryPredicates},
+    atom_collection::{AtomCollection, QueryPredicates, dfs_flatten},
     error::FormatterError,
+    provenance::Origin,
 };
 
 /// Supported visualisation formats
@@ -24,12 +28,21 @@ use crate::{
 pub enum Visualisation {
     GraphViz,
     Json,
+    /// Tree-sitter's S-expression notation, as used by its playground
+    SExpression,
+    Mermaid,
+    /// A self-contained HTML page, with a collapsible tree beside the source
+    Html,
+    /// The atoms from the query, before and after post-processing
+    Atoms,
+    /// The atoms from the query, before and after post-processing, as JSON
+    AtomsRaw,
 }
 
 /// Refers to a position within the code. Used for error reporting, and for
 /// comparing input with formatted output. The numbers are 1-based, because that
 /// is how editors usually refer to a position. Derived from tree_sitter::Point.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
 pub struct Position {
     pub row: u32,
     pub column: u32,
@@ -96,6 +109,37 @@ impl TopiaryQuery {
     pub fn pattern_position(&self, _pattern_index: usize) -> Position {
         unimplemented!()
     }
+
+    /// Check the query's capture names and predicates, which are otherwise only checked once a
+    /// pattern that uses them matches. Each problem is described with its pattern's position.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn check(&self) -> Vec<String> {
+        let mut problems: Vec<String> = self
+            .query
+            .capture_names()
+            .into_iter()
+            .filter(|name| !AtomCollection::CAPTURE_NAMES.contains(name))
+            .map(|name| format!("@{name} is not a valid capture name"))
+            .collect();
+
+        for pattern_index in 0..self.query.pattern_count() {
+            let position = self.pattern_position(pattern_index);
+            let mut predicates = QueryPredicates::default();
+
+            for predicate in self.query.general_predicates(pattern_index) {
+                match handle_predicate(&predicate, &predicates) {
+                    Ok(handled) => predicates = handled,
+                    Err(e) => problems.push(format!("Pattern at {position}: {e}")),
+                }
+            }
+
+            if let Err(e) = check_predicates(&predicates) {
+                problems.push(format!("Pattern at {position}: {e}"));
+            }
+        }
+
+        problems
+    }
 }
 
 impl From<Point> for Position {
@@ -114,37 +158,108 @@ pub struct SyntaxNode {
     pub id: usize,
 
     pub kind: String,
+    /// The name of the field that the node is in, within its parent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
     pub is_named: bool,
-    is_extra: bool,
-    is_error: bool,
-    is_missing: bool,
-    start: Position,
-    end: Position,
+    pub(crate) is_extra: bool,
+    pub(crate) is_error: bool,
+    pub(crate) is_missing: bool,
+    pub(crate) start: Position,
+    pub(crate) end: Position,
+    /// The node's byte range in the source, when requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) start_byte: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) end_byte: Option<u32>,
+    /// The source text of a leaf node, when requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) text: Option<String>,
 
     pub children: Vec<SyntaxNode>,
+    /// The number of children pruned from the visualisation, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) elided: Option<usize>,
 }
 
-impl From<Node<'_>> for SyntaxNode {
-    fn from(node: Node) -> Self {
+impl SyntaxNode {
+    /// Simplify the given node and its subtree, optionally with the source text of leaf nodes and
+    /// the byte range of every node
+    pub fn new(node: Node, source: &str, leaf_text: bool, byte_ranges: bool) -> Self {
+        Self::from_node(&node, &|node| {
+            let text = (leaf_text && node.child_count() == 0).then(|| {
+                String::from_utf8_lossy(
+                    &source.as_bytes()[node.start_byte() as usize..node.end_byte() as usize],
+                )
+                .into_owned()
+            });
+            let range = byte_ranges.then(|| (node.start_byte(), node.end_byte()));
+
+            (text, range)
+        })
+    }
+
+    fn from_node<F>(node: &Node, details: &F) -> Self
+    where
+        F: Fn(&Node) -> (Option<String>, Option<(u32, u32)>),
+    {
+        let mut children = Vec::new();
         let mut walker = node.walk();
-        let children = node.children(&mut walker).map(Self::from).collect();
+        if walker.goto_first_child() {
+            loop {
+                let mut child = Self::from_node(&walker.node(), details);
+                child.field = walker.field_name().map(Into::into);
+                children.push(child);
+
+                if !walker.goto_next_sibling() {
+                    break;
+                }
+            }
+        }
+
+        let (text, range) = details(node);
 
         Self {
             id: node.id(),
 
             kind: node.kind().into(),
+            field: None,
             is_named: node.is_named(),
             is_extra: node.is_extra(),
             is_error: node.is_error(),
             is_missing: node.is_missing(),
             start: node.start_position().into(),
             end: node.end_position().into(),
+            start_byte: range.map(|(start, _)| start),
+            end_byte: range.map(|(_, end)| end),
+            text,
 
             children,
+            elided: None,
+        }
+    }
+
+    /// Prune the subtree to the given depth, recording how many children were pruned at its edge
+    pub(crate) fn prune(&mut self, depth: usize) {
+        if depth == 0 {
+            if !self.children.is_empty() {
+                self.elided = Some(self.children.len());
+                self.children.clear();
+            }
+        } else {
+            for child in &mut self.children {
+                child.prune(depth - 1);
+            }
         }
     }
 }
 
+impl From<Node<'_>> for SyntaxNode {
+    fn from(node: Node) -> Self {
+        Self::from_node(&node, &|_| (None, None))
+    }
+}
+
 /// Extension trait for [`Node`] to allow for 1-based display in logs.
 ///
 /// (Can't be done as a [`Display`] impl on [`Node`] directly, since that would
@@ -212,10 +327,48 @@ impl Display for LocalQueryMatch<'_> {
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
 // A struct to store the result of a query coverage check
 pub struct CoverageData {
     pub cover_percentage: f32,
     pub missing_patterns: Vec<LabeledSpan>,
+    pub covered_patterns: Vec<CoveredPattern>,
+}
+
+/// A named node kind that the query does not handle, with the nodes of that kind in the inputs
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnhandledKind {
+    pub kind: String,
+    pub count: usize,
+    /// The first few nodes, as the index of their input and their position
+    pub examples: Vec<(usize, Position)>,
+}
+
+impl UnhandledKind {
+    /// The number of example nodes to give
+    const EXAMPLES: usize = 3;
+}
+
+/// The named node kinds and field names of a grammar that no pattern of a query refers to
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct GrammarCoverage {
+    pub kinds: Vec<String>,
+    pub fields: Vec<String>,
+}
+
+impl GrammarCoverage {
+    pub fn is_empty(&self) -> bool {
+        self.kinds.is_empty() && self.fields.is_empty()
+    }
+}
+
+/// A query pattern that matches some of the inputs checked for coverage
+#[derive(Clone, Debug, PartialEq)]
+pub struct CoveredPattern {
+    /// The pattern's span in the query
+    pub span: SourceSpan,
+    /// The indices of the inputs that the pattern matches
+    pub inputs: Vec<usize>,
 }
 
 impl CoverageData {
@@ -267,6 +420,80 @@ impl miette::Diagnostic for CoverageData {
     }
 }
 
+/// A node captured by a query match, for inspecting what a query matches
+#[derive(Debug, Serialize)]
+pub struct CaptureData {
+    pub name: String,
+    pub kind: String,
+    pub start: Position,
+    pub end: Position,
+    pub text: String,
+
+    #[serde(skip_serializing)]
+    node: String,
+}
+
+impl Display for CaptureData {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "@{} {} {:?}", self.name, self.node, self.text)
+    }
+}
+
+/// A match of a query pattern, with its captured nodes
+#[derive(Debug, Serialize)]
+pub struct QueryMatchData {
+    pub pattern_index: usize,
+    pub captures: Vec<CaptureData>,
+}
+
+/// Runs a query against an input content and returns every match, in the order that Tree-sitter
+/// finds them. Unlike [`apply_query`], captures are not interpreted as formatting directives.
+///
+/// # Errors
+///
+/// This function can return an error if the input content cannot be parsed by the grammar.
+pub fn query_matches(
+    input_content: &str,
+    query: &TopiaryQuery,
+    grammar: &topiary_tree_sitter_facade::Language,
+    tolerate_parsing_errors: bool,
+) -> FormatterResult<Vec<QueryMatchData>> {
+    let tree = parse(input_content, grammar, tolerate_parsing_errors)?;
+    let root = tree.root_node();
+    let source = input_content.as_bytes();
+    let capture_names = query.query.capture_names();
+
+    let mut cursor = QueryCursor::new();
+    let mut query_matches = query.query.matches(&root, source, &mut cursor);
+
+    let mut matches = Vec::new();
+    #[allow(clippy::while_let_on_iterator)] // This is not a normal iterator
+    while let Some(query_match) = query_matches.next() {
+        let captures = query_match
+            .captures()
+            .map(|capture| {
+                let node = capture.node();
+
+                Ok(CaptureData {
+                    name: capture.name(capture_names.as_slice()).into_owned(),
+                    kind: node.kind().into(),
+                    start: node.start_position().into(),
+                    end: node.end_position().into(),
+                    text: node.utf8_text(source)?.into_owned(),
+                    node: node.display_one_based(),
+                })
+            })
+            .collect::<FormatterResult<_>>()?;
+
+        matches.push(QueryMatchData {
+            pattern_index: query_match.pattern_index(),
+            captures,
+        });
+    }
+
+    Ok(matches)
+}
+
 /// Applies a query to an input content and returns a collection of atoms.
 ///
 /// # Errors
@@ -301,31 +528,39 @@ pub fn apply_query_tree(
     input_content: &str,
     query: &TopiaryQuery,
 ) -> FormatterResult<AtomCollection> {
-    let root = tree.root_node();
+    apply_query_node(&tree.root_node(), input_content, query, false)
+}
+
+/// Applies a query to the subtree rooted at the given node and returns a collection of atoms.
+/// Patterns are only matched within that subtree, so any context from its ancestors is ignored.
+/// When `trace` is set, the provenance of every atom is recorded in the collection.
+///
+/// # Errors
+///
+/// This function can return the same errors as [`apply_query_tree`].
+pub(crate) fn apply_query_node(
+    root: &Node,
+    input_content: &str,
+    query: &TopiaryQuery,
+    trace: bool,
+) -> FormatterResult<AtomCollection> {
     let source = input_content.as_bytes();
 
     // Match queries
     let mut cursor = QueryCursor::new();
-    let mut matches: Vec<LocalQueryMatch> = Vec::new();
+    let matches = collect_matches(root, source, query, &mut cursor);
     let capture_names = query.query.capture_names();
 
-    let mut query_matches = query.query.matches(&root, source, &mut cursor);
-    #[allow(clippy::while_let_on_iterator)] // This is not a normal iterator
-    while let Some(query_match) = query_matches.next() {
-        let local_captures: Vec<QueryCapture> = query_match.captures().collect();
-
-        matches.push(LocalQueryMatch {
-            pattern_index: query_match.pattern_index(),
-            captures: local_captures,
-        });
-    }
-
     // Find the ids of all tree-sitter nodes that were identified as a leaf
     // We want to avoid recursing into them in the collect_leaves function.
     let specified_leaf_nodes: HashSet<usize> = collect_leaf_ids(&matches, capture_names.clone());
 
     // The Flattening: collects all terminal nodes of the tree-sitter tree in a Vec
-    let mut atoms = AtomCollection::collect_leaves(&root, source, specified_leaf_nodes)?;
+    let mut atoms = if trace {
+        AtomCollection::collect_leaves_traced(root, source, specified_leaf_nodes)?
+    } else {
+        AtomCollection::collect_leaves(root, source, specified_leaf_nodes)?
+    };
 
     log::debug!("List of atoms before formatting: {atoms:?}");
 
@@ -335,7 +570,7 @@ pub fn apply_query_tree(
     // The web bindings for tree-sitter do not have support for pattern_count, so instead we will resize as needed
     // Only reallocate if we are actually going to use the vec
     #[cfg(not(target_arch = "wasm32"))]
-    if log::log_enabled!(log::Level::Info) {
+    if log::log_enabled!(log::Level::Info) || trace {
         pattern_positions.resize(query.query.pattern_count(), None);
     }
 
@@ -355,8 +590,10 @@ pub fn apply_query_tree(
         }
         check_predicates(&predicates)?;
 
-        // NOTE: Only performed if logging is enabled to avoid unnecessary computation of Position
-        if log::log_enabled!(log::Level::Info) {
+        // NOTE: Only performed if logging or tracing is enabled to avoid unnecessary computation
+        // of Position
+        let mut pos = None;
+        if log::log_enabled!(log::Level::Info) || trace {
             #[cfg(target_arch = "wasm32")]
             // Resize the pattern_positions vector if we need to store more positions
             if m.pattern_index >= pattern_positions.len() {
@@ -364,11 +601,12 @@ pub fn apply_query_tree(
             }
 
             // Fetch from pattern_positions, otherwise insert
-            let pos = pattern_positions[m.pattern_index].unwrap_or_else(|| {
+            let position = pattern_positions[m.pattern_index].unwrap_or_else(|| {
                 let pos = query.pattern_position(m.pattern_index);
                 pattern_positions[m.pattern_index] = Some(pos);
                 pos
             });
+            pos = Some(position);
 
             let query_name_info = if let Some(name) = &predicates.query_name {
                 format!(" of query \"{name}\"")
@@ -376,20 +614,35 @@ pub fn apply_query_tree(
                 "".into()
             };
 
-            log::debug!("Processing match{query_name_info}: {m} at location {pos}");
+            log::debug!("Processing match{query_name_info}: {m} at location {position}");
         }
 
         // If any capture is a do_nothing, then do nothing.
-        if m.captures
+        let do_nothing = m
+            .captures
             .iter()
-            .any(|c| c.name(capture_names.as_slice()) == "do_nothing")
-        {
-            continue;
-        }
+            .any(|c| c.name(capture_names.as_slice()) == "do_nothing");
 
-        for c in m.captures {
+        for c in &m.captures {
             let name = c.name(capture_names.as_slice());
-            atoms.resolve_capture(&name, &c.node(), &predicates)?;
+
+            if let Some(tracer) = atoms.tracer() {
+                tracer.capture(Origin {
+                    pattern_index: m.pattern_index,
+                    pattern_position: pos.unwrap_or(Position { row: 0, column: 0 }),
+                    query_name: predicates.query_name.clone(),
+                    capture: name.to_string(),
+                    node: (&c.node()).into(),
+                });
+
+                if do_nothing {
+                    tracer.skip("the match has a @do_nothing capture");
+                }
+            }
+
+            if !do_nothing {
+                atoms.resolve_capture(&name, &c.node(), &predicates)?;
+            }
         }
     }
 
@@ -399,6 +652,29 @@ pub fn apply_query_tree(
     Ok(atoms)
 }
 
+/// Runs the query on the subtree rooted at the given node, keeping hold of its matches
+fn collect_matches<'a>(
+    root: &Node<'a>,
+    source: &'a [u8],
+    query: &'a TopiaryQuery,
+    cursor: &'a mut QueryCursor,
+) -> Vec<LocalQueryMatch<'a>> {
+    let mut matches: Vec<LocalQueryMatch> = Vec::new();
+
+    let mut query_matches = query.query.matches(root, source, cursor);
+    #[allow(clippy::while_let_on_iterator)] // This is not a normal iterator
+    while let Some(query_match) = query_matches.next() {
+        let local_captures: Vec<QueryCapture> = query_match.captures().collect();
+
+        matches.push(LocalQueryMatch {
+            pattern_index: query_match.pattern_index(),
+            captures: local_captures,
+        });
+    }
+
+    matches
+}
+
 /// Represents the code span for a given tree-sitter node
 #[derive(Debug)]
 pub struct NodeSpan {
@@ -632,64 +908,90 @@ fn check_predicates(predicates: &QueryPredicates) -> FormatterResult<()> {
     }
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-/// Check if the input tests all patterns in the query, by successively disabling
-/// all patterns. If disabling a pattern does not decrease the number of matches,
-/// then that pattern originally matched nothing in the input.
+/// Check if the input tests all patterns in the query, by matching each pattern on its own. This
+/// is [`check_query_coverage_inputs`], for a single input.
 pub fn check_query_coverage(
     input_content: &str,
     original_query: &TopiaryQuery,
     grammar: &topiary_tree_sitter_facade::Language,
+) -> FormatterResult<CoverageData> {
+    let tree = parse(input_content, grammar, false)?;
+    check_query_coverage_inputs(&[(&tree, input_content)], original_query, grammar)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+/// Check if the inputs, given with their parse trees, test all patterns in the query, by matching
+/// each pattern on its own. A pattern is covered if it matches any of the inputs.
+pub fn check_query_coverage_inputs(
+    inputs: &[(&Tree, &str)],
+    original_query: &TopiaryQuery,
+    grammar: &topiary_tree_sitter_facade::Language,
 ) -> FormatterResult<CoverageData> {
     use miette::LabeledSpan;
     use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
-    let tree = parse(input_content, grammar, false)?;
-    let root = tree.root_node();
-    let source = input_content.as_bytes();
-    let mut missing_patterns = Vec::new();
-
-    // Match queries
-    let mut cursor = QueryCursor::new();
-    let ref_match_count = original_query
-        .query
-        .matches(&root, source, &mut cursor)
-        .count();
+    let inputs: Vec<(Node, &[u8])> = inputs
+        .iter()
+        .map(|(tree, content)| (tree.root_node(), content.as_bytes()))
+        .collect();
 
     let pattern_count = original_query.query.pattern_count();
     let query_content = &original_query.query_content;
     let query = &original_query.query;
 
+    // The indices of the inputs that the given query matches
+    let matching_inputs = |query: &Query| -> Vec<usize> {
+        inputs
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, (root, source))| {
+                let mut cursor = QueryCursor::new();
+                let has_matches = query.matches(root, *source, &mut cursor).next().is_some();
+                has_matches.then_some(idx)
+            })
+            .collect()
+    };
+
     // If there are no queries at all (e.g., when debugging) return early
     // rather than dividing by zero
     if pattern_count == 0 {
-        let cover_percentage = 0.0;
         return Ok(CoverageData {
-            cover_percentage,
-            missing_patterns,
+            cover_percentage: 0.0,
+            missing_patterns: Vec::new(),
+            covered_patterns: Vec::new(),
         });
     }
 
     // This particular test avoids a SIGSEGV error that occurs when trying
     // to count the matches of an empty query (see #481)
     if pattern_count == 1 {
-        let mut cover_percentage = 1.0;
-        if ref_match_count == 0 {
-            missing_patterns.push(LabeledSpan::new_with_span(
-                Some("empty query".into()),
-                SourceSpan::from(0..query_content.len()),
-            ));
-            cover_percentage = 0.0
-        }
-        return Ok(CoverageData {
-            cover_percentage,
-            missing_patterns,
+        let span = SourceSpan::from(0..query_content.len());
+        let covering_inputs = matching_inputs(query);
+
+        return Ok(if covering_inputs.is_empty() {
+            CoverageData {
+                cover_percentage: 0.0,
+                missing_patterns: vec![LabeledSpan::new_with_span(
+                    Some("empty query".into()),
+                    span,
+                )],
+                covered_patterns: Vec::new(),
+            }
+        } else {
+            CoverageData {
+                cover_percentage: 1.0,
+                missing_patterns: Vec::new(),
+                covered_patterns: vec![CoveredPattern {
+                    span,
+                    inputs: covering_inputs,
+                }],
+            }
         });
     }
 
-    let missing_patterns: Vec<LabeledSpan> = (0..pattern_count)
+    let patterns: Vec<(SourceSpan, Vec<usize>)> = (0..pattern_count)
         .into_par_iter()
-        .filter_map(|i| {
+        .map(|i| {
             // The TreeSitter API doesn't support splitting a query per pattern subqueries.
             // We do so manually here by using the `query_content` and `query` fields for the same
             // `TopiaryQuery` object.
@@ -703,43 +1005,200 @@ pub fn check_query_coverage(
             let pattern_query = Query::new(grammar, pattern_content)
                 .expect("unable to create subquery of valid query, this is a bug");
 
-            let mut cursor = QueryCursor::new();
-            let pattern_has_matches = pattern_query
-                .matches(&root, source, &mut cursor)
-                .next()
-                .is_some();
-            if !pattern_has_matches {
-                let trimmed_end_idx = pattern_content
-                    .rmatch_indices('\n')
-                    .map(|(i, _)| i)
-                    .find_map(|i| {
-                        let line = pattern_content[i..].trim_start();
-                        let is_pattern_line = !line.is_empty() && !line.starts_with(';');
-                        is_pattern_line.then_some(start_idx + i + 2)
-                    })
-                    .unwrap_or(pattern_content.len());
-                return Some(LabeledSpan::new_with_span(
-                    Some("unmatched".into()),
-                    SourceSpan::from(start_idx..trimmed_end_idx),
-                ));
-            }
-            None
+            let trimmed_end_idx = pattern_content
+                .rmatch_indices('\n')
+                .map(|(i, _)| i)
+                .find_map(|i| {
+                    let line = pattern_content[i..].trim_start();
+                    let is_pattern_line = !line.is_empty() && !line.starts_with(';');
+                    is_pattern_line.then_some(start_idx + i + 2)
+                })
+                .unwrap_or(pattern_content.len());
+
+            (
+                SourceSpan::from(start_idx..trimmed_end_idx),
+                matching_inputs(&pattern_query),
+            )
         })
         .collect();
 
+    let (covered, missing): (Vec<_>, Vec<_>) = patterns
+        .into_iter()
+        .partition(|(_, covering_inputs)| !covering_inputs.is_empty());
+
+    let missing_patterns: Vec<LabeledSpan> = missing
+        .into_iter()
+        .map(|(span, _)| LabeledSpan::new_with_span(Some("unmatched".into()), span))
+        .collect();
+    let covered_patterns = covered
+        .into_iter()
+        .map(|(span, inputs)| CoveredPattern { span, inputs })
+        .collect();
+
     let ok_patterns = pattern_count - missing_patterns.len();
     let cover_percentage = ok_patterns as f32 / pattern_count as f32;
     Ok(CoverageData {
         cover_percentage,
         missing_patterns,
+        covered_patterns,
     })
 }
 
 #[cfg(target_arch = "wasm32")]
-pub fn check_query_coverage(
-    _input_content: &str,
+pub fn check_query_coverage_inputs(
+    _inputs: &[(&Tree, &str)],
     _original_query: &TopiaryQuery,
     _grammar: &topiary_tree_sitter_facade::Language,
 ) -> FormatterResult<CoverageData> {
     unimplemented!();
 }
+
+/// Find the named node kinds in the inputs, given with their parse trees, that the query does not
+/// handle: no node of such a kind is captured, contains a captured node, or is within a leaf. The
+/// leaves of such nodes are output as they are, without any whitespace between them.
+pub fn check_node_coverage(inputs: &[(&Tree, &str)], query: &TopiaryQuery) -> Vec<UnhandledKind> {
+    let capture_names = query.query.capture_names();
+
+    // For each node kind, whether any of its nodes is handled, and those that aren't
+    let mut kinds: HashMap<String, (bool, UnhandledKind)> = HashMap::new();
+
+    for (input, (tree, content)) in inputs.iter().enumerate() {
+        let root = tree.root_node();
+        let mut cursor = QueryCursor::new();
+        let matches: Vec<LocalQueryMatch> =
+            collect_matches(&root, content.as_bytes(), query, &mut cursor)
+                .into_iter()
+                .filter(|m| {
+                    !m.captures
+                        .iter()
+                        .any(|c| c.name(capture_names.as_slice()) == "do_nothing")
+                })
+                .collect();
+
+        let leaf_ids = collect_leaf_ids(&matches, capture_names.clone());
+
+        // Captured nodes, and their ancestors, are handled
+        let mut handled: HashSet<usize> = HashSet::new();
+        for capture in matches.iter().flat_map(|m| &m.captures) {
+            let mut node = Some(capture.node());
+            while let Some(current) = node {
+                if !handled.insert(current.id()) {
+                    break;
+                }
+                node = current.parent();
+            }
+        }
+
+        // The range of the leaf that the traversal is within, if any
+        let mut leaf: Option<(u32, u32)> = None;
+
+        for node in dfs_flatten(&root) {
+            let within_leaf = leaf
+                .is_some_and(|(start, end)| start <= node.start_byte() && node.end_byte() <= end);
+            if !within_leaf && leaf_ids.contains(&node.id()) {
+                leaf = Some((node.start_byte(), node.end_byte()));
+            }
+
+            if !node.is_named() {
+                continue;
+            }
+
+            let (kind_handled, unhandled) = kinds.entry(node.kind().into()).or_insert_with(|| {
+                (
+                    false,
+                    UnhandledKind {
+                        kind: node.kind().into(),
+                        count: 0,
+                        examples: Vec::new(),
+                    },
+                )
+            });
+
+            if within_leaf || leaf_ids.contains(&node.id()) || handled.contains(&node.id()) {
+                *kind_handled = true;
+            } else {
+                unhandled.count += 1;
+                if unhandled.examples.len() < UnhandledKind::EXAMPLES {
+                    unhandled
+                        .examples
+                        .push((input, node.start_position().into()));
+                }
+            }
+        }
+    }
+
+    let mut unhandled: Vec<UnhandledKind> = kinds
+        .into_values()
+        .filter_map(|(handled, unhandled)| (!handled).then_some(unhandled))
+        .collect();
+    unhandled.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.kind.cmp(&b.kind)));
+
+    unhandled
+}
+
+/// Find the named node kinds and field names of the grammar that no pattern of the query refers
+/// to, without any input. The query's patterns are parsed with `query_grammar`, which must be the
+/// grammar of Tree-sitter queries themselves.
+pub fn check_grammar_coverage(
+    query: &TopiaryQuery,
+    grammar: &topiary_tree_sitter_facade::Language,
+    query_grammar: &topiary_tree_sitter_facade::Language,
+) -> FormatterResult<GrammarCoverage> {
+    // Anonymous nodes are referred to by their text, so only named nodes and fields are collected
+    let references = TopiaryQuery::new(
+        query_grammar,
+        "(named_node name: (identifier) @kind)
+         (field_definition name: (identifier) @field)
+         (negated_field (identifier) @field)",
+    )?;
+    let capture_names = references.query.capture_names();
+
+    let tree = parse(&query.query_content, query_grammar, false)?;
+    let mut cursor = QueryCursor::new();
+    let mut kinds: HashSet<String> = HashSet::new();
+    let mut fields: HashSet<String> = HashSet::new();
+
+    for capture in collect_matches(
+        &tree.root_node(),
+        query.query_content.as_bytes(),
+        &references,
+        &mut cursor,
+    )
+    .iter()
+    .flat_map(|m| &m.captures)
+    {
+        let text = capture.node().utf8_text(query.query_content.as_bytes())?;
+        match capture.name(capture_names.as_slice()).as_ref() {
+            "kind" => kinds.insert(text.into()),
+            _ => fields.insert(text.into()),
+        };
+    }
+
+    // Distinct node kinds can share a name, e.g. when they are aliased
+    let mut coverage = GrammarCoverage::default();
+    for id in 0..grammar.node_kind_count() {
+        if !grammar.node_kind_is_named(id) || !grammar.node_kind_is_visible(id) {
+            continue;
+        }
+        if let Some(kind) = grammar.node_kind_for_id(id)
+            && !kinds.contains(kind.as_ref())
+            && !coverage.kinds.iter().any(|k| *k == kind)
+        {
+            coverage.kinds.push(kind.into());
+        }
+    }
+
+    // Field IDs start at 1
+    for id in 1..=grammar.field_count() {
+        if let Some(field) = grammar.field_name_for_id(id)
+            && !fields.contains(field.as_ref())
+        {
+            coverage.fields.push(field.into());
+        }
+    }
+
+    coverage.kinds.sort();
+    coverage.fields.sort();
+
+    Ok(coverage)
+}